@@ -4,6 +4,7 @@ use clap::{Parser, Subcommand};
 use dotenv::dotenv;
 use serde_json::Value;
 
+use sdk::endpoint::{EndpointHeader, UpdateEndpoint};
 use sdk::WebhooksSDK;
 
 /// Cli app to manage webhook-rs server
@@ -50,6 +51,30 @@ enum EndpointSubcommand {
         url: String,
         #[arg(value_parser, num_args = 1.., value_delimiter = ',', required = true)]
         topics: Vec<String>,
+        /// Custom header sent with every delivery, e.g. "X-Tenant: acme"
+        #[arg(long = "header", value_parser(parse_header))]
+        headers: Vec<(String, String)>,
+        /// Custom header which value is encrypted at rest, e.g. "Authorization: Bearer token"
+        #[arg(long = "secret-header", value_parser(parse_header))]
+        secret_headers: Vec<(String, String)>,
+    },
+    /// Updates an endpoint
+    Update {
+        app_id: String,
+        endpoint_id: String,
+        #[arg(long)]
+        url: Option<String>,
+        #[arg(long, value_delimiter = ',')]
+        topics: Option<Vec<String>>,
+        /// Replaces custom headers, e.g. "X-Tenant: acme"
+        #[arg(long = "header", value_parser(parse_header))]
+        headers: Vec<(String, String)>,
+        /// Replaces custom headers with secret one, e.g. "Authorization: Bearer token"
+        #[arg(long = "secret-header", value_parser(parse_header))]
+        secret_headers: Vec<(String, String)>,
+        /// Removes all custom headers
+        #[arg(long, conflicts_with_all = ["headers", "secret_headers"])]
+        clear_headers: bool,
     },
 }
 
@@ -69,6 +94,28 @@ fn parse_json_value(val: &str) -> Result<Value, String> {
     Ok(payload)
 }
 
+fn parse_header(val: &str) -> Result<(String, String), String> {
+    let (name, value) = val
+        .split_once(':')
+        .ok_or_else(|| format!("'{}' should be in 'Name: value' format", val))?;
+
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+fn endpoint_headers(
+    headers: Vec<(String, String)>,
+    secret_headers: Vec<(String, String)>,
+) -> Vec<EndpointHeader> {
+    let headers = headers
+        .iter()
+        .map(|(name, value)| EndpointHeader::new(name, value));
+    let secret_headers = secret_headers
+        .iter()
+        .map(|(name, value)| EndpointHeader::secret(name, value));
+
+    headers.chain(secret_headers).collect()
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv().ok();
@@ -90,12 +137,45 @@ async fn main() -> anyhow::Result<()> {
                 app_id,
                 url,
                 topics,
+                headers,
+                secret_headers,
             } => {
                 let topics_str = topics.iter().map(|s| s.as_str()).collect();
-                let endpoint = sdk.endpoints().create(&app_id, &url, topics_str).await?;
+                let headers = endpoint_headers(headers, secret_headers);
+                let endpoint = sdk
+                    .endpoints()
+                    .create_with_headers(&app_id, &url, topics_str, headers)
+                    .await?;
 
                 println!("Endpoint {} has been created", endpoint.id);
             }
+            EndpointSubcommand::Update {
+                app_id,
+                endpoint_id,
+                url,
+                topics,
+                headers,
+                secret_headers,
+                clear_headers,
+            } => {
+                let headers = endpoint_headers(headers, secret_headers);
+                let headers = if clear_headers || !headers.is_empty() {
+                    Some(headers)
+                } else {
+                    None
+                };
+                let update = UpdateEndpoint {
+                    url,
+                    topics,
+                    headers,
+                };
+                let endpoint = sdk
+                    .endpoints()
+                    .update(&app_id, &endpoint_id, update)
+                    .await?;
+
+                println!("Endpoint {} has been updated", endpoint.id);
+            }
         },
         Command::Event { subcommand } => match subcommand {
             EventSubcommand::Create {
@@ -115,7 +195,7 @@ async fn main() -> anyhow::Result<()> {
 
 #[cfg(test)]
 mod test {
-    use clap::error::ErrorKind::MissingRequiredArgument;
+    use clap::error::ErrorKind::{MissingRequiredArgument, ValueValidation};
     use clap::{CommandFactory, Parser};
     use serde_json::json;
 
@@ -158,6 +238,8 @@ mod test {
                     app_id: "app_2hRzcGs8D5aLaHBWHyqIcibuFA1".to_string(),
                     url: "http://localhost:8080".to_string(),
                     topics: vec!["contact.created".to_string()],
+                    headers: vec![],
+                    secret_headers: vec![],
                 },
             },
         };
@@ -187,6 +269,90 @@ mod test {
                         "contact.updated".to_string(),
                         "contact.deleted".to_string(),
                     ],
+                    headers: vec![],
+                    secret_headers: vec![],
+                },
+            },
+        };
+
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn endpoint_create_with_headers() {
+        let result = Cli::try_parse_from([
+            "webhooks-cli",
+            "endpoint",
+            "create",
+            "app_2hRzcGs8D5aLaHBWHyqIcibuFA1",
+            "http://localhost:8080",
+            "contact.created",
+            "--header",
+            "X-Tenant: acme",
+            "--secret-header",
+            "Authorization: Bearer token",
+        ]);
+
+        let expected = Cli {
+            command: Endpoint {
+                subcommand: EndpointSubcommand::Create {
+                    app_id: "app_2hRzcGs8D5aLaHBWHyqIcibuFA1".to_string(),
+                    url: "http://localhost:8080".to_string(),
+                    topics: vec!["contact.created".to_string()],
+                    headers: vec![("X-Tenant".to_string(), "acme".to_string())],
+                    secret_headers: vec![("Authorization".to_string(), "Bearer token".to_string())],
+                },
+            },
+        };
+
+        assert!(result.is_ok());
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn endpoint_create_header_should_have_name_and_value() {
+        let result = Cli::try_parse_from([
+            "webhooks-cli",
+            "endpoint",
+            "create",
+            "app_2hRzcGs8D5aLaHBWHyqIcibuFA1",
+            "http://localhost:8080",
+            "contact.created",
+            "--header",
+            "X-Tenant",
+        ]);
+
+        assert!(result.is_err());
+        assert_eq!(ValueValidation, result.err().unwrap().kind());
+    }
+
+    #[test]
+    fn endpoint_update() {
+        let result = Cli::try_parse_from([
+            "webhooks-cli",
+            "endpoint",
+            "update",
+            "app_2hRzcGs8D5aLaHBWHyqIcibuFA1",
+            "ep_2hRzcGs8D5aLaHBWHyqIcibuFA1",
+            "--topics",
+            "contact.created,contact.updated",
+            "--clear-headers",
+        ]);
+
+        let expected = Cli {
+            command: Endpoint {
+                subcommand: EndpointSubcommand::Update {
+                    app_id: "app_2hRzcGs8D5aLaHBWHyqIcibuFA1".to_string(),
+                    endpoint_id: "ep_2hRzcGs8D5aLaHBWHyqIcibuFA1".to_string(),
+                    url: None,
+                    topics: Some(vec![
+                        "contact.created".to_string(),
+                        "contact.updated".to_string(),
+                    ]),
+                    headers: vec![],
+                    secret_headers: vec![],
+                    clear_headers: true,
                 },
             },
         };
@@ -2,6 +2,11 @@
 
 cp -n .env.dist .env
 
+if ! grep -q "^ENCRYPTION_KEY=." .env; then
+  echo "Generate ENCRYPTION_KEY";
+  sed -i.bak "s|^ENCRYPTION_KEY=.*|ENCRYPTION_KEY=$(openssl rand -base64 32)|" .env && rm .env.bak
+fi
+
 source .env
 
 echo "Run migrations";
@@ -3,6 +3,7 @@ use std::str::FromStr;
 
 use reqwest::header;
 use reqwest::header::USER_AGENT;
+use reqwest::Method;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use url::Url;
@@ -25,12 +26,28 @@ impl Client {
     }
 
     pub async fn post<I, O>(&self, endpoint: EndpointUrl, body: I) -> Result<O, Error>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        self.send(Method::POST, endpoint, body).await
+    }
+
+    pub async fn patch<I, O>(&self, endpoint: EndpointUrl, body: I) -> Result<O, Error>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        self.send(Method::PATCH, endpoint, body).await
+    }
+
+    async fn send<I, O>(&self, method: Method, endpoint: EndpointUrl, body: I) -> Result<O, Error>
     where
         I: Serialize,
         O: DeserializeOwned,
     {
         let url = self.url(endpoint);
-        let response = self.client.post(url).json(&body).send().await?;
+        let response = self.client.request(method, url).json(&body).send().await?;
 
         if 400 == response.status().as_u16() {
             let result = response.json::<crate::error::BadRequest>().await?;
@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::client::{Client, EndpointUrl};
@@ -10,6 +10,45 @@ pub struct Endpoint {
     pub app_id: String,
     pub url: String,
     pub topics: Vec<String>,
+    #[serde(default)]
+    pub headers: Vec<EndpointHeader>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EndpointHeader {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub secret: bool,
+}
+
+impl EndpointHeader {
+    #[must_use]
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            secret: false,
+        }
+    }
+
+    #[must_use]
+    pub fn secret(name: &str, value: &str) -> Self {
+        Self {
+            secret: true,
+            ..Self::new(name, value)
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct UpdateEndpoint {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topics: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<Vec<EndpointHeader>>,
 }
 
 pub struct EndpointApi {
@@ -27,10 +66,22 @@ impl EndpointApi {
         app_id: &str,
         url: &str,
         topics: Vec<&str>,
+    ) -> Result<Endpoint, Error> {
+        self.create_with_headers(app_id, url, topics, Vec::new())
+            .await
+    }
+
+    pub async fn create_with_headers(
+        &self,
+        app_id: &str,
+        url: &str,
+        topics: Vec<&str>,
+        headers: Vec<EndpointHeader>,
     ) -> Result<Endpoint, Error> {
         let body = json!({
             "url": url,
-            "topics": topics
+            "topics": topics,
+            "headers": headers
         });
 
         self.client
@@ -40,4 +91,124 @@ impl EndpointApi {
             )
             .await
     }
+
+    pub async fn update(
+        &self,
+        app_id: &str,
+        endpoint_id: &str,
+        update: UpdateEndpoint,
+    ) -> Result<Endpoint, Error> {
+        self.client
+            .patch(
+                EndpointUrl::try_from(format!("application/{}/endpoint/{}", app_id, endpoint_id))
+                    .unwrap(),
+                update,
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher::Json;
+    use serde_json::json;
+
+    use crate::endpoint::{Endpoint, EndpointHeader, UpdateEndpoint};
+    use crate::WebhooksSDK;
+
+    #[tokio::test]
+    async fn create_endpoint_with_headers() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock("POST", "/application/app_2dSZgxc6qw0vR7hwZVXDJFleRXj/endpoint")
+            .match_body(Json(json!({
+                "url": "http://localhost:8080",
+                "topics": ["contact.created"],
+                "headers": [
+                    {"name": "X-Tenant", "value": "acme", "secret": false},
+                    {"name": "Authorization", "value": "Bearer token", "secret": true}
+                ]
+            })))
+            .with_body(
+                r#"{"id":"ep_2dSZgxc6qw0vR7hwZVXDJFleRXj","app_id":"app_2dSZgxc6qw0vR7hwZVXDJFleRXj","url":"http://localhost:8080","topics":["contact.created"],"headers":[{"name":"X-Tenant","value":"acme","secret":false},{"name":"Authorization","value":"********","secret":true}]}"#,
+            )
+            .with_header("content-type", "application/json")
+            .with_status(201)
+            .create_async()
+            .await;
+
+        let endpoint = WebhooksSDK::new(url.as_str())
+            .endpoints()
+            .create_with_headers(
+                "app_2dSZgxc6qw0vR7hwZVXDJFleRXj",
+                "http://localhost:8080",
+                vec!["contact.created"],
+                vec![
+                    EndpointHeader::new("X-Tenant", "acme"),
+                    EndpointHeader::secret("Authorization", "Bearer token"),
+                ],
+            )
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+
+        assert_eq!(
+            Endpoint {
+                id: "ep_2dSZgxc6qw0vR7hwZVXDJFleRXj".to_string(),
+                app_id: "app_2dSZgxc6qw0vR7hwZVXDJFleRXj".to_string(),
+                url: "http://localhost:8080".to_string(),
+                topics: vec!["contact.created".to_string()],
+                headers: vec![
+                    EndpointHeader::new("X-Tenant", "acme"),
+                    EndpointHeader::secret("Authorization", "********"),
+                ],
+            },
+            endpoint
+        );
+    }
+
+    #[tokio::test]
+    async fn update_endpoint_sends_only_changed_fields() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mock = server
+            .mock(
+                "PATCH",
+                "/application/app_2dSZgxc6qw0vR7hwZVXDJFleRXj/endpoint/ep_2dSZgxc6qw0vR7hwZVXDJFleRXj",
+            )
+            .match_body(Json(json!({
+                "headers": [{"name": "X-Api-Key", "value": "key", "secret": true}]
+            })))
+            .with_body(
+                r#"{"id":"ep_2dSZgxc6qw0vR7hwZVXDJFleRXj","app_id":"app_2dSZgxc6qw0vR7hwZVXDJFleRXj","url":"http://localhost:8080","topics":["contact.created"],"headers":[{"name":"X-Api-Key","value":"********","secret":true}]}"#,
+            )
+            .with_header("content-type", "application/json")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let endpoint = WebhooksSDK::new(url.as_str())
+            .endpoints()
+            .update(
+                "app_2dSZgxc6qw0vR7hwZVXDJFleRXj",
+                "ep_2dSZgxc6qw0vR7hwZVXDJFleRXj",
+                UpdateEndpoint {
+                    headers: Some(vec![EndpointHeader::secret("X-Api-Key", "key")]),
+                    ..UpdateEndpoint::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+
+        assert_eq!(
+            vec![EndpointHeader::secret("X-Api-Key", "********")],
+            endpoint.headers
+        );
+    }
 }
@@ -8,7 +8,7 @@ use crate::event::EventsApi;
 
 mod application;
 mod client;
-mod endpoint;
+pub mod endpoint;
 pub mod error;
 mod event;
 
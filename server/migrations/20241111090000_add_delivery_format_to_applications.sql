@@ -0,0 +1,2 @@
+ALTER TABLE applications
+    ADD COLUMN delivery_format TEXT NOT NULL DEFAULT 'raw';
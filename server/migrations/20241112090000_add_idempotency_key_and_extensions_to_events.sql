@@ -0,0 +1,5 @@
+ALTER TABLE events
+    ADD COLUMN idempotency_key TEXT NULL,
+    ADD COLUMN extensions      JSON NOT NULL DEFAULT '{}';
+
+CREATE UNIQUE INDEX events_app_id_idempotency_key_idx ON events (app_id, idempotency_key);
@@ -0,0 +1,2 @@
+ALTER TABLE endpoints
+    ADD COLUMN headers JSON NOT NULL DEFAULT '[]';
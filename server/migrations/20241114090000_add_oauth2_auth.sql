@@ -0,0 +1,5 @@
+ALTER TABLE endpoints
+    ADD COLUMN auth JSON NOT NULL DEFAULT '{"type": "none"}';
+
+ALTER TABLE attempts
+    ADD COLUMN status_kind TEXT NULL;
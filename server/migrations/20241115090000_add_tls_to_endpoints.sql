@@ -0,0 +1,2 @@
+ALTER TABLE endpoints
+    ADD COLUMN tls JSON NOT NULL DEFAULT '{}';
@@ -0,0 +1,3 @@
+ALTER TABLE endpoints
+    ADD COLUMN connect_timeout_ms INT NULL,
+    ADD COLUMN request_timeout_ms INT NULL;
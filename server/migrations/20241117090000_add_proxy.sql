@@ -0,0 +1,5 @@
+ALTER TABLE applications
+    ADD COLUMN proxy JSON NULL;
+
+ALTER TABLE attempt_logs
+    ADD COLUMN proxy TEXT NULL;
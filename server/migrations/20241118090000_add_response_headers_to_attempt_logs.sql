@@ -0,0 +1,2 @@
+ALTER TABLE attempt_logs
+    ADD COLUMN response_headers JSON NULL;
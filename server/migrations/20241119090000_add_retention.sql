@@ -0,0 +1,9 @@
+ALTER TABLE applications
+    ADD COLUMN retention_days         INT NULL,
+    ADD COLUMN payload_retention_days INT NULL;
+
+ALTER TABLE events
+    ALTER COLUMN payload DROP NOT NULL;
+
+CREATE INDEX events_created_at_idx ON events (created_at);
+CREATE INDEX messages_event_id_idx ON messages (event_id);
@@ -0,0 +1,12 @@
+CREATE TABLE jobs
+(
+    id            BIGSERIAL NOT NULL,
+    primary key (id),
+    queue         text      NOT NULL,
+    payload       JSONB     NOT NULL,
+    trace_context JSONB     NULL,
+    run_at        TIMESTAMP NOT NULL,
+    locked_until  TIMESTAMP NULL
+);
+
+CREATE INDEX jobs_queue_run_at_idx ON jobs (queue, run_at);
@@ -0,0 +1,3 @@
+ALTER TABLE events
+    ADD COLUMN deliver_at   TIMESTAMP NULL,
+    ADD COLUMN cancelled_at TIMESTAMP NULL;
@@ -0,0 +1,5 @@
+ALTER TABLE applications
+    ADD COLUMN topic_ttls JSONB NULL;
+
+ALTER TABLE events
+    ADD COLUMN expires_at TIMESTAMP NULL;
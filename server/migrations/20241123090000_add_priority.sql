@@ -0,0 +1,11 @@
+ALTER TABLE applications
+    ADD COLUMN topic_priorities JSONB NULL;
+
+ALTER TABLE events
+    ADD COLUMN priority TEXT NOT NULL DEFAULT 'normal';
+
+ALTER TABLE jobs
+    ADD COLUMN priority SMALLINT NOT NULL DEFAULT 1;
+
+DROP INDEX jobs_queue_run_at_idx;
+CREATE INDEX jobs_queue_priority_run_at_idx ON jobs (queue, priority DESC, run_at);
@@ -0,0 +1,19 @@
+ALTER TABLE attempt_logs
+    ADD COLUMN created_at TIMESTAMP NULL;
+
+UPDATE attempt_logs l
+SET created_at = GREATEST(e.created_at, COALESCE(e.deliver_at, e.created_at)) + l.processing_time * INTERVAL '1 millisecond'
+FROM messages m
+         JOIN events e ON e.id = m.event_id
+WHERE m.id = l.message_id;
+
+UPDATE attempt_logs
+SET created_at = NOW() AT TIME ZONE 'UTC'
+WHERE created_at IS NULL;
+
+ALTER TABLE attempt_logs
+    ALTER COLUMN created_at SET DEFAULT (NOW() AT TIME ZONE 'UTC'),
+    ALTER COLUMN created_at SET NOT NULL;
+
+CREATE INDEX attempt_logs_created_at_idx ON attempt_logs (created_at);
+CREATE INDEX messages_endpoint_id_idx ON messages (endpoint_id);
@@ -0,0 +1,6 @@
+ALTER TABLE events
+    ADD COLUMN source TEXT NULL;
+
+DROP INDEX events_app_id_idempotency_key_idx;
+
+CREATE UNIQUE INDEX events_app_id_source_idempotency_key_idx ON events (app_id, source, idempotency_key);
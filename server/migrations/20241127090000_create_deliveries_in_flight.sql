@@ -0,0 +1,9 @@
+CREATE TABLE deliveries_in_flight
+(
+    id         BIGSERIAL NOT NULL,
+    primary key (id),
+    app_id     char(27)  NOT NULL,
+    expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX deliveries_in_flight_app_id_idx ON deliveries_in_flight (app_id);
@@ -0,0 +1,4 @@
+ALTER TABLE events
+    ADD COLUMN published_at TIMESTAMP NULL;
+
+UPDATE events SET published_at = created_at;
@@ -1,29 +1,78 @@
 use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::Duration;
 
+use async_trait::async_trait;
+use futures_lite::StreamExt;
+use lapin::acker::Acker;
 use lapin::options::{
-    BasicPublishOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+    BasicAckOptions, BasicCancelOptions, BasicConsumeOptions, BasicGetOptions, BasicNackOptions,
+    BasicPublishOptions, BasicQosOptions, ConfirmSelectOptions, ExchangeBindOptions,
+    ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions, QueueDeleteOptions,
 };
 use lapin::publisher_confirm::Confirmation;
 use lapin::types::{AMQPType, AMQPValue, FieldTable, ShortString};
-use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, ExchangeKind};
-use log::info;
-use serde::de::DeserializeOwned;
-use serde::Serialize;
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, Consumer, ExchangeKind};
+use log::{error, info, warn};
+use opentelemetry::trace::SpanKind;
+use opentelemetry::Context;
 use serde_json::Value;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
 
 use crate::cmd::AsyncMessage;
 use crate::config::AMQPConfig;
+use crate::configuration::domain::Priority;
+use crate::error::Error;
+use crate::queue::{Acknowledger, LaneScheduler, Queue, QueueConsumer, QueueDelivery};
+use crate::telemetry::{end_span, extract_amqp_headers, inject_amqp_headers, start_span};
 
-pub async fn establish_connection_with_rabbit(amqp_config: AMQPConfig) -> Channel {
+const PERSISTENT_DELIVERY_MODE: u8 = 2;
+const DURABLE_EXCHANGE: ExchangeDeclareOptions = ExchangeDeclareOptions {
+    passive: false,
+    durable: true,
+    auto_delete: false,
+    internal: false,
+    nowait: false,
+};
+const DURABLE_QUEUE: QueueDeclareOptions = QueueDeclareOptions {
+    passive: false,
+    durable: true,
+    exclusive: false,
+    auto_delete: false,
+    nowait: false,
+};
+const PASSIVE_EXCHANGE: ExchangeDeclareOptions = ExchangeDeclareOptions {
+    passive: true,
+    durable: false,
+    auto_delete: false,
+    internal: false,
+    nowait: false,
+};
+const PASSIVE_QUEUE: QueueDeclareOptions = QueueDeclareOptions {
+    passive: true,
+    durable: false,
+    exclusive: false,
+    auto_delete: false,
+    nowait: false,
+};
+const MIN_RESUBSCRIBE_DELAY: Duration = Duration::from_millis(500);
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+pub async fn connect_with_rabbit(amqp_config: AMQPConfig) -> (Connection, Channel) {
+    try_connect(&amqp_config).await.unwrap()
+}
+
+async fn try_connect(amqp_config: &AMQPConfig) -> Result<(Connection, Channel), Error> {
     let addr = amqp_config.connection_string();
-    let conn = Connection::connect(&addr, ConnectionProperties::default())
-        .await
-        .unwrap();
+    let conn = Connection::connect(&addr, ConnectionProperties::default()).await?;
 
     info!("connected established with rabbitmq");
 
-    let channel = conn.create_channel().await.unwrap();
+    let channel = conn.create_channel().await?;
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
 
     let args = FieldTable::from(BTreeMap::from(
         [(
@@ -37,55 +86,344 @@ pub async fn establish_connection_with_rabbit(amqp_config: AMQPConfig) -> Channe
         .exchange_declare(
             &amqp_config.sent_message_exchange_name(),
             ExchangeKind::Custom(String::from("x-delayed-message")),
-            ExchangeDeclareOptions::default(),
+            DURABLE_EXCHANGE,
             args,
         )
+        .await?;
+
+    declare_dead_letter(&channel, amqp_config).await?;
+
+    for priority in Priority::ALL {
+        declare_priority_queue(&channel, amqp_config, priority).await?;
+    }
+
+    if let Err(err) = migrate_legacy(&conn, amqp_config).await {
+        error!("Legacy queue cannot be migrated: {:?}", err);
+    }
+
+    Ok((conn, channel))
+}
+
+// Versions before the durable topology declared a non-durable exchange and queue, which cannot be redeclared as
+// durable. Delayed messages still held by the legacy exchange are routed to the durable one once they are due and
+// the legacy queue is drained into it.
+async fn migrate_legacy(conn: &Connection, amqp_config: &AMQPConfig) -> Result<(), Error> {
+    let legacy_exchange = amqp_config.legacy_exchange_name();
+    let legacy_queue = amqp_config.legacy_queue_name();
+    let exchange = amqp_config.sent_message_exchange_name();
+
+    // Passive declare of a missing exchange or queue closes the channel, so the migration has its own
+    let channel = conn.create_channel().await?;
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+
+    let declared = channel
+        .exchange_declare(
+            &legacy_exchange,
+            ExchangeKind::Custom(String::from("x-delayed-message")),
+            PASSIVE_EXCHANGE,
+            FieldTable::default(),
+        )
+        .await;
+    if declared.is_err() {
+        return Ok(());
+    }
+
+    channel
+        .exchange_bind(
+            &exchange,
+            &legacy_exchange,
+            "",
+            ExchangeBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    if channel
+        .queue_declare(&legacy_queue, PASSIVE_QUEUE, FieldTable::default())
         .await
-        .unwrap();
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    // Due messages reach the durable exchange through the binding above, so the legacy queue stops receiving them
+    channel
+        .queue_unbind(&legacy_queue, &legacy_exchange, "", FieldTable::default())
+        .await?;
+
+    let mut drained = 0;
+    while let Some(message) = channel
+        .basic_get(&legacy_queue, BasicGetOptions::default())
+        .await?
+    {
+        let delivery = message.delivery;
+        let confirm = channel
+            .basic_publish(
+                &exchange,
+                "",
+                BasicPublishOptions::default(),
+                &delivery.data,
+                undelayed(delivery.properties.clone()).with_delivery_mode(PERSISTENT_DELIVERY_MODE),
+            )
+            .await?
+            .await?;
+        confirmed(confirm, &exchange)?;
+
+        delivery.acker.ack(BasicAckOptions::default()).await?;
+        drained += 1;
+    }
+
+    // Dispatchers of older versions may still consume the legacy queue, it is deleted on a later connect then
+    let deleted = channel
+        .queue_delete(
+            &legacy_queue,
+            QueueDeleteOptions {
+                if_unused: true,
+                if_empty: true,
+                nowait: false,
+            },
+        )
+        .await;
+
+    info!(
+        "{} messages moved from legacy queue {} to {} (legacy queue deleted: {})",
+        drained,
+        legacy_queue,
+        exchange,
+        deleted.is_ok()
+    );
+
+    if deleted.is_ok() {
+        channel.close(200, "Legacy queue migrated").await?;
+    }
+
+    Ok(())
+}
+
+// Messages in the legacy queue are due already, so they must not be held by the delayed exchange again
+fn undelayed(properties: BasicProperties) -> BasicProperties {
+    let headers: BTreeMap<ShortString, AMQPValue> = properties
+        .headers()
+        .as_ref()
+        .map(|headers| headers.inner().clone())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(key, _)| key.as_str() != "x-delay")
+        .collect();
+
+    properties.with_headers(FieldTable::from(headers))
+}
+
+async fn declare_priority_queue(
+    channel: &Channel,
+    amqp_config: &AMQPConfig,
+    priority: Priority,
+) -> Result<(), Error> {
+    let queue_args = FieldTable::from(BTreeMap::from(
+        [(
+            ShortString::from("x-dead-letter-exchange"),
+            AMQPValue::LongString(amqp_config.dead_letter_exchange_name().into()),
+        ); 1],
+    ));
 
     let queue = channel
         .queue_declare(
-            &amqp_config.sent_message_queue_name(),
-            QueueDeclareOptions::default(),
+            &amqp_config.priority_queue_name(priority),
+            DURABLE_QUEUE,
+            queue_args,
+        )
+        .await?;
+
+    // Messages published before priorities were introduced or routed from the legacy exchange have no routing key
+    let mut routing_keys = vec![priority.to_string()];
+    if priority == Priority::Normal {
+        routing_keys.push(String::new());
+    }
+
+    for routing_key in routing_keys {
+        channel
+            .queue_bind(
+                queue.name().as_str(),
+                &amqp_config.sent_message_exchange_name(),
+                &routing_key,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    info!("queue declared {:?}", queue);
+
+    Ok(())
+}
+
+async fn declare_dead_letter(channel: &Channel, amqp_config: &AMQPConfig) -> Result<(), Error> {
+    let exchange = amqp_config.dead_letter_exchange_name();
+    let queue = amqp_config.dead_letter_queue_name();
+
+    channel
+        .exchange_declare(
+            &exchange,
+            ExchangeKind::Fanout,
+            DURABLE_EXCHANGE,
             FieldTable::default(),
         )
-        .await
-        .unwrap();
+        .await?;
+
+    channel
+        .queue_declare(&queue, DURABLE_QUEUE, FieldTable::default())
+        .await?;
 
     channel
         .queue_bind(
-            queue.name().as_str(),
-            &amqp_config.sent_message_exchange_name(),
+            &queue,
+            &exchange,
             "",
             QueueBindOptions::default(),
             FieldTable::default(),
         )
-        .await
-        .unwrap();
+        .await?;
 
-    info!("queue declared {:?}", queue);
+    Ok(())
+}
 
-    channel
+struct AmqpConnector {
+    amqp_config: AMQPConfig,
+    state: Mutex<(Connection, Channel)>,
+}
+
+impl AmqpConnector {
+    async fn channel(&self) -> Result<Channel, Error> {
+        let mut state = self.state.lock().await;
+
+        if !state.1.status().connected() {
+            warn!(
+                "AMQP channel is {:?}, reconnecting",
+                state.1.status().state()
+            );
+
+            *state = try_connect(&self.amqp_config).await?;
+        }
+
+        Ok(state.1.clone())
+    }
 }
 
-pub struct Publisher {
-    channel: Channel,
+pub struct AmqpQueue {
+    connector: Arc<AmqpConnector>,
     amqp_config: AMQPConfig,
 }
 
-impl Publisher {
-    pub fn new(channel: Channel, amqp_config: AMQPConfig) -> Self {
+impl AmqpQueue {
+    pub async fn connect(amqp_config: AMQPConfig) -> Self {
+        let state = connect_with_rabbit(amqp_config.clone()).await;
+
         Self {
-            channel,
+            connector: Arc::new(AmqpConnector {
+                amqp_config: amqp_config.clone(),
+                state: Mutex::new(state),
+            }),
             amqp_config,
         }
     }
 
-    pub async fn publish(&self, message: AsyncMessage) {
+    async fn lane_consumer(&self, consumer_tag: &str, priority: Priority) -> AmqpConsumer {
+        let mut consumer = AmqpConsumer {
+            connector: self.connector.clone(),
+            channel: None,
+            consumer: None,
+            consumer_tag: format!("{}-{}", consumer_tag, priority),
+            queue: self.amqp_config.priority_queue_name(priority),
+            prefetch: self.amqp_config.prefetch(),
+        };
+        consumer.subscribe().await.unwrap();
+
+        consumer
+    }
+
+    fn resolve_exchange(&self, message: &AsyncMessage) -> String {
+        match message {
+            AsyncMessage::SentMessage(_) => self.amqp_config.sent_message_exchange_name().clone(),
+        }
+    }
+
+    async fn do_publish(
+        &self,
+        message: AsyncMessage,
+        properties: BasicProperties,
+    ) -> Result<(), Error> {
+        let exchange = self.resolve_exchange(&message);
+        let cx = start_span(
+            format!("{} publish", exchange),
+            SpanKind::Producer,
+            &Context::current(),
+        );
+
+        let mut headers = properties.headers().clone().unwrap_or_default();
+        inject_amqp_headers(&cx, &mut headers);
+
+        let properties = properties
+            .with_headers(headers)
+            .with_delivery_mode(PERSISTENT_DELIVERY_MODE);
+        let result = self
+            .publish_on_channel(&exchange, message, properties)
+            .await;
+
+        end_span(&cx, result.as_ref().err().map(|err| format!("{:?}", err)));
+
+        result
+    }
+
+    async fn publish_on_channel(
+        &self,
+        exchange: &str,
+        message: AsyncMessage,
+        properties: BasicProperties,
+    ) -> Result<(), Error> {
+        let confirm = self
+            .connector
+            .channel()
+            .await?
+            .basic_publish(
+                exchange,
+                &message.priority().to_string(),
+                BasicPublishOptions::default(),
+                &message.encode(),
+                properties,
+            )
+            .await?
+            .await?;
+
+        confirmed(confirm, exchange)
+    }
+}
+
+fn confirmed(confirm: Confirmation, exchange: &str) -> Result<(), Error> {
+    match confirm {
+        Confirmation::Ack(_) => Ok(()),
+        Confirmation::Nack(_) => Err(Error::Queue(format!(
+            "Message was nacked by broker on {}",
+            exchange
+        ))),
+        Confirmation::NotRequested => Err(Error::Queue(
+            "Publisher confirms are not enabled on the channel".to_string(),
+        )),
+    }
+}
+
+#[async_trait]
+impl Queue for AmqpQueue {
+    fn kind(&self) -> &'static str {
+        "amqp"
+    }
+
+    async fn publish(&self, message: AsyncMessage) -> Result<(), Error> {
         self.do_publish(message, BasicProperties::default()).await
     }
 
-    pub async fn publish_delayed(&self, message: AsyncMessage, delay: Duration) {
+    async fn publish_delayed(&self, message: AsyncMessage, delay: Duration) -> Result<(), Error> {
         let btree: BTreeMap<_, _> = [(
             ShortString::from("x-delay"),
             AMQPValue::LongLongInt(delay.as_millis() as i64),
@@ -97,50 +435,263 @@ impl Publisher {
         self.do_publish(message, properties).await
     }
 
-    fn resolve_exchange(&self, message: &AsyncMessage) -> String {
-        match message {
-            AsyncMessage::SentMessage(_) => self.amqp_config.sent_message_exchange_name().clone(),
-        }
+    async fn consumer(&self, consumer_tag: &str) -> Box<dyn QueueConsumer> {
+        Box::new(PriorityConsumer {
+            queue: self.amqp_config.sent_message_queue_name(),
+            lanes: [
+                self.lane_consumer(consumer_tag, Priority::High).await,
+                self.lane_consumer(consumer_tag, Priority::Normal).await,
+                self.lane_consumer(consumer_tag, Priority::Low).await,
+            ],
+            scheduler: LaneScheduler::default(),
+        })
     }
 
-    async fn do_publish(&self, message: AsyncMessage, properties: BasicProperties) {
-        let confirm = self
-            .channel
-            .basic_publish(
-                self.resolve_exchange(&message).as_str(),
-                "",
-                BasicPublishOptions::default(),
-                &Serializer::serialize(message),
-                properties,
+    async fn ping(&self) -> Result<(), String> {
+        let state = self.connector.state.lock().await;
+        let channel = &state.1;
+
+        if !channel.status().connected() {
+            return Err(format!("Channel is {:?}", channel.status().state()));
+        }
+
+        channel
+            .queue_declare(
+                &self.amqp_config.priority_queue_name(Priority::Normal),
+                QueueDeclareOptions {
+                    passive: true,
+                    ..QueueDeclareOptions::default()
+                },
+                FieldTable::default(),
             )
             .await
-            .unwrap()
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    // Delayed messages are held by the exchange until they are due, so they are not counted
+    async fn depth(&self) -> Result<u64, Error> {
+        let channel = self.connector.channel().await?;
+        let mut depth = 0;
+
+        for priority in Priority::ALL {
+            let queue = channel
+                .queue_declare(
+                    &self.amqp_config.priority_queue_name(priority),
+                    QueueDeclareOptions {
+                        passive: true,
+                        ..QueueDeclareOptions::default()
+                    },
+                    FieldTable::default(),
+                )
+                .await?;
+
+            depth += u64::from(queue.message_count());
+        }
+
+        Ok(depth)
+    }
+
+    async fn close(&self) {
+        let (connection, channel) = &*self.connector.state.lock().await;
+
+        if let Err(err) = channel.close(200, "Shutdown").await {
+            error!("Channel cannot be closed: {}", err);
+        }
+
+        if let Err(err) = connection.close(200, "Shutdown").await {
+            error!("AMQP connection cannot be closed: {}", err);
+        }
+    }
+}
+
+struct AmqpConsumer {
+    connector: Arc<AmqpConnector>,
+    channel: Option<Channel>,
+    consumer: Option<Consumer>,
+    consumer_tag: String,
+    queue: String,
+    prefetch: u16,
+}
+
+impl AmqpConsumer {
+    async fn subscribe(&mut self) -> Result<(), Error> {
+        let channel = self.connector.channel().await?;
+        // Lanes share the channel, so the limit is set for each consumer rather than globally
+        channel
+            .basic_qos(self.prefetch, BasicQosOptions::default())
+            .await?;
+        let consumer = channel
+            .basic_consume(
+                &self.queue,
+                &self.consumer_tag,
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+
+        self.channel = Some(channel);
+        self.consumer = Some(consumer);
+
+        Ok(())
+    }
+
+    async fn resubscribe(&mut self) {
+        self.consumer = None;
+
+        let mut delay = MIN_RESUBSCRIBE_DELAY;
+
+        while let Err(err) = self.subscribe().await {
+            error!(
+                "Consumer cannot subscribe to {}, retrying in {:?}: {:?}",
+                self.queue, delay, err
+            );
+
+            sleep(delay).await;
+            delay = (delay * 2).min(MAX_RESUBSCRIBE_DELAY);
+        }
+
+        info!("Consumer subscribed to {} again", self.queue);
+    }
+}
+
+#[async_trait]
+impl QueueConsumer for AmqpConsumer {
+    fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    async fn next(&mut self) -> Option<QueueDelivery> {
+        loop {
+            let next = match self.consumer.as_mut() {
+                Some(consumer) => consumer.next().await,
+                None => None,
+            };
+
+            match next {
+                Some(Ok(delivery)) => {
+                    let parent = extract_amqp_headers(delivery.properties.headers().as_ref());
+
+                    return Some(QueueDelivery::new(
+                        delivery.data,
+                        parent,
+                        Box::new(AmqpAcknowledger(delivery.acker)),
+                    ));
+                }
+                Some(Err(err)) => error!("Consumer of {} failed: {}", self.queue, err),
+                None => warn!("Consumer of {} was closed", self.queue),
+            }
+
+            self.resubscribe().await;
+        }
+    }
+
+    async fn cancel(&mut self) {
+        let Some(channel) = &self.channel else {
+            return;
+        };
+
+        if let Err(err) = channel
+            .basic_cancel(&self.consumer_tag, BasicCancelOptions::default())
             .await
-            .unwrap();
+        {
+            error!("Consumer cannot be cancelled: {}", err);
+        }
+    }
+}
+
+struct PriorityConsumer {
+    queue: String,
+    lanes: [AmqpConsumer; 3],
+    scheduler: LaneScheduler,
+}
+
+#[async_trait]
+impl QueueConsumer for PriorityConsumer {
+    fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    // The preferred lane of this turn goes first and the others follow, so no lane waits while another has messages
+    async fn next(&mut self) -> Option<QueueDelivery> {
+        let mut lanes = self.lanes.each_mut().map(Some);
+        let [first, second, third] = self
+            .scheduler
+            .order()
+            .map(|lane| lanes[lane].take().unwrap());
+
+        tokio::select! {
+            biased;
+            delivery = first.next() => delivery,
+            delivery = second.next() => delivery,
+            delivery = third.next() => delivery,
+        }
+    }
 
-        assert_eq!(confirm, Confirmation::NotRequested);
+    async fn cancel(&mut self) {
+        for lane in &mut self.lanes {
+            lane.cancel().await;
+        }
     }
 }
 
-pub struct Serializer {}
+struct AmqpAcknowledger(Acker);
 
-impl Serializer {
-    pub fn deserialize<T>(binary: &[u8]) -> T
-    where
-        T: DeserializeOwned,
-    {
-        let msg = String::from_utf8_lossy(binary);
+#[async_trait]
+impl Acknowledger for AmqpAcknowledger {
+    async fn ack(&self) {
+        if let Err(err) = self.0.ack(BasicAckOptions::default()).await {
+            error!("Delivery cannot be acked: {}", err);
+        }
+    }
 
-        serde_json::from_str(&msg).unwrap()
+    async fn requeue(&self) {
+        let result = self
+            .0
+            .nack(BasicNackOptions {
+                requeue: true,
+                ..BasicNackOptions::default()
+            })
+            .await;
+
+        if let Err(err) = result {
+            error!("Delivery cannot be requeued: {}", err);
+        }
     }
 
-    pub fn serialize<T>(value: T) -> Vec<u8>
-    // is possible to return &[u8] ?
-    where
-        T: Serialize,
-    {
-        let string = serde_json::to_string(&value);
+    async fn reject(&self) {
+        let result = self
+            .0
+            .nack(BasicNackOptions {
+                requeue: false,
+                ..BasicNackOptions::default()
+            })
+            .await;
+
+        if let Err(err) = result {
+            error!("Delivery cannot be dead-lettered: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lapin::publisher_confirm::Confirmation;
+    use test_case::test_case;
+
+    use crate::amqp::confirmed;
+    use crate::error::Error;
 
-        string.unwrap().as_bytes().to_vec()
+    #[test_case(Confirmation::Ack(None), Ok(()))]
+    #[test_case(
+        Confirmation::Nack(None),
+        Err(Error::Queue("Message was nacked by broker on sent-message-exchange".to_string()))
+    )]
+    #[test_case(
+        Confirmation::NotRequested,
+        Err(Error::Queue("Publisher confirms are not enabled on the channel".to_string()))
+    )]
+    fn only_acked_publish_is_confirmed(confirm: Confirmation, expected: Result<(), Error>) {
+        assert_eq!(expected, confirmed(confirm, "sent-message-exchange"));
     }
 }
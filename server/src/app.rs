@@ -1,31 +1,73 @@
+use std::future::Future;
 use std::net::TcpListener;
+use std::sync::Arc;
 
-use actix_web::dev::Server;
+use actix_web::dev::{Server, Service};
 use actix_web::middleware::Logger;
-use actix_web::web::Data;
+use actix_web::web::{get, Data};
 use actix_web::{App, HttpServer};
 use log::info;
+use opentelemetry::trace::FutureExt;
 use sqlx::PgPool;
+use tokio::select;
+use tokio::signal::ctrl_c;
+use tokio::signal::unix::{signal, SignalKind};
 
-use crate::amqp::{establish_connection_with_rabbit, Publisher};
-use crate::config::AMQPConfig;
+use crate::config::{DeliveryConfig, RetentionConfig};
+use crate::crypto::Cipher;
 use crate::dispatch_consumer::consume;
+use crate::egress::EgressPolicy;
+use crate::handlers::health_check::{health_check, readiness};
+use crate::handlers::metrics::metrics;
+use crate::health::HealthCheck;
+use crate::memory_queue::InMemoryQueue;
+use crate::queue::Queue;
+use crate::retention::Purger;
 use crate::routes::routes;
 use crate::storage::Storage;
+use crate::telemetry::{end_server_span, start_server_span};
 
 pub async fn run_server(
     listener: TcpListener,
     pool: PgPool,
-    amqp_config: AMQPConfig,
+    queue: Arc<dyn Queue>,
+    cipher: Cipher,
+    egress: EgressPolicy,
 ) -> Result<Server, std::io::Error> {
-    let channel = establish_connection_with_rabbit(amqp_config.clone()).await;
-    let storage = Data::new(Storage::new(pool));
-    let publisher = Data::new(Publisher::new(channel.clone(), amqp_config));
+    let health = HealthCheck::new(pool.clone(), queue.clone());
+
+    run_server_with_storage(listener, Storage::new(pool, cipher), health, queue, egress)
+}
+
+pub fn run_server_with_storage(
+    listener: TcpListener,
+    storage: Storage,
+    health: HealthCheck,
+    queue: Arc<dyn Queue>,
+    egress: EgressPolicy,
+) -> Result<Server, std::io::Error> {
+    let health = Data::new(health);
+    let storage = Data::new(storage);
+    let publisher: Data<dyn Queue> = Data::from(queue);
+    let egress = Data::new(egress);
     let app = move || {
         App::new()
+            .wrap_fn(|request, service| {
+                let cx = start_server_span(&request);
+                let response = service.call(request).with_context(cx.clone());
+
+                async move {
+                    let response = response.await;
+                    end_server_span(&cx, response.as_ref().ok().map(|r| r.status()));
+
+                    response
+                }
+            })
             .wrap(Logger::default())
             .app_data(storage.clone())
             .app_data(publisher.clone())
+            .app_data(egress.clone())
+            .app_data(health.clone())
             .configure(routes)
     };
 
@@ -37,8 +79,100 @@ pub async fn run_server(
     Ok(server)
 }
 
-pub async fn run_dispatcher(pool: PgPool, amqp_config: AMQPConfig) {
-    let channel = establish_connection_with_rabbit(amqp_config.clone()).await;
+pub fn run_metrics_server(
+    listener: TcpListener,
+    health: HealthCheck,
+    queue: Option<Arc<dyn Queue>>,
+) -> Result<Server, std::io::Error> {
+    let addr = listener.local_addr().unwrap();
+    let health = Data::new(health);
+    let queue: Option<Data<dyn Queue>> = queue.map(Data::from);
+    let server = HttpServer::new(move || {
+        let app = App::new().app_data(health.clone());
+        let app = match &queue {
+            Some(queue) => app.app_data(queue.clone()),
+            None => app,
+        };
+
+        app.route("/metrics", get().to(metrics))
+            .route("/health/live", get().to(health_check))
+            .route("/health/ready", get().to(readiness))
+    })
+    .workers(1)
+    .listen(listener)?
+    .run();
+
+    info!("Metrics are exposed on {}/metrics", addr);
+
+    Ok(server)
+}
+
+pub async fn run_dispatcher(
+    pool: PgPool,
+    queue: Arc<dyn Queue>,
+    cipher: Cipher,
+    delivery_config: DeliveryConfig,
+    egress: EgressPolicy,
+    shutdown: impl Future<Output = ()>,
+) {
+    consume(
+        queue,
+        "dispatcher",
+        Storage::new(pool, cipher),
+        delivery_config,
+        egress,
+        shutdown,
+    )
+    .await;
+}
+
+// Server and dispatcher share in-memory storage and queue, so nothing else has to run
+pub async fn run_dev(
+    listener: TcpListener,
+    delivery_config: DeliveryConfig,
+    egress: EgressPolicy,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), std::io::Error> {
+    let storage = Storage::in_memory();
+    let queue: Arc<dyn Queue> = Arc::new(InMemoryQueue::new());
+
+    let server = run_server_with_storage(
+        listener,
+        storage.clone(),
+        HealthCheck::without_database(queue.clone()),
+        queue.clone(),
+        egress.clone(),
+    )?;
+    let server_handle = server.handle();
+
+    #[allow(clippy::let_underscore_future)]
+    let _ = tokio::spawn(server);
+
+    consume(
+        queue,
+        "dispatcher",
+        storage,
+        delivery_config,
+        egress,
+        shutdown,
+    )
+    .await;
+
+    server_handle.stop(true).await;
+
+    Ok(())
+}
+
+pub async fn shutdown_signal() {
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("SIGTERM handler cannot be installed");
+
+    select! {
+        _ = ctrl_c() => info!("SIGINT received"),
+        _ = terminate.recv() => info!("SIGTERM received"),
+    }
+}
 
-    consume(channel, "dispatcher", Storage::new(pool), amqp_config).await;
+pub async fn run_purger(pool: PgPool, retention_config: RetentionConfig) {
+    Purger::new(pool, retention_config).run().await;
 }
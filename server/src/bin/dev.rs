@@ -0,0 +1,28 @@
+use std::net::TcpListener;
+
+use dotenv::dotenv;
+use envconfig::Envconfig;
+use log::info;
+
+use server::app::{run_dev, shutdown_signal};
+use server::config::{DeliveryConfig, EgressConfig, LogConfig, ServerConfig};
+use server::logs::init_log;
+
+#[tokio::main]
+async fn main() -> Result<(), std::io::Error> {
+    dotenv().ok();
+    init_log(&LogConfig::init_from_env().unwrap());
+
+    let config = ServerConfig::init_from_env().unwrap();
+    let listener = TcpListener::bind((config.host, config.port))
+        .unwrap_or_else(|_| panic!("Failed to bind port {}", config.port));
+
+    let delivery_config = DeliveryConfig::init_from_env().unwrap();
+    let egress = EgressConfig::init_from_env().unwrap().policy();
+
+    run_dev(listener, delivery_config, egress, shutdown_signal()).await?;
+
+    info!("Development server has been stopped");
+
+    Ok(())
+}
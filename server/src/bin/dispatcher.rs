@@ -1,20 +1,65 @@
+use std::net::TcpListener;
+
 use dotenv::dotenv;
 use envconfig::Envconfig;
+use log::info;
 use sqlx::PgPool;
 
-use server::app::run_dispatcher;
-use server::config::{AMQPConfig, PostgresConfig};
+use server::app::{run_dispatcher, run_metrics_server, shutdown_signal};
+use server::config::{
+    AMQPConfig, DeliveryConfig, EgressConfig, EncryptionConfig, LogConfig, MetricsConfig,
+    PostgresConfig, QueueConfig, TracingConfig,
+};
+use server::health::HealthCheck;
 use server::logs::init_log;
+use server::queue::connect_queue;
+use server::telemetry::{init_tracing, shutdown_tracing};
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    init_log();
+    init_log(&LogConfig::init_from_env().unwrap());
+    init_tracing(
+        &TracingConfig::init_from_env().unwrap(),
+        "webhooks-dispatcher",
+    );
+
+    let metrics_config = MetricsConfig::init_from_env().unwrap();
+    let listener = TcpListener::bind((metrics_config.host, metrics_config.port))
+        .unwrap_or_else(|_| panic!("Failed to bind port {}", metrics_config.port));
 
     let con_string = PostgresConfig::init_from_env().unwrap().connection_string();
     let pool = PgPool::connect(&con_string).await.unwrap();
 
-    let amqp_config = AMQPConfig::init_from_env().unwrap();
+    let queue = connect_queue(
+        &QueueConfig::init_from_env().unwrap(),
+        AMQPConfig::init_from_env().unwrap(),
+        pool.clone(),
+    )
+    .await;
+    let cipher = EncryptionConfig::init_from_env().unwrap().cipher();
+    let delivery_config = DeliveryConfig::init_from_env().unwrap();
+    let egress = EgressConfig::init_from_env().unwrap().policy();
+
+    let health = HealthCheck::new(pool.clone(), queue.clone());
+    let metrics_server = run_metrics_server(listener, health, Some(queue.clone())).unwrap();
+
+    #[allow(clippy::let_underscore_future)]
+    let _ = tokio::spawn(metrics_server);
+
+    run_dispatcher(
+        pool.clone(),
+        queue.clone(),
+        cipher,
+        delivery_config,
+        egress,
+        shutdown_signal(),
+    )
+    .await;
+
+    queue.close().await;
+    pool.close().await;
+    shutdown_tracing();
 
-    run_dispatcher(pool, amqp_config).await;
+    info!("Dispatcher has been stopped");
 }
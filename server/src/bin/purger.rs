@@ -0,0 +1,33 @@
+use std::net::TcpListener;
+
+use dotenv::dotenv;
+use envconfig::Envconfig;
+use sqlx::PgPool;
+
+use server::app::{run_metrics_server, run_purger};
+use server::config::{LogConfig, PostgresConfig, PurgerMetricsConfig, RetentionConfig};
+use server::health::HealthCheck;
+use server::logs::init_log;
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    init_log(&LogConfig::init_from_env().unwrap());
+
+    let metrics_config = PurgerMetricsConfig::init_from_env().unwrap();
+    let listener = TcpListener::bind((metrics_config.host, metrics_config.port))
+        .unwrap_or_else(|_| panic!("Failed to bind port {}", metrics_config.port));
+
+    let con_string = PostgresConfig::init_from_env().unwrap().connection_string();
+    let pool = PgPool::connect(&con_string).await.unwrap();
+
+    let retention_config = RetentionConfig::init_from_env().unwrap();
+
+    let health = HealthCheck::without_queue(pool.clone());
+    let metrics_server = run_metrics_server(listener, health, None).unwrap();
+
+    #[allow(clippy::let_underscore_future)]
+    let _ = tokio::spawn(metrics_server);
+
+    run_purger(pool, retention_config).await;
+}
@@ -4,23 +4,49 @@ use dotenv::dotenv;
 use envconfig::Envconfig;
 use sqlx::PgPool;
 
-use server::app::run_server;
-use server::config::{AMQPConfig, PostgresConfig, ServerConfig};
+use server::app::{run_metrics_server, run_server};
+use server::config::{
+    AMQPConfig, EgressConfig, EncryptionConfig, LogConfig, PostgresConfig, QueueConfig,
+    ServerConfig, ServerMetricsConfig, TracingConfig,
+};
+use server::health::HealthCheck;
 use server::logs::init_log;
+use server::queue::connect_queue;
+use server::telemetry::init_tracing;
 
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
     dotenv().ok();
-    init_log();
+    init_log(&LogConfig::init_from_env().unwrap());
+    init_tracing(&TracingConfig::init_from_env().unwrap(), "webhooks-server");
 
     let config = ServerConfig::init_from_env().unwrap();
     let listener = TcpListener::bind((config.host, config.port))
         .unwrap_or_else(|_| panic!("Failed to bind port {}", config.port));
 
+    let metrics_config = ServerMetricsConfig::init_from_env().unwrap();
+    let metrics_listener = TcpListener::bind((metrics_config.host, metrics_config.port))
+        .unwrap_or_else(|_| panic!("Failed to bind port {}", metrics_config.port));
+
     let con_string = PostgresConfig::init_from_env().unwrap().connection_string();
     let pool = PgPool::connect(&con_string).await.unwrap();
 
-    let amqp_config = AMQPConfig::init_from_env().unwrap();
+    let queue = connect_queue(
+        &QueueConfig::init_from_env().unwrap(),
+        AMQPConfig::init_from_env().unwrap(),
+        pool.clone(),
+    )
+    .await;
+    let cipher = EncryptionConfig::init_from_env().unwrap().cipher();
+    let egress = EgressConfig::init_from_env().unwrap().policy();
+
+    let health = HealthCheck::new(pool.clone(), queue.clone());
+    let metrics_server = run_metrics_server(metrics_listener, health, Some(queue.clone()))?;
+
+    #[allow(clippy::let_underscore_future)]
+    let _ = tokio::spawn(metrics_server);
 
-    run_server(listener, pool, amqp_config).await?.await
+    run_server(listener, pool, queue, cipher, egress)
+        .await?
+        .await
 }
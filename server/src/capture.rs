@@ -0,0 +1,290 @@
+use itertools::Itertools;
+use log::debug;
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use reqwest::Response;
+use serde_json::{Map, Value as JsonValue};
+
+const DEFAULT_BODY_LIMIT: usize = 64 * 1024;
+const TRUNCATION_MARKER: &str = "...[truncated]";
+const REDACTED: &str = "********";
+const SENSITIVE_HEADERS: [&str; 4] = [
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+];
+
+pub struct CapturedResponse {
+    pub body: String,
+    pub headers: JsonValue,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturePolicy {
+    body_limit: usize,
+    redacted_fields: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl CapturePolicy {
+    pub fn new(body_limit: usize, redacted_fields: Vec<String>) -> Self {
+        let redacted_fields: Vec<String> = redacted_fields
+            .iter()
+            .map(|field| field.trim().to_lowercase())
+            .filter(|field| !field.is_empty())
+            .collect();
+
+        let patterns = redacted_fields
+            .iter()
+            .map(|field| {
+                Regex::new(&format!(
+                    r#"(?i)("{}"\s*:\s*)("(?:[^"\\]|\\.)*"|[^,\}}\]\s]+)"#,
+                    regex::escape(field)
+                ))
+                .unwrap()
+            })
+            .collect();
+
+        Self {
+            body_limit,
+            redacted_fields,
+            patterns,
+        }
+    }
+
+    pub async fn capture(&self, mut response: Response) -> CapturedResponse {
+        let headers = self.headers(response.headers());
+
+        let mut body = Vec::new();
+        let mut truncated = false;
+
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    let remaining = self.body_limit - body.len();
+                    if chunk.len() > remaining {
+                        body.extend_from_slice(&chunk[..remaining]);
+                        truncated = true;
+                        break;
+                    }
+
+                    body.extend_from_slice(&chunk);
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    debug!("Response body cannot be read: {}", err);
+                    break;
+                }
+            }
+        }
+
+        CapturedResponse {
+            body: self.body(body, truncated),
+            headers,
+        }
+    }
+
+    fn body(&self, bytes: Vec<u8>, truncated: bool) -> String {
+        let marker = if truncated { TRUNCATION_MARKER } else { "" };
+
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(err) if truncated && err.utf8_error().error_len().is_none() => {
+                let valid_up_to = err.utf8_error().valid_up_to();
+                let mut bytes = err.into_bytes();
+                bytes.truncate(valid_up_to);
+
+                String::from_utf8(bytes).unwrap()
+            }
+            Err(err) => {
+                return format!("[binary body of {} bytes]{}", err.as_bytes().len(), marker);
+            }
+        };
+
+        format!("{}{}", self.redact(text), marker)
+    }
+
+    fn redact(&self, body: String) -> String {
+        if self.redacted_fields.is_empty() {
+            return body;
+        }
+
+        if let Ok(mut json) = serde_json::from_str::<JsonValue>(&body) {
+            self.redact_json(&mut json);
+
+            return json.to_string();
+        }
+
+        self.patterns.iter().fold(body, |body, pattern| {
+            pattern
+                .replace_all(&body, format!("${{1}}\"{}\"", REDACTED))
+                .to_string()
+        })
+    }
+
+    fn redact_json(&self, value: &mut JsonValue) {
+        match value {
+            JsonValue::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.is_redacted(key) {
+                        *value = JsonValue::String(REDACTED.to_string());
+                    } else {
+                        self.redact_json(value);
+                    }
+                }
+            }
+            JsonValue::Array(items) => items.iter_mut().for_each(|item| self.redact_json(item)),
+            _ => {}
+        }
+    }
+
+    fn headers(&self, headers: &HeaderMap) -> JsonValue {
+        let mut captured = Map::new();
+
+        for name in headers.keys() {
+            let value =
+                if SENSITIVE_HEADERS.contains(&name.as_str()) || self.is_redacted(name.as_str()) {
+                    REDACTED.to_string()
+                } else {
+                    headers
+                        .get_all(name)
+                        .iter()
+                        .map(|value| String::from_utf8_lossy(value.as_bytes()).to_string())
+                        .join(", ")
+                };
+
+            captured.insert(name.to_string(), JsonValue::String(value));
+        }
+
+        JsonValue::Object(captured)
+    }
+
+    fn is_redacted(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+
+        self.redacted_fields.iter().any(|field| field == &name)
+    }
+}
+
+impl Default for CapturePolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_BODY_LIMIT, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use test_case::test_case;
+
+    use crate::capture::CapturePolicy;
+
+    #[tokio::test]
+    async fn body_is_truncated_at_limit() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/")
+            .with_body("a".repeat(100))
+            .create_async()
+            .await;
+
+        let response = reqwest::get(server.url()).await.unwrap();
+
+        let captured = CapturePolicy::new(10, Vec::new()).capture(response).await;
+
+        assert_eq!("aaaaaaaaaa...[truncated]", captured.body);
+    }
+
+    #[tokio::test]
+    async fn body_within_limit_is_not_truncated() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/")
+            .with_body("0123456789")
+            .create_async()
+            .await;
+
+        let response = reqwest::get(server.url()).await.unwrap();
+
+        let captured = CapturePolicy::new(10, Vec::new()).capture(response).await;
+
+        assert_eq!("0123456789", captured.body);
+    }
+
+    #[tokio::test]
+    async fn headers_are_captured_with_sensitive_values_redacted() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/")
+            .with_header("x-request-id", "abc")
+            .with_header("set-cookie", "session=secret")
+            .with_header("x-api-key", "key")
+            .create_async()
+            .await;
+
+        let response = reqwest::get(server.url()).await.unwrap();
+
+        let captured = CapturePolicy::new(10, vec!["X-Api-Key".to_string()])
+            .capture(response)
+            .await;
+
+        assert_eq!("abc", captured.headers["x-request-id"]);
+        assert_eq!("********", captured.headers["set-cookie"]);
+        assert_eq!("********", captured.headers["x-api-key"]);
+    }
+
+    #[test]
+    fn binary_body_is_not_captured() {
+        let sut = CapturePolicy::default();
+
+        assert_eq!(
+            "[binary body of 4 bytes]",
+            sut.body(vec![0xff, 0xfe, 0x00, 0x01], false)
+        );
+    }
+
+    #[test]
+    fn multibyte_character_cut_by_limit_is_dropped() {
+        let sut = CapturePolicy::default();
+        let mut bytes = "zażółć".as_bytes().to_vec();
+        bytes.truncate(3);
+
+        assert_eq!("za...[truncated]", sut.body(bytes, true));
+    }
+
+    #[test_case(
+        json!({"token": "abc", "user": {"Password": "secret", "name": "john"}}),
+        json!({"token": "********", "user": {"Password": "********", "name": "john"}})
+        ; "nested fields"
+    )]
+    #[test_case(
+        json!([{"token": {"value": "abc"}}]),
+        json!([{"token": "********"}])
+        ; "fields in array"
+    )]
+    fn json_fields_are_redacted(body: Value, expected: Value) {
+        let sut = CapturePolicy::new(1024, vec!["token".to_string(), "password".to_string()]);
+
+        let redacted = sut.body(body.to_string().into_bytes(), false);
+
+        assert_eq!(expected, serde_json::from_str::<Value>(&redacted).unwrap());
+    }
+
+    #[test]
+    fn fields_are_redacted_in_truncated_json() {
+        let sut = CapturePolicy::new(1024, vec!["token".to_string()]);
+
+        let redacted = sut.body(
+            r#"{"token": "abc\"def", "expires": 3600, "refresh_token": "x"#
+                .as_bytes()
+                .to_vec(),
+            true,
+        );
+
+        assert_eq!(
+            r#"{"token": "********", "expires": 3600, "refresh_token": "x...[truncated]"#,
+            redacted
+        );
+    }
+}
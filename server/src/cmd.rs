@@ -1,35 +1,251 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use crate::types::MessageId;
+use crate::configuration::domain::Priority;
+use crate::error::Error;
+use crate::error::Error::InvalidArgument;
+use crate::types::{ApplicationId, MessageId};
 
-#[derive(Serialize, Deserialize)]
+pub const MESSAGE_VERSION: u64 = 1;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(tag = "t", content = "c")]
 pub enum AsyncMessage {
     SentMessage(SentMessage),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl AsyncMessage {
+    pub fn to_value(&self) -> Value {
+        let mut value = json!(self);
+        value["v"] = json!(MESSAGE_VERSION);
+
+        value
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.to_value()).unwrap()
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<Self, Error> {
+        let mut value: Value = serde_json::from_slice(binary)
+            .map_err(|err| InvalidArgument(format!("Message is not valid JSON: {}", err)))?;
+
+        let version = match value.as_object_mut().and_then(|object| object.remove("v")) {
+            None => 0,
+            Some(version) => version.as_u64().ok_or_else(|| {
+                InvalidArgument(format!("Message version '{}' is invalid", version))
+            })?,
+        };
+
+        if version > MESSAGE_VERSION {
+            return Err(InvalidArgument(format!(
+                "Message version {} is not supported",
+                version
+            )));
+        }
+
+        serde_json::from_value(value)
+            .map_err(|err| InvalidArgument(format!("Message cannot be decoded: {}", err)))
+    }
+
+    pub fn priority(&self) -> Priority {
+        match self {
+            AsyncMessage::SentMessage(cmd) => cmd.priority,
+        }
+    }
+
+    pub fn app_id(&self) -> Option<ApplicationId> {
+        match self {
+            AsyncMessage::SentMessage(cmd) => cmd.app_id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "SentMessagePayload", into = "SentMessagePayload")]
 pub struct SentMessage {
-    msg_id: String,
+    msg_id: MessageId,
     pub attempt: usize,
+    pub redelivery: usize,
+    pub priority: Priority,
+    pub app_id: Option<ApplicationId>,
 }
 
 impl SentMessage {
     pub fn new(message_id: MessageId) -> Self {
         Self {
-            msg_id: message_id.to_string(),
+            msg_id: message_id,
             attempt: 1,
+            redelivery: 0,
+            priority: Priority::default(),
+            app_id: None,
         }
     }
 
+    #[must_use]
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    #[must_use]
+    pub fn with_app_id(mut self, app_id: ApplicationId) -> Self {
+        self.app_id = Some(app_id);
+        self
+    }
+
+    // Retries stay in their lane and are queued behind fresh messages once their delay has passed
     pub fn with_increased_attempt(&self) -> SentMessage {
         Self {
-            msg_id: self.msg_id.clone(),
+            msg_id: self.msg_id,
             attempt: self.attempt + 1,
+            redelivery: 0,
+            priority: self.priority,
+            app_id: self.app_id,
+        }
+    }
+
+    pub fn with_increased_redelivery(&self) -> SentMessage {
+        Self {
+            redelivery: self.redelivery + 1,
+            ..self.clone()
         }
     }
 
     pub fn msg_id(&self) -> MessageId {
-        MessageId::try_from(self.msg_id.clone()).unwrap()
+        self.msg_id
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SentMessagePayload {
+    msg_id: String,
+    attempt: usize,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    redelivery: usize,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    app_id: Option<String>,
+}
+
+impl TryFrom<SentMessagePayload> for SentMessage {
+    type Error = Error;
+
+    fn try_from(value: SentMessagePayload) -> Result<Self, Self::Error> {
+        Ok(Self {
+            msg_id: MessageId::try_from(value.msg_id)?,
+            attempt: value.attempt,
+            redelivery: value.redelivery,
+            priority: value.priority,
+            app_id: value.app_id.map(ApplicationId::try_from).transpose()?,
+        })
+    }
+}
+
+impl From<SentMessage> for SentMessagePayload {
+    fn from(value: SentMessage) -> Self {
+        Self {
+            msg_id: value.msg_id.to_string(),
+            attempt: value.attempt,
+            redelivery: value.redelivery,
+            priority: value.priority,
+            app_id: value.app_id.map(|app_id| app_id.to_string()),
+        }
+    }
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::cmd::{AsyncMessage, SentMessage};
+    use crate::configuration::domain::Priority;
+    use crate::error::Error::InvalidArgument;
+    use crate::types::{ApplicationId, MessageId};
+
+    #[test]
+    fn message_is_encoded_with_version() {
+        let msg_id = MessageId::new();
+        let app_id = ApplicationId::new();
+        let message = AsyncMessage::SentMessage(
+            SentMessage::new(msg_id)
+                .with_priority(Priority::High)
+                .with_app_id(app_id),
+        );
+
+        let encoded = message.encode();
+
+        assert_eq!(
+            json!({"v": 1, "t": "SentMessage", "c": {"msg_id": msg_id.to_string(), "attempt": 1, "priority": "high", "app_id": app_id.to_string()}}),
+            serde_json::from_slice::<serde_json::Value>(&encoded).unwrap()
+        );
+        assert_eq!(Ok(message), AsyncMessage::decode(&encoded));
+    }
+
+    #[test]
+    fn message_without_version_is_decoded() {
+        let msg_id = MessageId::new();
+        let legacy = json!({"t": "SentMessage", "c": {"msg_id": msg_id.to_string(), "attempt": 3}});
+
+        let message = AsyncMessage::decode(legacy.to_string().as_bytes()).unwrap();
+
+        let AsyncMessage::SentMessage(cmd) = message;
+        assert_eq!(msg_id, cmd.msg_id());
+        assert_eq!(3, cmd.attempt);
+        assert_eq!(Priority::Normal, cmd.priority);
+        assert_eq!(None, cmd.app_id);
+    }
+
+    #[test]
+    fn retried_message_keeps_its_priority() {
+        let cmd = SentMessage::new(MessageId::new()).with_priority(Priority::High);
+
+        let retried = cmd.with_increased_attempt();
+
+        assert_eq!(2, retried.attempt);
+        assert_eq!(Priority::High, retried.priority);
+    }
+
+    #[test]
+    fn redelivered_message_keeps_its_attempt() {
+        let cmd = SentMessage::new(MessageId::new()).with_increased_attempt();
+
+        let redelivered = cmd.with_increased_redelivery();
+        let encoded = AsyncMessage::SentMessage(redelivered.clone()).encode();
+
+        assert_eq!(2, redelivered.attempt);
+        assert_eq!(1, redelivered.redelivery);
+        assert_eq!(0, redelivered.with_increased_attempt().redelivery);
+        assert_eq!(
+            Ok(AsyncMessage::SentMessage(redelivered)),
+            AsyncMessage::decode(&encoded)
+        );
+    }
+
+    #[test]
+    fn message_with_newer_version_is_not_decoded() {
+        let message = json!({"v": 2, "t": "SentMessage", "c": {"msg_id": MessageId::new().to_string(), "attempt": 1}});
+
+        assert_eq!(
+            Err(InvalidArgument(
+                "Message version 2 is not supported".to_string()
+            )),
+            AsyncMessage::decode(message.to_string().as_bytes())
+        );
+    }
+
+    #[test]
+    fn malformed_messages_are_not_decoded() {
+        let invalid_id = json!({"t": "SentMessage", "c": {"msg_id": "msg_invalid", "attempt": 1}});
+        let unknown = json!({"t": "Unknown", "c": {}});
+
+        assert!(AsyncMessage::decode(b"not json").is_err());
+        assert!(AsyncMessage::decode(invalid_id.to_string().as_bytes()).is_err());
+        assert!(AsyncMessage::decode(unknown.to_string().as_bytes()).is_err());
     }
 }
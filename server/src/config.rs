@@ -1,5 +1,12 @@
+use std::time::Duration;
+
 use envconfig::Envconfig;
 
+use crate::capture::CapturePolicy;
+use crate::configuration::domain::{OutboundProxy, Priority};
+use crate::crypto::Cipher;
+use crate::egress::EgressPolicy;
+
 #[derive(Envconfig, Clone)]
 pub struct ServerConfig {
     #[envconfig(from = "SERVER_PORT")]
@@ -8,6 +15,74 @@ pub struct ServerConfig {
     pub host: String,
 }
 
+#[derive(Envconfig, Clone)]
+pub struct ServerMetricsConfig {
+    #[envconfig(from = "SERVER_METRICS_PORT", default = "9090")]
+    pub port: u16,
+    #[envconfig(from = "SERVER_METRICS_HOST", default = "localhost")]
+    pub host: String,
+}
+
+#[derive(Envconfig, Clone)]
+pub struct MetricsConfig {
+    #[envconfig(from = "DISPATCHER_METRICS_PORT", default = "9091")]
+    pub port: u16,
+    #[envconfig(from = "DISPATCHER_METRICS_HOST", default = "localhost")]
+    pub host: String,
+}
+
+#[derive(Envconfig, Clone)]
+pub struct PurgerMetricsConfig {
+    #[envconfig(from = "PURGER_METRICS_PORT", default = "9092")]
+    pub port: u16,
+    #[envconfig(from = "PURGER_METRICS_HOST", default = "localhost")]
+    pub host: String,
+}
+
+#[derive(Envconfig, Clone)]
+pub struct LogConfig {
+    #[envconfig(from = "LOG_LEVEL", default = "info")]
+    levels: String,
+    #[envconfig(from = "LOG_FORMAT", default = "text")]
+    format: String,
+    #[envconfig(from = "LOG_PAYLOADS", default = "false")]
+    payloads: bool,
+}
+
+impl LogConfig {
+    pub fn levels(&self) -> &str {
+        &self.levels
+    }
+
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    pub fn payloads(&self) -> bool {
+        self.payloads
+    }
+}
+
+#[derive(Envconfig, Clone)]
+pub struct TracingConfig {
+    #[envconfig(from = "OTEL_EXPORTER_OTLP_ENDPOINT", default = "")]
+    endpoint: String,
+    #[envconfig(from = "OTEL_SERVICE_NAME")]
+    service_name: Option<String>,
+}
+
+impl TracingConfig {
+    pub fn endpoint(&self) -> Option<&str> {
+        Some(self.endpoint.trim()).filter(|endpoint| !endpoint.is_empty())
+    }
+
+    pub fn service_name(&self, default: &str) -> String {
+        self.service_name
+            .clone()
+            .unwrap_or_else(|| default.to_string())
+    }
+}
+
 #[derive(Envconfig)]
 pub struct PostgresConfig {
     #[envconfig(from = "POSTGRES_HOST")]
@@ -64,6 +139,16 @@ pub struct AMQPConfig {
     password: String,
     #[envconfig(from = "AMQP_SENT_MESSAGE_QUEUE")]
     sent_message_queue: String,
+    #[envconfig(from = "AMQP_DEAD_LETTER_EXCHANGE")]
+    dead_letter_exchange: Option<String>,
+    #[envconfig(from = "AMQP_HIGH_PRIORITY_QUEUE")]
+    high_priority_queue: Option<String>,
+    #[envconfig(from = "AMQP_NORMAL_PRIORITY_QUEUE")]
+    normal_priority_queue: Option<String>,
+    #[envconfig(from = "AMQP_LOW_PRIORITY_QUEUE")]
+    low_priority_queue: Option<String>,
+    #[envconfig(from = "AMQP_PREFETCH", default = "64")]
+    prefetch: u16,
 }
 
 impl AMQPConfig {
@@ -76,11 +161,8 @@ impl AMQPConfig {
 
     pub fn with_sent_message_queue(&self, queue_name: &str) -> Self {
         Self {
-            host: self.host.clone(),
-            port: self.port,
-            user: self.user.clone(),
-            password: self.password.clone(),
             sent_message_queue: queue_name.to_string(),
+            ..self.clone()
         }
     }
 
@@ -88,7 +170,294 @@ impl AMQPConfig {
         self.sent_message_queue.clone()
     }
 
+    pub fn prefetch(&self) -> u16 {
+        self.prefetch
+    }
+
+    pub fn priority_queue_name(&self, priority: Priority) -> String {
+        let (configured, suffix) = match priority {
+            Priority::High => (&self.high_priority_queue, "high"),
+            Priority::Normal => (&self.normal_priority_queue, "normal"),
+            Priority::Low => (&self.low_priority_queue, "low"),
+        };
+
+        configured
+            .clone()
+            .filter(|queue| !queue.is_empty())
+            .unwrap_or_else(|| format!("{}-{}", self.sent_message_queue, suffix))
+    }
+
     pub fn sent_message_exchange_name(&self) -> String {
+        format!("{}-priority-exchange", self.sent_message_queue)
+    }
+
+    // Non-durable exchange and queue declared by versions before the durable topology
+    pub fn legacy_exchange_name(&self) -> String {
         format!("{}-exchange", self.sent_message_queue)
     }
+
+    pub fn legacy_queue_name(&self) -> String {
+        self.sent_message_queue.clone()
+    }
+
+    pub fn dead_letter_exchange_name(&self) -> String {
+        self.dead_letter_exchange
+            .clone()
+            .filter(|exchange| !exchange.is_empty())
+            .unwrap_or_else(|| format!("{}-dead-letter-exchange", self.sent_message_queue))
+    }
+
+    pub fn dead_letter_queue_name(&self) -> String {
+        format!("{}-dead-letter", self.sent_message_queue)
+    }
+}
+
+#[derive(Envconfig, Clone)]
+pub struct QueueConfig {
+    #[envconfig(from = "QUEUE_BACKEND", default = "amqp")]
+    backend: String,
+    #[envconfig(from = "QUEUE_POLL_INTERVAL_MS", default = "250")]
+    poll_interval_ms: u64,
+    #[envconfig(from = "QUEUE_VISIBILITY_TIMEOUT_SECS", default = "300")]
+    visibility_timeout_secs: u64,
+}
+
+impl QueueConfig {
+    pub fn with_backend(&self, backend: &str) -> Self {
+        Self {
+            backend: backend.to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn backend(&self) -> &str {
+        &self.backend
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+
+    pub fn visibility_timeout(&self) -> Duration {
+        Duration::from_secs(self.visibility_timeout_secs)
+    }
+}
+
+#[derive(Envconfig, Clone)]
+pub struct ProxyConfig {
+    #[envconfig(from = "DELIVERY_PROXY_URL")]
+    url: Option<String>,
+    #[envconfig(from = "DELIVERY_PROXY_USERNAME")]
+    username: Option<String>,
+    #[envconfig(from = "DELIVERY_PROXY_PASSWORD")]
+    password: Option<String>,
+    #[envconfig(from = "DELIVERY_NO_PROXY", default = "")]
+    no_proxy: String,
+}
+
+impl ProxyConfig {
+    pub fn proxy(&self) -> Option<OutboundProxy> {
+        let url = self.url.as_deref().filter(|url| !url.is_empty())?;
+        let no_proxy = self.no_proxy.split(',').map(str::to_string).collect();
+
+        let proxy = OutboundProxy::new(
+            url,
+            self.username.as_deref().filter(|u| !u.is_empty()),
+            self.password.as_deref().filter(|p| !p.is_empty()),
+            no_proxy,
+        )
+        .expect("Invalid DELIVERY_PROXY_URL");
+
+        Some(proxy)
+    }
+}
+
+#[derive(Envconfig, Clone)]
+pub struct RetentionConfig {
+    #[envconfig(from = "RETENTION_DAYS")]
+    days: Option<u32>,
+    #[envconfig(from = "RETENTION_PAYLOAD_DAYS")]
+    payload_days: Option<u32>,
+    #[envconfig(from = "RETENTION_BATCH_SIZE", default = "1000")]
+    batch_size: u32,
+    #[envconfig(from = "RETENTION_INTERVAL_SECS", default = "3600")]
+    interval_secs: u64,
+}
+
+impl RetentionConfig {
+    pub fn days(&self) -> Option<u32> {
+        self.days
+    }
+
+    pub fn payload_days(&self) -> Option<u32> {
+        self.payload_days
+    }
+
+    pub fn batch_size(&self) -> u32 {
+        self.batch_size
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+#[derive(Envconfig, Clone)]
+pub struct EncryptionConfig {
+    #[envconfig(from = "ENCRYPTION_KEY", default = "")]
+    key: String,
+}
+
+impl EncryptionConfig {
+    pub fn cipher(&self) -> Cipher {
+        if self.key.trim().is_empty() {
+            panic!("ENCRYPTION_KEY is not set, generate one with `openssl rand -base64 32`");
+        }
+
+        Cipher::new(&self.key).unwrap_or_else(|err| panic!("Invalid ENCRYPTION_KEY: {:?}", err))
+    }
+}
+
+#[derive(Envconfig, Clone)]
+pub struct EgressConfig {
+    #[envconfig(from = "EGRESS_ALLOWLIST", default = "")]
+    allowlist: String,
+}
+
+impl EgressConfig {
+    pub fn policy(&self) -> EgressPolicy {
+        EgressPolicy::new(&self.allowlist).expect("Invalid EGRESS_ALLOWLIST")
+    }
+}
+
+#[derive(Envconfig, Clone)]
+pub struct DeliveryConfig {
+    #[envconfig(from = "DELIVERY_CONNECT_TIMEOUT_MS", default = "5000")]
+    connect_timeout_ms: u64,
+    #[envconfig(from = "DELIVERY_REQUEST_TIMEOUT_MS", default = "30000")]
+    request_timeout_ms: u64,
+    #[envconfig(from = "DELIVERY_POOL_IDLE_TIMEOUT_MS", default = "90000")]
+    pool_idle_timeout_ms: u64,
+    #[envconfig(from = "DELIVERY_POOL_MAX_IDLE_PER_HOST", default = "32")]
+    pool_max_idle_per_host: usize,
+    #[envconfig(from = "DELIVERY_RESPONSE_BODY_LIMIT_BYTES", default = "65536")]
+    response_body_limit_bytes: usize,
+    #[envconfig(from = "DELIVERY_REDACTED_FIELDS", default = "")]
+    redacted_fields: String,
+    #[envconfig(from = "DELIVERY_SHUTDOWN_TIMEOUT_MS", default = "25000")]
+    shutdown_timeout_ms: u64,
+    #[envconfig(from = "DELIVERY_PREFETCH", default = "64")]
+    prefetch: usize,
+    #[envconfig(from = "DELIVERY_APP_CONCURRENCY", default = "0")]
+    app_concurrency: usize,
+    #[envconfig(from = "DELIVERY_DEFER_DELAY_MS", default = "1000")]
+    defer_delay_ms: u64,
+    #[envconfig(nested = true)]
+    proxy: ProxyConfig,
+}
+
+impl DeliveryConfig {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout_ms)
+    }
+
+    pub fn pool_idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.pool_idle_timeout_ms)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_millis(self.shutdown_timeout_ms)
+    }
+
+    pub fn prefetch(&self) -> usize {
+        self.prefetch.max(1)
+    }
+
+    pub fn app_concurrency(&self) -> Option<usize> {
+        Some(self.app_concurrency).filter(|concurrency| *concurrency > 0)
+    }
+
+    pub fn defer_delay(&self) -> Duration {
+        Duration::from_millis(self.defer_delay_ms)
+    }
+
+    pub fn pool_max_idle_per_host(&self) -> usize {
+        self.pool_max_idle_per_host
+    }
+
+    pub fn proxy(&self) -> Option<OutboundProxy> {
+        self.proxy.proxy()
+    }
+
+    pub fn capture_policy(&self) -> CapturePolicy {
+        CapturePolicy::new(
+            self.response_body_limit_bytes,
+            self.redacted_fields
+                .split(',')
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use envconfig::Envconfig;
+
+    use crate::config::{AMQPConfig, EncryptionConfig};
+    use crate::configuration::domain::Priority;
+
+    #[test]
+    #[should_panic(expected = "ENCRYPTION_KEY is not set")]
+    fn cipher_cannot_be_created_without_key() {
+        EncryptionConfig::init_from_hashmap(&HashMap::new())
+            .unwrap()
+            .cipher();
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid ENCRYPTION_KEY")]
+    fn cipher_cannot_be_created_with_invalid_key() {
+        let env = HashMap::from([("ENCRYPTION_KEY".to_string(), "c2hvcnQ=".to_string())]);
+
+        EncryptionConfig::init_from_hashmap(&env).unwrap().cipher();
+    }
+
+    #[test]
+    fn durable_topology_does_not_reuse_legacy_names() {
+        let env: HashMap<String, String> = [
+            ("AMQP_HOST", "localhost"),
+            ("AMQP_PORT", "5672"),
+            ("AMQP_USER", "guest"),
+            ("AMQP_PASSWORD", "guest"),
+            ("AMQP_SENT_MESSAGE_QUEUE", "sent-message"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let config = AMQPConfig::init_from_hashmap(&env).unwrap();
+
+        assert_eq!("sent-message-exchange", config.legacy_exchange_name());
+        assert_eq!("sent-message", config.legacy_queue_name());
+        assert_eq!(
+            "sent-message-priority-exchange",
+            config.sent_message_exchange_name()
+        );
+        assert_eq!(
+            vec![
+                "sent-message-high",
+                "sent-message-normal",
+                "sent-message-low"
+            ],
+            Priority::ALL
+                .map(|p| config.priority_queue_name(p))
+                .to_vec()
+        );
+    }
 }
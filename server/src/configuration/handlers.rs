@@ -1,27 +1,41 @@
-use actix_web::web::{Data, Json, Path};
+use actix_web::web::{Data, Json, Path, Query};
 use actix_web::{HttpResponse, Responder};
 use log::debug;
-use validator::Validate;
+use url::Url;
+use validator::{Validate, ValidationError, ValidationErrors};
 
-use crate::configuration::domain::{Application, Endpoint, TopicsList};
+use crate::configuration::domain::{Application, Endpoint, EndpointAuth, TopicsList};
 use crate::configuration::models::{
     CreateAppRequest, CreateAppResponse, CreateEndpointRequest, CreateEndpointResponse,
+    EndpointStatsQuery, EndpointStatsResponse, UpdateEndpointRequest,
 };
+use crate::egress::EgressPolicy;
 use crate::error::ResponseError;
 use crate::storage::Storage;
+use crate::time::Clock;
 use crate::types::{ApplicationId, EndpointId};
 
 pub async fn create_application_handler(
     storage: Data<Storage>,
+    egress: Data<EgressPolicy>,
     request: Json<CreateAppRequest>,
 ) -> Result<impl Responder, ResponseError> {
     if let Err(err) = request.validate() {
         return Err(ResponseError::ValidationError(err));
     }
 
-    let app = Application::new(request.name.to_string());
+    if let Some(proxy) = &request.proxy {
+        check_destination(&egress, "url", &proxy.url)?;
+    }
+
+    let app = Application::new(request.name.to_string(), request.delivery_format())
+        .with_proxy(request.proxy())
+        .with_retention(request.retention())
+        .with_topic_ttls(request.topic_ttls())
+        .with_topic_priorities(request.topic_priorities())
+        .with_max_concurrency(request.max_concurrency);
 
-    storage.applications.save(app.clone()).await;
+    storage.applications.save(app.clone()).await?;
 
     debug!("Application created: {:?}", app,);
 
@@ -30,6 +44,7 @@ pub async fn create_application_handler(
 
 pub async fn create_endpoint_handler(
     storage: Data<Storage>,
+    egress: Data<EgressPolicy>,
     request: Json<CreateEndpointRequest>,
     path: Path<String>,
 ) -> Result<impl Responder, ResponseError> {
@@ -37,21 +52,105 @@ pub async fn create_endpoint_handler(
         return Err(ResponseError::ValidationError(err));
     }
 
+    check_destination(&egress, "url", &request.url)?;
+    check_auth_destination(&egress, &request.auth())?;
+
     let app_id = ApplicationId::try_from(path.into_inner())?;
     let app = storage.applications.get(&app_id).await?;
 
     let url = request.url.clone();
     let topics: TopicsList = request.topics.clone().into_iter().collect();
 
-    let endpoint = Endpoint::new(&url, app.id, topics);
+    let endpoint = Endpoint::new(&url, app.id, topics)
+        .with_headers(request.headers())
+        .with_auth(request.auth())
+        .with_tls(request.tls())
+        .with_timeouts(request.timeouts());
 
-    storage.endpoints.save(endpoint.clone()).await;
+    storage.endpoints.save(endpoint.clone()).await?;
 
     debug!("Endpoint created: {:?}", endpoint,);
 
     Ok(HttpResponse::Created().json(CreateEndpointResponse::from(endpoint)))
 }
 
+pub async fn update_endpoint_handler(
+    storage: Data<Storage>,
+    egress: Data<EgressPolicy>,
+    request: Json<UpdateEndpointRequest>,
+    path: Path<(String, String)>,
+) -> Result<impl Responder, ResponseError> {
+    if let Err(err) = request.validate() {
+        return Err(ResponseError::ValidationError(err));
+    }
+
+    if let Some(url) = &request.url {
+        check_destination(&egress, "url", url)?;
+    }
+
+    if let Some(auth) = request.auth() {
+        check_auth_destination(&egress, &auth)?;
+    }
+
+    let mut endpoint = get_endpoint(&storage, path).await?;
+
+    if let Some(url) = &request.url {
+        endpoint.change_url(url);
+    }
+
+    if let Some(topics) = &request.topics {
+        endpoint.change_topics(topics.clone().into_iter().collect());
+    }
+
+    if let Some(headers) = request.headers() {
+        endpoint.change_headers(headers);
+    }
+
+    if let Some(auth) = request.auth() {
+        endpoint.change_auth(auth);
+    }
+
+    if let Some(tls) = request.tls() {
+        endpoint.change_tls(tls);
+    }
+
+    endpoint.change_timeouts(request.timeouts(endpoint.timeouts));
+
+    storage.endpoints.save(endpoint.clone()).await?;
+
+    debug!("Endpoint updated: {:?}", endpoint,);
+
+    Ok(HttpResponse::Ok().json(CreateEndpointResponse::from(endpoint)))
+}
+
+fn check_destination(
+    egress: &EgressPolicy,
+    field: &'static str,
+    url: &str,
+) -> Result<(), ResponseError> {
+    let url = Url::parse(url).unwrap();
+
+    if let Err(err) = egress.check_url(&url) {
+        let mut errors = ValidationErrors::new();
+        errors.add(
+            field,
+            ValidationError::new("blocked_destination").with_message(err.to_string().into()),
+        );
+
+        return Err(ResponseError::ValidationError(errors));
+    }
+
+    Ok(())
+}
+
+fn check_auth_destination(egress: &EgressPolicy, auth: &EndpointAuth) -> Result<(), ResponseError> {
+    if let EndpointAuth::OAuth2ClientCredentials(credentials) = auth {
+        check_destination(egress, "auth", credentials.token_url().as_str())?;
+    }
+
+    Ok(())
+}
+
 pub async fn disable_endpoint_handler(
     storage: Data<Storage>,
     path: Path<(String, String)>,
@@ -66,6 +165,23 @@ pub async fn enable_endpoint_handler(
     handle_status(storage, path, StatusAction::Enable).await
 }
 
+pub async fn endpoint_stats_handler(
+    storage: Data<Storage>,
+    path: Path<(String, String)>,
+    query: Query<EndpointStatsQuery>,
+) -> Result<impl Responder, ResponseError> {
+    let endpoint = get_endpoint(&storage, path).await?;
+
+    let window = query.window()?;
+    let since = window.since(&Clock::chrono());
+    let stats = storage
+        .attempt_log
+        .endpoint_stats(&endpoint.id, since)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(EndpointStatsResponse::new(window, since, &endpoint, stats)))
+}
+
 enum StatusAction {
     Enable,
     Disable,
@@ -76,25 +192,15 @@ async fn handle_status(
     path: Path<(String, String)>,
     action: StatusAction,
 ) -> Result<impl Responder, ResponseError> {
-    let (app_id, endpoint_id) = path.into_inner();
-
-    let app_id = ApplicationId::try_from(app_id)?;
-    let app = storage.applications.get(&app_id).await?;
-
-    let endpoint_id = EndpointId::try_from(endpoint_id)?;
-    let mut endpoint = storage.endpoints.get(&endpoint_id).await?;
-
-    if !endpoint.app_id.eq(&app.id) {
-        // todo get endpoint with one query - app_id + endpoint_id
-        return Err(ResponseError::NotFound("Endpoint not found".to_string()));
-    }
+    let mut endpoint = get_endpoint(&storage, path).await?;
+    let endpoint_id = endpoint.id;
 
     match action {
         StatusAction::Enable => endpoint.enable_manually(),
         StatusAction::Disable => endpoint.disable_manually(),
     }
 
-    storage.endpoints.save(endpoint).await;
+    storage.endpoints.save(endpoint).await?;
 
     match action {
         StatusAction::Enable => debug!("Endpoint {} enabled", endpoint_id),
@@ -103,3 +209,23 @@ async fn handle_status(
 
     Ok(HttpResponse::NoContent())
 }
+
+async fn get_endpoint(
+    storage: &Storage,
+    path: Path<(String, String)>,
+) -> Result<Endpoint, ResponseError> {
+    let (app_id, endpoint_id) = path.into_inner();
+
+    let app_id = ApplicationId::try_from(app_id)?;
+    let app = storage.applications.get(&app_id).await?;
+
+    let endpoint_id = EndpointId::try_from(endpoint_id)?;
+    let endpoint = storage.endpoints.get(&endpoint_id).await?;
+
+    if !endpoint.app_id.eq(&app.id) {
+        // todo get endpoint with one query - app_id + endpoint_id
+        return Err(ResponseError::NotFound("Endpoint not found".to_string()));
+    }
+
+    Ok(endpoint)
+}
@@ -1,7 +1,17 @@
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
 use validator::{Validate, ValidationError};
 
-use crate::configuration::domain::{Application, Endpoint, Topic};
+use crate::configuration::domain::{
+    Application, ClientCredentials, CustomHeader, CustomHeaders, DeliveryFormat, Endpoint,
+    EndpointAuth, OutboundProxy, Retention, Timeouts, TlsConfig, Topic, TopicPriorities, TopicTtls,
+};
+use crate::error::Error;
+use crate::error::Error::InvalidArgument;
+use crate::events::domain::{EndpointStats, Percentiles, StatsWindow};
 
 fn is_not_empty(value: &str) -> Result<(), ValidationError> {
     let value = value.trim();
@@ -26,16 +36,164 @@ fn topic_are_valid(value: &Vec<String>) -> Result<(), ValidationError> {
     Ok(())
 }
 
+fn delivery_format_is_valid(value: &str) -> Result<(), ValidationError> {
+    if DeliveryFormat::try_from(value.to_string()).is_err() {
+        let err = ValidationError::new("invalid_delivery_format")
+            .with_message(format!("'{}' is invalid delivery format", value).into());
+
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+fn proxy_is_valid(value: &ProxyRequest) -> Result<(), ValidationError> {
+    if let Err(Error::InvalidArgument(message)) = to_outbound_proxy(value) {
+        return Err(ValidationError::new("invalid_proxy").with_message(message.into()));
+    }
+
+    Ok(())
+}
+
+fn to_outbound_proxy(value: &ProxyRequest) -> Result<OutboundProxy, Error> {
+    OutboundProxy::new(
+        &value.url,
+        value.username.as_deref(),
+        value.password.as_deref(),
+        value.no_proxy.clone(),
+    )
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProxyRequest {
+    pub url: String,
+    username: Option<String>,
+    #[serde(skip_serializing)]
+    password: Option<String>,
+    #[serde(default)]
+    no_proxy: Vec<String>,
+}
+
+fn topic_ttls_are_valid(value: &BTreeMap<String, u32>) -> Result<(), ValidationError> {
+    if let Err(Error::InvalidArgument(message)) = TopicTtls::new(value.clone()) {
+        return Err(ValidationError::new("invalid_topic_ttls").with_message(message.into()));
+    }
+
+    Ok(())
+}
+
+fn topic_priorities_are_valid(value: &BTreeMap<String, String>) -> Result<(), ValidationError> {
+    if let Err(Error::InvalidArgument(message)) = TopicPriorities::new(value.clone()) {
+        return Err(ValidationError::new("invalid_topic_priorities").with_message(message.into()));
+    }
+
+    Ok(())
+}
+
+fn retention_is_valid(value: &CreateAppRequest) -> Result<(), ValidationError> {
+    if let Err(Error::InvalidArgument(message)) =
+        Retention::new(value.retention_days, value.payload_retention_days)
+    {
+        return Err(ValidationError::new("invalid_retention").with_message(message.into()));
+    }
+
+    Ok(())
+}
+
 #[derive(Deserialize, Validate)]
+#[validate(schema(function = retention_is_valid))]
 pub struct CreateAppRequest {
     #[validate(custom(function = is_not_empty, message = "Name cannot be empty"))]
     pub name: String,
+    #[validate(custom(function = delivery_format_is_valid))]
+    pub delivery_format: Option<String>,
+    #[validate(custom(function = proxy_is_valid))]
+    pub proxy: Option<ProxyRequest>,
+    #[validate(range(
+        min = 1,
+        max = 3650,
+        message = "Retention should be between 1 and 3650 days"
+    ))]
+    pub retention_days: Option<u32>,
+    #[validate(range(
+        min = 1,
+        max = 3650,
+        message = "Payload retention should be between 1 and 3650 days"
+    ))]
+    pub payload_retention_days: Option<u32>,
+    #[validate(custom(function = topic_ttls_are_valid))]
+    pub topic_ttl_secs: Option<BTreeMap<String, u32>>,
+    #[validate(custom(function = topic_priorities_are_valid))]
+    pub topic_priorities: Option<BTreeMap<String, String>>,
+    #[validate(range(
+        min = 1,
+        max = 1000,
+        message = "Max concurrency should be between 1 and 1000"
+    ))]
+    pub max_concurrency: Option<u32>,
+}
+
+impl CreateAppRequest {
+    pub fn delivery_format(&self) -> DeliveryFormat {
+        self.delivery_format
+            .clone()
+            .map(|f| DeliveryFormat::try_from(f).unwrap())
+            .unwrap_or_default()
+    }
+
+    pub fn proxy(&self) -> Option<OutboundProxy> {
+        self.proxy
+            .as_ref()
+            .map(|proxy| to_outbound_proxy(proxy).unwrap())
+    }
+
+    pub fn retention(&self) -> Retention {
+        Retention::new(self.retention_days, self.payload_retention_days).unwrap()
+    }
+
+    pub fn topic_ttls(&self) -> TopicTtls {
+        self.topic_ttl_secs
+            .clone()
+            .map(|secs| TopicTtls::new(secs).unwrap())
+            .unwrap_or_default()
+    }
+
+    pub fn topic_priorities(&self) -> TopicPriorities {
+        self.topic_priorities
+            .clone()
+            .map(|priorities| TopicPriorities::new(priorities).unwrap())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize)]
+pub struct ProxyResponse {
+    url: String,
+    username: Option<String>,
+    no_proxy: Vec<String>,
+}
+
+impl From<OutboundProxy> for ProxyResponse {
+    fn from(value: OutboundProxy) -> Self {
+        Self {
+            url: value.address(),
+            username: value.username(),
+            no_proxy: value.no_proxy(),
+        }
+    }
 }
 
 #[derive(Serialize)]
 pub struct CreateAppResponse {
     id: String,
     name: String,
+    delivery_format: String,
+    proxy: Option<ProxyResponse>,
+    retention_days: Option<u32>,
+    payload_retention_days: Option<u32>,
+    topic_ttl_secs: BTreeMap<String, u32>,
+    topic_priorities: BTreeMap<String, String>,
+    max_concurrency: Option<u32>,
 }
 
 impl From<Application> for CreateAppResponse {
@@ -43,8 +201,104 @@ impl From<Application> for CreateAppResponse {
         Self {
             id: value.id.to_string(),
             name: value.name,
+            delivery_format: value.delivery_format.to_string(),
+            proxy: value.proxy.map(ProxyResponse::from),
+            retention_days: value.retention.days,
+            payload_retention_days: value.retention.payload_days,
+            topic_ttl_secs: value.topic_ttls.secs(),
+            topic_priorities: value.topic_priorities.names(),
+            max_concurrency: value.max_concurrency,
+        }
+    }
+}
+
+fn headers_are_valid(value: &[HeaderRequest]) -> Result<(), ValidationError> {
+    if let Err(Error::InvalidArgument(message)) = to_custom_headers(value) {
+        return Err(ValidationError::new("invalid_headers").with_message(message.into()));
+    }
+
+    Ok(())
+}
+
+fn to_custom_headers(value: &[HeaderRequest]) -> Result<CustomHeaders, Error> {
+    let headers = value
+        .iter()
+        .map(|h| CustomHeader::new(&h.name, &h.value, h.secret))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    CustomHeaders::new(headers)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HeaderRequest {
+    name: String,
+    #[serde(skip_serializing)]
+    value: String,
+    #[serde(default)]
+    secret: bool,
+}
+
+fn auth_is_valid(value: &AuthRequest) -> Result<(), ValidationError> {
+    if let Err(Error::InvalidArgument(message)) = to_endpoint_auth(value) {
+        return Err(ValidationError::new("invalid_auth").with_message(message.into()));
+    }
+
+    Ok(())
+}
+
+fn to_endpoint_auth(value: &AuthRequest) -> Result<EndpointAuth, Error> {
+    match value.auth_type.as_str() {
+        "none" => Ok(EndpointAuth::None),
+        "oauth2_client_credentials" => {
+            let credentials = ClientCredentials::new(
+                value.token_url.as_deref().unwrap_or_default(),
+                value.client_id.as_deref().unwrap_or_default(),
+                value.client_secret.as_deref().unwrap_or_default(),
+                value.scopes.clone(),
+            )?;
+
+            Ok(EndpointAuth::OAuth2ClientCredentials(credentials))
         }
+        other => Err(InvalidArgument(format!("'{}' is invalid auth type", other))),
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuthRequest {
+    #[serde(rename = "type")]
+    auth_type: String,
+    token_url: Option<String>,
+    client_id: Option<String>,
+    #[serde(skip_serializing)]
+    client_secret: Option<String>,
+    #[serde(default)]
+    scopes: Vec<String>,
+}
+
+fn tls_is_valid(value: &TlsRequest) -> Result<(), ValidationError> {
+    if let Err(Error::InvalidArgument(message)) = to_tls_config(value) {
+        return Err(ValidationError::new("invalid_tls").with_message(message.into()));
     }
+
+    Ok(())
+}
+
+fn to_tls_config(value: &TlsRequest) -> Result<TlsConfig, Error> {
+    TlsConfig::new(
+        value.client_certificate.as_deref(),
+        value.client_key.as_deref(),
+        value.ca_certificate.as_deref(),
+    )
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TlsRequest {
+    #[serde(skip_serializing)]
+    client_certificate: Option<String>,
+    #[serde(skip_serializing)]
+    client_key: Option<String>,
+    #[serde(skip_serializing)]
+    ca_certificate: Option<String>,
 }
 
 #[derive(Deserialize, Validate)]
@@ -54,6 +308,246 @@ pub struct CreateEndpointRequest {
     #[validate(length(min = 1, message = "Should be at leas one topic"))]
     #[validate(custom(function = topic_are_valid))]
     pub topics: Vec<String>,
+    #[serde(default)]
+    #[validate(custom(function = headers_are_valid))]
+    pub headers: Vec<HeaderRequest>,
+    #[validate(custom(function = auth_is_valid))]
+    pub auth: Option<AuthRequest>,
+    #[validate(custom(function = tls_is_valid))]
+    pub tls: Option<TlsRequest>,
+    #[validate(range(
+        min = 1,
+        max = 300000,
+        message = "Connect timeout should be between 1 and 300000 ms"
+    ))]
+    pub connect_timeout_ms: Option<u64>,
+    #[validate(range(
+        min = 1,
+        max = 300000,
+        message = "Request timeout should be between 1 and 300000 ms"
+    ))]
+    pub request_timeout_ms: Option<u64>,
+}
+
+impl CreateEndpointRequest {
+    pub fn headers(&self) -> CustomHeaders {
+        to_custom_headers(&self.headers).unwrap()
+    }
+
+    pub fn auth(&self) -> EndpointAuth {
+        self.auth
+            .as_ref()
+            .map(|auth| to_endpoint_auth(auth).unwrap())
+            .unwrap_or_default()
+    }
+
+    pub fn tls(&self) -> TlsConfig {
+        self.tls
+            .as_ref()
+            .map(|tls| to_tls_config(tls).unwrap())
+            .unwrap_or_default()
+    }
+
+    pub fn timeouts(&self) -> Timeouts {
+        Timeouts::new(self.connect_timeout_ms, self.request_timeout_ms)
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateEndpointRequest {
+    #[validate(url(message = "Url should be valid"))]
+    pub url: Option<String>,
+    #[validate(length(min = 1, message = "Should be at leas one topic"))]
+    #[validate(custom(function = topic_are_valid))]
+    pub topics: Option<Vec<String>>,
+    #[validate(custom(function = headers_are_valid))]
+    pub headers: Option<Vec<HeaderRequest>>,
+    #[validate(custom(function = auth_is_valid))]
+    pub auth: Option<AuthRequest>,
+    #[validate(custom(function = tls_is_valid))]
+    pub tls: Option<TlsRequest>,
+    #[validate(range(
+        min = 1,
+        max = 300000,
+        message = "Connect timeout should be between 1 and 300000 ms"
+    ))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub connect_timeout_ms: Option<Option<u64>>,
+    #[validate(range(
+        min = 1,
+        max = 300000,
+        message = "Request timeout should be between 1 and 300000 ms"
+    ))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub request_timeout_ms: Option<Option<u64>>,
+}
+
+impl UpdateEndpointRequest {
+    pub fn headers(&self) -> Option<CustomHeaders> {
+        self.headers
+            .as_ref()
+            .map(|headers| to_custom_headers(headers).unwrap())
+    }
+
+    pub fn auth(&self) -> Option<EndpointAuth> {
+        self.auth
+            .as_ref()
+            .map(|auth| to_endpoint_auth(auth).unwrap())
+    }
+
+    pub fn tls(&self) -> Option<TlsConfig> {
+        self.tls.as_ref().map(|tls| to_tls_config(tls).unwrap())
+    }
+
+    // Missing timeout is left unchanged, while null resets it to the default
+    pub fn timeouts(&self, current: Timeouts) -> Timeouts {
+        Timeouts {
+            connect: self
+                .connect_timeout_ms
+                .map_or(current.connect, |ms| ms.map(Duration::from_millis)),
+            request: self
+                .request_timeout_ms
+                .map_or(current.request, |ms| ms.map(Duration::from_millis)),
+        }
+    }
+}
+
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize)]
+pub struct HeaderResponse {
+    name: String,
+    value: String,
+    secret: bool,
+}
+
+#[derive(Serialize)]
+pub struct AuthResponse {
+    #[serde(rename = "type")]
+    auth_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scopes: Option<Vec<String>>,
+}
+
+impl From<EndpointAuth> for AuthResponse {
+    fn from(value: EndpointAuth) -> Self {
+        let auth_type = value.to_string();
+
+        match value {
+            EndpointAuth::None => Self {
+                auth_type,
+                token_url: None,
+                client_id: None,
+                scopes: None,
+            },
+            EndpointAuth::OAuth2ClientCredentials(credentials) => Self {
+                auth_type,
+                token_url: Some(credentials.token_url().to_string()),
+                client_id: Some(credentials.client_id()),
+                scopes: Some(credentials.scopes()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TlsResponse {
+    client_certificate: bool,
+    ca_certificate: bool,
+}
+
+impl From<TlsConfig> for TlsResponse {
+    fn from(value: TlsConfig) -> Self {
+        Self {
+            client_certificate: value.has_client_certificate(),
+            ca_certificate: value.has_ca_certificate(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EndpointStatsQuery {
+    window: Option<String>,
+}
+
+impl EndpointStatsQuery {
+    pub fn window(&self) -> Result<StatsWindow, Error> {
+        self.window
+            .clone()
+            .map(StatsWindow::try_from)
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+}
+
+#[derive(Serialize)]
+pub struct PercentilesResponse {
+    p50: u128,
+    p95: u128,
+    p99: u128,
+}
+
+impl From<Percentiles> for PercentilesResponse {
+    fn from(value: Percentiles) -> Self {
+        Self {
+            p50: value.p50.as_millis(),
+            p95: value.p95.as_millis(),
+            p99: value.p99.as_millis(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct EndpointStatsResponse {
+    window: String,
+    since: DateTime<Utc>,
+    attempts: u64,
+    success_rate: Option<f64>,
+    status_classes: BTreeMap<String, u64>,
+    response_time_ms: Option<PercentilesResponse>,
+    avg_processing_time_ms: Option<u128>,
+    last_success_at: Option<DateTime<Utc>>,
+    last_failure_at: Option<DateTime<Utc>>,
+    circuit_state: String,
+}
+
+impl EndpointStatsResponse {
+    pub fn new(
+        window: StatsWindow,
+        since: DateTime<Utc>,
+        endpoint: &Endpoint,
+        stats: EndpointStats,
+    ) -> Self {
+        // An endpoint disabled for failing has tripped its circuit
+        let circuit_state = if endpoint.is_failing() {
+            "open"
+        } else {
+            "closed"
+        };
+
+        Self {
+            window: window.to_string(),
+            since,
+            attempts: stats.attempts(),
+            success_rate: stats.success_rate(),
+            response_time_ms: stats.response_time.map(PercentilesResponse::from),
+            avg_processing_time_ms: stats.avg_processing_time.map(|t| t.as_millis()),
+            last_success_at: stats.last_success_at,
+            last_failure_at: stats.last_failure_at,
+            status_classes: stats.status_classes,
+            circuit_state: circuit_state.to_string(),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -62,15 +556,39 @@ pub struct CreateEndpointResponse {
     app_id: String,
     url: String,
     topics: Vec<String>,
+    headers: Vec<HeaderResponse>,
+    auth: AuthResponse,
+    tls: TlsResponse,
+    connect_timeout_ms: Option<u128>,
+    request_timeout_ms: Option<u128>,
 }
 
 impl From<Endpoint> for CreateEndpointResponse {
     fn from(value: Endpoint) -> Self {
+        let headers = value
+            .headers
+            .iter()
+            .map(|h| HeaderResponse {
+                name: h.name(),
+                value: if h.is_secret() {
+                    "********".to_string()
+                } else {
+                    h.value()
+                },
+                secret: h.is_secret(),
+            })
+            .collect();
+
         Self {
             id: value.id.to_string(),
             app_id: value.app_id.to_string(),
             url: value.url.to_string(),
             topics: value.topics.into(),
+            headers,
+            auth: AuthResponse::from(value.auth),
+            tls: TlsResponse::from(value.tls),
+            connect_timeout_ms: value.timeouts.connect.map(|t| t.as_millis()),
+            request_timeout_ms: value.timeouts.request.map(|t| t.as_millis()),
         }
     }
 }
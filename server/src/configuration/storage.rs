@@ -1,63 +1,176 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde_json::json;
-use sqlx::{query, query_as, PgPool};
+use sqlx::{query, query_as, query_scalar, PgPool};
 
 use crate::configuration::domain::{Application, Endpoint, Topic};
+use crate::crypto::Cipher;
 use crate::error::Error;
+use crate::telemetry::WithDbSpan;
 use crate::types::{ApplicationId, EndpointId};
 
+#[async_trait]
+pub trait ApplicationRepository: Send + Sync {
+    async fn save(&self, app: Application) -> Result<(), Error>;
+
+    async fn get(&self, app_id: &ApplicationId) -> Result<Application, Error>;
+}
+
+#[async_trait]
+pub trait EndpointRepository: Send + Sync {
+    async fn save(&self, endpoint: Endpoint) -> Result<(), Error>;
+
+    async fn for_topic(
+        &self,
+        application_id: &ApplicationId,
+        topic: &Topic,
+    ) -> Result<Vec<Endpoint>, Error>;
+
+    async fn get(&self, endpoint_id: &EndpointId) -> Result<Endpoint, Error>;
+}
+
+#[async_trait]
+pub trait InFlightRepository: Send + Sync {
+    // No lease is acquired while the application already has `max` deliveries in flight
+    async fn acquire(
+        &self,
+        app_id: &ApplicationId,
+        max: Option<usize>,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<i64>, Error>;
+
+    async fn release(&self, lease: i64) -> Result<(), Error>;
+}
+
 pub struct ApplicationStorage {
     pool: PgPool,
+    cipher: Cipher,
 }
 
 impl ApplicationStorage {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, cipher: Cipher) -> Self {
+        Self { pool, cipher }
     }
+}
+
+#[async_trait]
+impl ApplicationRepository for ApplicationStorage {
+    async fn save(&self, app: Application) -> Result<(), Error> {
+        let proxy = match app.proxy {
+            Some(proxy) => Some(
+                proxy
+                    .map_secret(|secret| Ok::<_, Error>(self.cipher.encrypt(secret)))?
+                    .as_json(),
+            ),
+            None => None,
+        };
 
-    pub async fn save(&self, app: Application) {
         query(
             r"
-            INSERT INTO applications (id, name)
-            VALUES ($1, $2)
+            INSERT INTO applications (id, name, delivery_format, proxy, retention_days, payload_retention_days, topic_ttls, topic_priorities, max_concurrency)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
         ",
         )
         .bind(app.id)
         .bind(app.name)
+        .bind(app.delivery_format.to_string())
+        .bind(proxy)
+        .bind(app.retention.days.map(|d| d as i32))
+        .bind(app.retention.payload_days.map(|d| d as i32))
+        .bind(app.topic_ttls.as_json())
+        .bind(app.topic_priorities.as_json())
+        .bind(app.max_concurrency.map(|c| c as i32))
         .execute(&self.pool)
-        .await
-        .unwrap();
+        .with_db_span("INSERT applications")
+        .await?;
+
+        Ok(())
     }
 
-    pub async fn get(&self, app_id: &ApplicationId) -> Result<Application, Error> {
-        Ok(query_as::<_, Application>(
+    async fn get(&self, app_id: &ApplicationId) -> Result<Application, Error> {
+        let app = query_as::<_, Application>(
             r"
             SELECT * FROM applications WHERE id = $1
             ",
         )
         .bind(app_id)
         .fetch_one(&self.pool)
-        .await?)
+        .with_db_span("SELECT applications")
+        .await?;
+
+        let proxy = match app.proxy {
+            Some(proxy) => Some(proxy.map_secret(|secret| self.cipher.decrypt(secret))?),
+            None => None,
+        };
+
+        Ok(Application { proxy, ..app })
     }
 }
 
 pub struct EndpointStorage {
     pool: PgPool,
+    cipher: Cipher,
 }
 
 impl EndpointStorage {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, cipher: Cipher) -> Self {
+        Self { pool, cipher }
+    }
+
+    fn decrypt(&self, endpoint: Endpoint) -> Result<Endpoint, Error> {
+        let headers = endpoint.headers.map_values(|h| {
+            if h.is_secret() {
+                return self.cipher.decrypt(&h.value());
+            }
+
+            Ok(h.value())
+        })?;
+        let auth = endpoint
+            .auth
+            .map_secret(|secret| self.cipher.decrypt(secret))?;
+        let tls = endpoint
+            .tls
+            .map_secret(|secret| self.cipher.decrypt(secret))?;
+
+        Ok(endpoint.with_headers(headers).with_auth(auth).with_tls(tls))
     }
+}
+
+#[async_trait]
+impl EndpointRepository for EndpointStorage {
+    async fn save(&self, endpoint: Endpoint) -> Result<(), Error> {
+        let headers = endpoint.headers.map_values(|h| {
+            if h.is_secret() {
+                return Ok::<_, Error>(self.cipher.encrypt(&h.value()));
+            }
+
+            Ok(h.value())
+        })?;
+        let auth = endpoint
+            .auth
+            .map_secret(|secret| Ok::<_, Error>(self.cipher.encrypt(secret)))?;
+        let tls = endpoint
+            .tls
+            .map_secret(|secret| Ok::<_, Error>(self.cipher.encrypt(secret)))?;
 
-    pub async fn save(&self, endpoint: Endpoint) {
         query(
             r"
-        INSERT INTO endpoints (id, app_id, url, topics, status)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO endpoints (id, app_id, url, topics, status, headers, auth, tls, connect_timeout_ms, request_timeout_ms)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
         ON CONFLICT (id) DO UPDATE 
             SET url = EXCLUDED.url,
                 topics = EXCLUDED.topics,
-                status = EXCLUDED.status
+                status = EXCLUDED.status,
+                headers = EXCLUDED.headers,
+                auth = EXCLUDED.auth,
+                tls = EXCLUDED.tls,
+                connect_timeout_ms = EXCLUDED.connect_timeout_ms,
+                request_timeout_ms = EXCLUDED.request_timeout_ms
         ",
         )
         .bind(endpoint.id)
@@ -65,12 +178,23 @@ impl EndpointStorage {
         .bind(endpoint.url.to_string())
         .bind(json!(endpoint.topics.as_strings()))
         .bind(endpoint.status.to_string())
+        .bind(headers.as_json())
+        .bind(auth.as_json())
+        .bind(tls.as_json())
+        .bind(endpoint.timeouts.connect.map(|t| t.as_millis() as i32))
+        .bind(endpoint.timeouts.request.map(|t| t.as_millis() as i32))
         .execute(&self.pool)
-        .await
-        .unwrap();
+        .with_db_span("INSERT endpoints")
+        .await?;
+
+        Ok(())
     }
 
-    pub async fn for_topic(&self, application_id: &ApplicationId, topic: &Topic) -> Vec<Endpoint> {
+    async fn for_topic(
+        &self,
+        application_id: &ApplicationId,
+        topic: &Topic,
+    ) -> Result<Vec<Endpoint>, Error> {
         let endpoints = query_as::<_, Endpoint>(
             r"
             SELECT * FROM endpoints WHERE app_id = $1
@@ -78,23 +202,195 @@ impl EndpointStorage {
         )
         .bind(application_id)
         .fetch_all(&self.pool)
-        .await
-        .expect("Error in query");
+        .with_db_span("SELECT endpoints")
+        .await?;
 
         endpoints
             .into_iter()
             .filter(|e| e.topics.contains(topic))
+            .map(|e| self.decrypt(e))
             .collect() // todo: add it to the query
     }
 
-    pub async fn get(&self, endpoint_id: &EndpointId) -> Result<Endpoint, Error> {
-        Ok(query_as::<_, Endpoint>(
+    async fn get(&self, endpoint_id: &EndpointId) -> Result<Endpoint, Error> {
+        let endpoint = query_as::<_, Endpoint>(
             r"
             SELECT * FROM endpoints WHERE id = $1
         ",
         )
         .bind(endpoint_id)
         .fetch_one(&self.pool)
-        .await?)
+        .with_db_span("SELECT endpoints")
+        .await?;
+
+        self.decrypt(endpoint)
     }
 }
+
+pub struct InFlightStorage {
+    pool: PgPool,
+}
+
+impl InFlightStorage {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl InFlightRepository for InFlightStorage {
+    async fn acquire(
+        &self,
+        app_id: &ApplicationId,
+        max: Option<usize>,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<i64>, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Dispatchers acquiring leases of the same application are serialized, so none of them exceeds `max`
+        query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(app_id)
+            .execute(&mut *tx)
+            .with_db_span("SELECT pg_advisory_xact_lock")
+            .await?;
+
+        query("DELETE FROM deliveries_in_flight WHERE app_id = $1 AND expires_at <= $2")
+            .bind(app_id)
+            .bind(now.naive_utc())
+            .execute(&mut *tx)
+            .with_db_span("DELETE deliveries_in_flight")
+            .await?;
+
+        let lease = query_scalar::<_, i64>(
+            r"
+            INSERT INTO deliveries_in_flight (app_id, expires_at)
+            SELECT $1, $2
+            WHERE $3::BIGINT IS NULL
+               OR (SELECT count(*) FROM deliveries_in_flight WHERE app_id = $1) < $3
+            RETURNING id
+        ",
+        )
+        .bind(app_id)
+        .bind(expires_at.naive_utc())
+        .bind(max.map(|max| max as i64))
+        .fetch_optional(&mut *tx)
+        .with_db_span("INSERT deliveries_in_flight")
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(lease)
+    }
+
+    async fn release(&self, lease: i64) -> Result<(), Error> {
+        query("DELETE FROM deliveries_in_flight WHERE id = $1")
+            .bind(lease)
+            .execute(&self.pool)
+            .with_db_span("DELETE deliveries_in_flight")
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryApplicationStorage {
+    applications: Mutex<HashMap<ApplicationId, Application>>,
+}
+
+#[async_trait]
+impl ApplicationRepository for InMemoryApplicationStorage {
+    async fn save(&self, app: Application) -> Result<(), Error> {
+        self.applications.lock().unwrap().insert(app.id, app);
+
+        Ok(())
+    }
+
+    async fn get(&self, app_id: &ApplicationId) -> Result<Application, Error> {
+        self.applications
+            .lock()
+            .unwrap()
+            .get(app_id)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryEndpointStorage {
+    endpoints: Mutex<HashMap<EndpointId, Endpoint>>,
+}
+
+#[async_trait]
+impl EndpointRepository for InMemoryEndpointStorage {
+    async fn save(&self, endpoint: Endpoint) -> Result<(), Error> {
+        self.endpoints.lock().unwrap().insert(endpoint.id, endpoint);
+
+        Ok(())
+    }
+
+    async fn for_topic(
+        &self,
+        application_id: &ApplicationId,
+        topic: &Topic,
+    ) -> Result<Vec<Endpoint>, Error> {
+        Ok(self
+            .endpoints
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|e| e.app_id == *application_id && e.topics.contains(topic))
+            .cloned()
+            .collect())
+    }
+
+    async fn get(&self, endpoint_id: &EndpointId) -> Result<Endpoint, Error> {
+        self.endpoints
+            .lock()
+            .unwrap()
+            .get(endpoint_id)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryInFlightStorage {
+    leases: Mutex<HashMap<i64, (ApplicationId, DateTime<Utc>)>>,
+    last_lease: AtomicI64,
+}
+
+#[async_trait]
+impl InFlightRepository for InMemoryInFlightStorage {
+    async fn acquire(
+        &self,
+        app_id: &ApplicationId,
+        max: Option<usize>,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<i64>, Error> {
+        let mut leases = self.leases.lock().unwrap();
+        leases.retain(|_, (_, lease_expires_at)| *lease_expires_at > now);
+
+        let in_flight = leases.values().filter(|(app, _)| app == app_id).count();
+        if max.is_some_and(|max| in_flight >= max) {
+            return Ok(None);
+        }
+
+        let lease = self.last_lease.fetch_add(1, Ordering::Relaxed) + 1;
+        leases.insert(lease, (*app_id, expires_at));
+
+        Ok(Some(lease))
+    }
+
+    async fn release(&self, lease: i64) -> Result<(), Error> {
+        self.leases.lock().unwrap().remove(&lease);
+
+        Ok(())
+    }
+}
+
+pub(crate) fn not_found() -> Error {
+    Error::EntityNotFound("Entity not found".to_string())
+}
@@ -0,0 +1,114 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::error::Error;
+use crate::error::Error::{Crypto, InvalidArgument};
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Clone)]
+pub struct Cipher {
+    cipher: Aes256Gcm,
+}
+
+impl Cipher {
+    pub fn new(key: &str) -> Result<Self, Error> {
+        let key = STANDARD
+            .decode(key)
+            .map_err(|_| InvalidArgument("Encryption key should be base64 encoded".to_string()))?;
+
+        if key.len() != KEY_LENGTH {
+            return Err(InvalidArgument(format!(
+                "Encryption key should have {} bytes but has {}",
+                KEY_LENGTH,
+                key.len()
+            )));
+        }
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        })
+    }
+
+    #[must_use]
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .expect("Encryption failed");
+
+        let mut bytes = nonce.to_vec();
+        bytes.extend(ciphertext);
+
+        STANDARD.encode(bytes)
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> Result<String, Error> {
+        let bytes = STANDARD
+            .decode(encrypted)
+            .map_err(|_| Crypto("Encrypted value should be base64 encoded".to_string()))?;
+
+        if bytes.len() < NONCE_LENGTH {
+            return Err(Crypto("Encrypted value is too short".to_string()));
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Crypto("Unable to decrypt value".to_string()))?;
+
+        String::from_utf8(plaintext).map_err(|_| Crypto("Decrypted value is not utf-8".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::Cipher;
+    use crate::error::Error::{Crypto, InvalidArgument};
+
+    const KEY: &str = "jT3R2ujRKfUFHYNggErDQ9SZY2iugvSw/WdKPhO6pkU=";
+    const OTHER_KEY: &str = "7M2rDqkH0WcZf2uXJ9oQ0Jr3m1Qy6uYJ0mGd7Q2l3kA=";
+
+    #[test]
+    fn encrypted_value_can_be_decrypted() {
+        let sut = Cipher::new(KEY).unwrap();
+
+        let encrypted = sut.encrypt("Bearer secret-token");
+
+        assert_ne!("Bearer secret-token", encrypted);
+        assert_eq!(
+            Ok("Bearer secret-token".to_string()),
+            sut.decrypt(&encrypted)
+        );
+    }
+
+    #[test]
+    fn the_same_value_is_encrypted_differently_every_time() {
+        let sut = Cipher::new(KEY).unwrap();
+
+        assert_ne!(sut.encrypt("secret"), sut.encrypt("secret"));
+    }
+
+    #[test]
+    fn value_cannot_be_decrypted_with_other_key() {
+        let encrypted = Cipher::new(KEY).unwrap().encrypt("secret");
+
+        let result = Cipher::new(OTHER_KEY).unwrap().decrypt(&encrypted);
+
+        assert_eq!(Err(Crypto("Unable to decrypt value".to_string())), result);
+    }
+
+    #[test_case::test_case("not base64!", "Encryption key should be base64 encoded"; "not base64")]
+    #[test_case::test_case("c2hvcnQ=", "Encryption key should have 32 bytes but has 5"; "too short")]
+    fn key_should_be_valid(key: &str, error: &str) {
+        assert_eq!(
+            Some(InvalidArgument(error.to_string())),
+            Cipher::new(key).err()
+        );
+    }
+}
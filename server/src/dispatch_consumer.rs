@@ -1,171 +1,801 @@
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
 use std::time::Duration;
 
-use futures_lite::StreamExt;
-use lapin::options::{BasicAckOptions, BasicConsumeOptions};
-use lapin::types::FieldTable;
-use lapin::Channel;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use opentelemetry::trace::{FutureExt, SpanKind};
+use tokio::select;
+use tokio::time::timeout;
 
-use crate::amqp::{Publisher, Serializer};
-use crate::circuit_breaker::{CircuitBreaker, Error};
+use crate::circuit_breaker::{CircuitBreaker, Error, State};
 use crate::cmd::AsyncMessage;
-use crate::config::AMQPConfig;
-use crate::retry::RetryPolicyBuilder;
-use crate::sender::Sender;
+use crate::config::DeliveryConfig;
+use crate::configuration::domain::EndpointAuth;
+use crate::egress::EgressPolicy;
+use crate::error::Error as StorageError;
+use crate::events::domain::{AttemptLog, Message};
+use crate::events::envelope::Envelope;
+use crate::fairness::FairConsumer;
+use crate::http_client::HttpClients;
+use crate::logs::{add_log_field, with_log_fields};
+use crate::metrics;
+use crate::oauth2::TokenProvider;
+use crate::queue::{Queue, QueueDelivery};
+use crate::retry::{retry_transient, Retry, RetryPolicyBuilder};
+use crate::sender::{Sender, SentResult};
 use crate::storage::Storage;
+use crate::telemetry::{end_span, start_span};
 use crate::time::Clock;
 
-pub async fn consume(
-    channel: Channel,
+pub async fn consume<S>(
+    queue: Arc<dyn Queue>,
     consumer_tag: &str,
     storage: Storage,
-    amqp_config: AMQPConfig,
-) {
+    delivery_config: DeliveryConfig,
+    egress: EgressPolicy,
+    shutdown: S,
+) where
+    S: Future<Output = ()>,
+{
     let retry_policy = RetryPolicyBuilder::new()
         .max_retries(5)
         .exponential(2, Duration::from_secs(2))
         .randomize(0.5)
         .build()
         .unwrap();
-
-    let mut circuit_breaker = CircuitBreaker::default();
-
-    let mut consumer = channel
-        .basic_consume(
-            &amqp_config.sent_message_queue_name(),
-            consumer_tag,
-            BasicConsumeOptions::default(),
-            FieldTable::default(),
-        )
-        .await
+    let redelivery_policy = RetryPolicyBuilder::new()
+        .max_retries(5)
+        .exponential(2, Duration::from_secs(1))
+        .build()
+        .unwrap();
+    let storage_retry = RetryPolicyBuilder::new()
+        .max_retries(5)
+        .exponential(2, Duration::from_millis(200))
+        .build()
         .unwrap();
 
-    let publisher = Publisher::new(channel, amqp_config);
+    let mut circuit_breaker = CircuitBreaker::default();
+    let capture_policy = delivery_config.capture_policy();
+    let shutdown_timeout = delivery_config.shutdown_timeout();
+    let mut consumer = FairConsumer::new(
+        queue.consumer(consumer_tag).await,
+        queue.clone(),
+        &delivery_config,
+    );
+    let mut http_clients = HttpClients::new(delivery_config, egress.clone())
+        .expect("Delivery http client cannot be built");
+    let mut token_provider =
+        TokenProvider::new(http_clients.default_client()).with_egress_policy(egress.clone());
+
+    let span_name = format!("{} process", consumer.queue());
     let clock = Clock::chrono();
+    let mut shutdown = pin!(shutdown);
+    let mut stopping = false;
 
     info!("consumer is ready");
 
-    while let Some(delivery) = consumer.next().await {
-        let delivery = delivery.expect("error in consumer");
-        let async_msg: AsyncMessage = Serializer::deserialize(&delivery.data);
+    while !stopping {
+        let delivery = select! {
+            biased;
+            _ = &mut shutdown => break,
+            delivery = consumer.next(&storage) => delivery,
+        };
 
-        let AsyncMessage::SentMessage(cmd) = async_msg;
+        let Some(delivery) = delivery else {
+            break;
+        };
 
-        info!("message consumed: {:?}", cmd);
+        let cx = start_span(span_name.clone(), SpanKind::Consumer, &delivery.parent);
 
-        let msg = storage.messages.get(cmd.msg_id()).await;
-        if msg.is_err() {
-            error!(
-                "Message {} doesn't exist and cannot be dispatched",
-                cmd.msg_id()
-            );
+        let process = async {
+            let async_msg = match AsyncMessage::decode(&delivery.data) {
+                Ok(async_msg) => async_msg,
+                Err(err) => {
+                    error!("Message cannot be decoded and is dead-lettered: {}", err);
 
-            delivery.ack(BasicAckOptions::default()).await.expect("ack");
+                    delivery.reject().await;
 
-            continue;
-        }
+                    return Ok(());
+                }
+            };
 
-        let mut msg = msg.unwrap();
+            let AsyncMessage::SentMessage(cmd) = async_msg;
 
-        let event = storage.events.get(msg.event_id).await;
-        if event.is_err() {
-            error!(
-                "Message {} doesn't exist and cannot be dispatched",
-                msg.event_id
-            );
+            add_log_field("msg_id", cmd.msg_id());
+            add_log_field("attempt", cmd.attempt);
 
-            delivery.ack(BasicAckOptions::default()).await.expect("ack");
+            info!("message consumed");
 
-            continue;
-        }
+            let msg = retry_transient(&storage_retry, || storage.messages.get(cmd.msg_id())).await;
+            if matches!(&msg, Err(StorageError::EntityNotFound(_))) {
+                error!(
+                    "Message {} doesn't exist and cannot be dispatched",
+                    cmd.msg_id()
+                );
 
-        let endpoint_id = msg.endpoint_id;
-        let endpoint = storage.endpoints.get(&endpoint_id).await;
-        if endpoint.is_err() {
-            error!(
-                "Endpoint {} doesn't not exists and message {} cannot be dispatched",
-                endpoint_id, msg.event_id
-            );
+                delivery.ack().await;
 
-            delivery.ack(BasicAckOptions::default()).await.expect("ack");
+                return Ok(());
+            }
 
-            continue;
-        }
+            let mut msg = msg?;
 
-        let event = event.unwrap();
-        let endpoint = endpoint.unwrap();
+            if msg.is_delivered() {
+                info!("Message {} was already delivered and is skipped", msg.id);
 
-        let sender = Sender::new(event.payload.clone(), endpoint.url.clone());
-        let key = endpoint_id.to_string();
+                delivery.ack().await;
 
-        if endpoint.is_active() && circuit_breaker.revive(&key).is_some() {
-            debug!("Endpoint {} has been reopened", key);
-        }
+                return Ok(());
+            }
 
-        let processing_time = event.calculate_processing_time(&clock);
+            add_log_field("event_id", msg.event_id);
+            add_log_field("endpoint_id", msg.endpoint_id);
 
-        debug!(
-            "Message {} for endpoint {} is being prepared to send. Processing time: {:?}",
-            event.id.to_string(),
-            endpoint.id.to_string(),
-            processing_time,
-        );
+            let event = retry_transient(&storage_retry, || storage.events.get(msg.event_id)).await;
+            if matches!(&event, Err(StorageError::EntityNotFound(_))) {
+                error!(
+                    "Message {} doesn't exist and cannot be dispatched",
+                    msg.event_id
+                );
+
+                delivery.ack().await;
 
-        match circuit_breaker.call(&key, || sender.send()).await {
-            Ok(res) => {
-                let log = msg.record_attempt(res, processing_time);
-                storage.messages.save(msg).await;
-                storage.attempt_log.save(log).await;
+                return Ok(());
             }
-            Err(err) => match err {
-                Error::Closed(res) => {
-                    let log = msg.record_attempt(res, processing_time);
-                    storage.messages.save(msg).await;
-                    storage.attempt_log.save(log).await;
 
-                    let mut endpoint = endpoint;
-                    let endpoint_id = endpoint.id;
+            let endpoint_id = msg.endpoint_id;
+            let endpoint =
+                retry_transient(&storage_retry, || storage.endpoints.get(&endpoint_id)).await;
+            if matches!(&endpoint, Err(StorageError::EntityNotFound(_))) {
+                error!(
+                    "Endpoint {} doesn't not exists and message {} cannot be dispatched",
+                    endpoint_id, msg.event_id
+                );
 
-                    endpoint.disable_failing();
-                    storage.endpoints.save(endpoint).await;
+                delivery.ack().await;
+
+                return Ok(());
+            }
 
-                    debug!("Endpoint {} has been disabled", endpoint_id);
+            let event = event?;
+            let endpoint = endpoint?;
+
+            if event.is_cancelled() {
+                info!(
+                    "Event {} was cancelled and message {} is skipped",
+                    event.id, msg.id
+                );
+
+                delivery.ack().await;
+
+                return Ok(());
+            }
+
+            if let Some(expires_at) = event.expires_at.filter(|_| event.is_expired(&clock)) {
+                let result = SentResult::expired(format!("Event expired at {}", expires_at));
+                let status = result.status.clone();
+                let log =
+                    msg.record_attempt(result, event.calculate_processing_time(&clock), &clock);
+                metrics::attempt_recorded(&status, &log);
+                save_attempt(&storage, &storage_retry, msg, log).await?;
+
+                info!(
+                    "Event {} expired at {} and is not dispatched",
+                    event.id, expires_at
+                );
+
+                delivery.ack().await;
+
+                return Ok(());
+            }
+
+            add_log_field("app_id", event.app_id);
+
+            let app =
+                retry_transient(&storage_retry, || storage.applications.get(&event.app_id)).await;
+            if matches!(&app, Err(StorageError::EntityNotFound(_))) {
+                error!(
+                    "Application {} doesn't exist and message {} cannot be dispatched",
+                    event.app_id, msg.id
+                );
+
+                delivery.ack().await;
+
+                return Ok(());
+            }
+
+            let app = app?;
+
+            let proxy = http_clients.proxy_for(&app, &endpoint);
+            let client = match http_clients.get(&endpoint, proxy.as_ref()) {
+                Ok(client) => client,
+                Err(err) => {
+                    error!(
+                        "Http client for endpoint {} cannot be built and message {} cannot be dispatched: {}",
+                        endpoint_id, msg.id, err
+                    );
+
+                    let result = SentResult::misconfigured(format!(
+                        "Http client cannot be built from endpoint configuration: {}",
+                        err
+                    ));
+                    let status = result.status.clone();
+                    let log =
+                        msg.record_attempt(result, event.calculate_processing_time(&clock), &clock);
+                    metrics::attempt_recorded(&status, &log);
+                    save_attempt(&storage, &storage_retry, msg, log).await?;
+
+                    delivery.ack().await;
+
+                    return Ok(());
                 }
-                Error::Open(res) => {
-                    let log = msg.record_attempt(res, processing_time);
-                    storage.messages.save(msg).await;
-                    storage.attempt_log.save(log).await;
-
-                    if retry_policy.is_retryable(cmd.attempt) {
-                        let cmd_to_retry = cmd.with_increased_attempt();
-                        let duration = retry_policy.get_waiting_time(cmd.attempt);
-
-                        publisher
-                            .publish_delayed(
-                                AsyncMessage::SentMessage(cmd_to_retry.clone()),
-                                duration,
-                            )
-                            .await;
+            };
+
+            let envelope = Envelope::new(&app.delivery_format, &event, cmd.attempt);
+            let mut sender = Sender::new(envelope.body(), endpoint.url.clone())
+                .with_client(client)
+                .with_egress_policy(egress.clone())
+                .with_proxy(proxy.is_some())
+                .with_capture_policy(capture_policy.clone())
+                .with_headers(endpoint.headers.to_header_map())
+                .with_headers(envelope.headers());
+
+            if let Some(timeout) = endpoint.timeouts.request {
+                sender = sender.with_timeout(timeout);
+            }
+
+            let key = endpoint_id.to_string();
+
+            if endpoint.is_active() && circuit_breaker.revive(&key).is_some() {
+                metrics::circuit_breaker_changed(State::Open);
+
+                debug!("Endpoint {} has been reopened", key);
+            }
+
+            let processing_time = event.calculate_processing_time(&clock);
+
+            debug!(
+                "Message {} for endpoint {} is being prepared to send. Processing time: {:?}",
+                event.id.to_string(),
+                endpoint.id.to_string(),
+                processing_time,
+            );
 
+            let tokens = &mut token_provider;
+            let auth = &endpoint.auth;
+            let sender = &sender;
+
+            match circuit_breaker
+                .call(&key, move || send(sender, auth, tokens))
+                .await
+            {
+                Ok(res) => {
+                    let status = res.status.clone();
+                    let log = msg
+                        .record_attempt(res, processing_time, &clock)
+                        .with_proxy(proxy.as_ref().map(|p| p.address()));
+                    metrics::attempt_recorded(&status, &log);
+                    save_attempt(&storage, &storage_retry, msg, log).await?;
+                }
+                Err(err) => match err {
+                    Error::Closed(res) => {
+                        let status = res.status.clone();
+                        let log = msg
+                            .record_attempt(res, processing_time, &clock)
+                            .with_proxy(proxy.as_ref().map(|p| p.address()));
+                        metrics::attempt_recorded(&status, &log);
+                        save_attempt(&storage, &storage_retry, msg, log).await?;
+
+                        let mut endpoint = endpoint;
+                        let endpoint_id = endpoint.id;
+
+                        endpoint.disable_failing();
+                        retry_transient(&storage_retry, || {
+                            storage.endpoints.save(endpoint.clone())
+                        })
+                        .await?;
+                        metrics::circuit_breaker_changed(State::Closed);
+
+                        debug!("Endpoint {} has been disabled", endpoint_id);
+                    }
+                    Error::Open(res) => {
+                        let status = res.status.clone();
+                        let log = msg
+                            .record_attempt(res, processing_time, &clock)
+                            .with_proxy(proxy.as_ref().map(|p| p.address()));
+                        metrics::attempt_recorded(&status, &log);
+                        save_attempt(&storage, &storage_retry, msg, log).await?;
+
+                        if retry_policy.is_retryable(cmd.attempt) {
+                            let cmd_to_retry = cmd.with_increased_attempt();
+                            let duration = retry_policy.get_waiting_time(cmd.attempt);
+                            let duration = event
+                                .expires_in(&clock)
+                                .map_or(duration, |left| duration.min(left));
+
+                            retry_transient(&storage_retry, || {
+                                queue.publish_delayed(
+                                    AsyncMessage::SentMessage(cmd_to_retry.clone()),
+                                    duration,
+                                )
+                            })
+                            .await?;
+
+                            debug!(
+                                "Message queued again. Attempt: {}. Delay: {:?}",
+                                cmd_to_retry.attempt, duration
+                            );
+                        }
+
+                        // todo add message that wasn't delivered to some storage
+                    }
+                    Error::Rejected => {
                         debug!(
-                            "Message queued again. Attempt: {}. Delay: {:?}",
-                            cmd_to_retry.attempt, duration
+                            "Endpoint {} is closed. Message {} rejected.",
+                            key, msg.event_id
                         );
+
+                        // todo do something with message? add to some "not delivered" bucket?
                     }
+                },
+            }
 
-                    // todo add message that wasn't delivered to some storage
-                }
-                Error::Rejected => {
-                    debug!(
-                        "Endpoint {} is closed. Message {} rejected.",
-                        key, msg.event_id
-                    );
+            delivery.ack().await;
+
+            Ok::<(), StorageError>(())
+        };
+
+        let outcome = {
+            let mut process = pin!(with_log_fields(process.with_context(cx.clone())));
 
-                    // todo do something with message? add to some "not delivered" bucket?
+            select! {
+                result = &mut process => Some(result),
+                _ = &mut shutdown => {
+                    stopping = true;
+
+                    info!("Shutdown requested, waiting for in-flight delivery to finish");
+
+                    timeout(shutdown_timeout, process).await.ok()
                 }
-            },
+            }
+        };
+
+        match outcome {
+            Some(Ok(())) => end_span(&cx, None),
+            Some(Err(err)) => {
+                redeliver(&queue, &redelivery_policy, &delivery, &err).await;
+
+                end_span(&cx, Some(format!("{:?}", err)));
+            }
+            None => {
+                warn!(
+                    "In-flight delivery hasn't finished in {:?} and is requeued",
+                    shutdown_timeout
+                );
+
+                delivery.requeue().await;
+
+                end_span(&cx, Some("Interrupted by shutdown".to_string()));
+            }
+        }
+    }
+
+    info!("Consumer is stopping");
+
+    consumer.cancel().await;
+}
+
+// Transient failures are redelivered with a backoff, others and exhausted ones are dead-lettered
+async fn redeliver(
+    queue: &Arc<dyn Queue>,
+    policy: &Retry,
+    delivery: &QueueDelivery,
+    err: &StorageError,
+) {
+    let Ok(AsyncMessage::SentMessage(cmd)) = AsyncMessage::decode(&delivery.data) else {
+        delivery.reject().await;
+
+        return;
+    };
+
+    if !err.is_transient() || !policy.is_retryable(cmd.redelivery) {
+        error!(
+            "Delivery failed {} times and is dead-lettered: {:?}",
+            cmd.redelivery + 1,
+            err
+        );
+
+        delivery.reject().await;
+
+        return;
+    }
+
+    let delay = policy.get_waiting_time(cmd.redelivery);
+    let redelivered = AsyncMessage::SentMessage(cmd.with_increased_redelivery());
+
+    match queue.publish_delayed(redelivered, delay).await {
+        Ok(()) => {
+            warn!(
+                "Delivery failed and is redelivered in {:?}: {:?}",
+                delay, err
+            );
+
+            delivery.ack().await;
+        }
+        Err(publish_err) => {
+            error!(
+                "Delivery failed and cannot be redelivered, it is requeued: {:?}",
+                publish_err
+            );
+
+            delivery.requeue().await;
         }
+    }
+}
 
-        delivery.ack(BasicAckOptions::default()).await.expect("ack");
+async fn save_attempt(
+    storage: &Storage,
+    retry: &Retry,
+    msg: Message,
+    log: AttemptLog,
+) -> Result<(), StorageError> {
+    retry_transient(retry, || storage.messages.save(msg.clone())).await?;
+    retry_transient(retry, || storage.attempt_log.save(log.clone())).await
+}
+
+async fn send(
+    sender: &Sender,
+    auth: &EndpointAuth,
+    tokens: &mut TokenProvider,
+) -> Result<SentResult, SentResult> {
+    let EndpointAuth::OAuth2ClientCredentials(credentials) = auth else {
+        return sender.send().await;
+    };
+
+    let token = tokens
+        .token(credentials)
+        .await
+        .map_err(SentResult::auth_failed)?;
+    let result = sender
+        .clone()
+        .with_bearer_token(&token)
+        .map_err(SentResult::auth_failed)?
+        .send()
+        .await;
+
+    match result {
+        Err(res) if res.is_unauthorized() => {
+            debug!("Access token has been rejected, refreshing it");
+
+            tokens.invalidate(credentials);
+
+            let token = tokens
+                .token(credentials)
+                .await
+                .map_err(SentResult::auth_failed)?;
+
+            sender
+                .clone()
+                .with_bearer_token(&token)
+                .map_err(SentResult::auth_failed)?
+                .send()
+                .await
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+    use envconfig::Envconfig;
+    use mockito::ServerGuard;
+    use serde_json::json;
+    use tokio::time::{sleep, timeout};
+    use url::Url;
+
+    use crate::cmd::{AsyncMessage, SentMessage};
+    use crate::config::DeliveryConfig;
+    use crate::configuration::domain::{
+        Application, ClientCredentials, DeliveryFormat, Endpoint, EndpointAuth, Topic, TopicsList,
+    };
+    use crate::dispatch_consumer::{consume, send};
+    use crate::egress::EgressPolicy;
+    use crate::events::domain::{Event, Message, Payload};
+    use crate::memory_queue::InMemoryQueue;
+    use crate::oauth2::TokenProvider;
+    use crate::queue::Queue;
+    use crate::sender::{Sender, SentResult, Status};
+    use crate::storage::Storage;
+    use crate::tests::dt;
+    use crate::time::Clock;
+    use crate::types::MessageId;
+
+    #[tokio::test]
+    async fn message_is_dispatched_and_its_attempt_recorded() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = server
+            .mock("POST", "/webhook")
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+        let storage = Storage::in_memory();
+        let queue: Arc<dyn Queue> = Arc::new(InMemoryQueue::new());
+        let message = given_message(&storage, &queue, &server, |event| event).await;
+
+        dispatch(&storage, queue, attempted(&storage, message.id)).await;
+
+        webhook.assert_async().await;
+        let message = storage.messages.get(message.id).await.unwrap();
+        assert_eq!(Status::Numeric(204), message.attempts()[0].status());
+
+        let stats = storage
+            .attempt_log
+            .endpoint_stats(&message.endpoint_id, dt!("2024-01-01T00:00:00Z"))
+            .await
+            .unwrap();
+        assert_eq!(Some(&1), stats.status_classes.get("2xx"));
+    }
+
+    #[tokio::test]
+    async fn expired_event_is_recorded_without_sending() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = server
+            .mock("POST", "/webhook")
+            .expect(0)
+            .create_async()
+            .await;
+        let storage = Storage::in_memory();
+        let queue: Arc<dyn Queue> = Arc::new(InMemoryQueue::new());
+        let message = given_message(&storage, &queue, &server, |mut event| {
+            event.expires_at = Some(dt!("2024-01-01T00:00:00Z"));
+            event
+        })
+        .await;
+
+        dispatch(&storage, queue, attempted(&storage, message.id)).await;
+
+        webhook.assert_async().await;
+        let message = storage.messages.get(message.id).await.unwrap();
+        assert!(matches!(message.attempts()[0].status(), Status::Expired(_)));
+    }
+
+    #[tokio::test]
+    async fn cancelled_event_is_skipped() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = server
+            .mock("POST", "/webhook")
+            .expect(0)
+            .create_async()
+            .await;
+        let storage = Storage::in_memory();
+        let queue: Arc<dyn Queue> = Arc::new(InMemoryQueue::new());
+        let message = given_message(&storage, &queue, &server, |mut event| {
+            event.cancelled_at = Some(dt!("2024-01-01T00:00:00Z"));
+            event
+        })
+        .await;
+
+        dispatch(&storage, queue.clone(), sleep(Duration::from_millis(200))).await;
+
+        webhook.assert_async().await;
+        let message = storage.messages.get(message.id).await.unwrap();
+        assert!(message.attempts().is_empty());
+        assert_eq!(0, queue.depth().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn delivered_message_is_not_sent_again() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = server
+            .mock("POST", "/webhook")
+            .expect(0)
+            .create_async()
+            .await;
+        let storage = Storage::in_memory();
+        let queue: Arc<dyn Queue> = Arc::new(InMemoryQueue::new());
+        let mut message = given_message(&storage, &queue, &server, |event| event).await;
+        message.record_attempt(
+            SentResult {
+                status: Status::Numeric(200),
+                response_time: Duration::from_millis(100),
+                body: None,
+                headers: None,
+            },
+            Duration::from_millis(100),
+            &Clock::chrono(),
+        );
+        storage.messages.save(message.clone()).await.unwrap();
+
+        dispatch(&storage, queue.clone(), sleep(Duration::from_millis(200))).await;
+
+        webhook.assert_async().await;
+        let message = storage.messages.get(message.id).await.unwrap();
+        assert_eq!(1, message.attempts().len());
+        assert_eq!(0, queue.depth().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn token_is_refreshed_once_when_rejected() {
+        let mut server = mockito::Server::new_async().await;
+        let first_token = token_mock(&mut server, "first").await;
+        let second_token = token_mock(&mut server, "second").await;
+        let rejected = server
+            .mock("POST", "/webhook")
+            .match_header("authorization", "Bearer first")
+            .with_status(401)
+            .create_async()
+            .await;
+        let accepted = server
+            .mock("POST", "/webhook")
+            .match_header("authorization", "Bearer second")
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let result = send(
+            &sender(&server),
+            &auth(&server),
+            &mut TokenProvider::default(),
+        )
+        .await;
+
+        first_token.assert_async().await;
+        second_token.assert_async().await;
+        rejected.assert_async().await;
+        accepted.assert_async().await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn token_failure_is_recorded_as_auth_failure() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/token")
+            .with_status(500)
+            .create_async()
+            .await;
+        let webhook = server
+            .mock("POST", "/webhook")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let result = send(
+            &sender(&server),
+            &auth(&server),
+            &mut TokenProvider::default(),
+        )
+        .await;
+
+        webhook.assert_async().await;
+        assert_eq!(
+            Status::AuthFailed("Token endpoint responded with status 500".to_string()),
+            result.err().unwrap().status
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_token_is_recorded_as_auth_failure() {
+        let mut server = mockito::Server::new_async().await;
+        token_mock(&mut server, "invalid\ntoken").await;
+        let webhook = server
+            .mock("POST", "/webhook")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let result = send(
+            &sender(&server),
+            &auth(&server),
+            &mut TokenProvider::default(),
+        )
+        .await;
+
+        webhook.assert_async().await;
+        assert_eq!(
+            Status::AuthFailed("Access token is not valid header value".to_string()),
+            result.err().unwrap().status
+        );
+    }
+
+    async fn token_mock(server: &mut ServerGuard, token: &str) -> mockito::Mock {
+        server
+            .mock("POST", "/token")
+            .with_body(json!({"access_token": token, "expires_in": 3600}).to_string())
+            .with_header("content-type", "application/json")
+            .expect(1)
+            .create_async()
+            .await
+    }
+
+    fn sender(server: &ServerGuard) -> Sender {
+        let url = Url::from_str(&format!("{}/webhook", server.url())).unwrap();
+
+        Sender::new(Payload::from(json!({"foo": "bar"})), url)
+    }
+
+    fn auth(server: &ServerGuard) -> EndpointAuth {
+        let credentials = ClientCredentials::new(
+            &format!("{}/token", server.url()),
+            "client",
+            "secret",
+            vec![],
+        )
+        .unwrap();
+
+        EndpointAuth::OAuth2ClientCredentials(credentials)
+    }
+
+    async fn given_message<F>(
+        storage: &Storage,
+        queue: &Arc<dyn Queue>,
+        server: &ServerGuard,
+        customize: F,
+    ) -> Message
+    where
+        F: FnOnce(Event) -> Event,
+    {
+        let app = Application::new("app".to_string(), DeliveryFormat::default());
+        let endpoint = Endpoint::new(
+            &format!("{}/webhook", server.url()),
+            app.id,
+            TopicsList::from(vec!["contact.created"]),
+        );
+        let event = customize(Event::new(
+            app.id,
+            Payload::from(json!({"foo": "bar"})),
+            Topic::try_from("contact.created").unwrap(),
+            &Clock::chrono(),
+        ));
+        let message = Message::from((event.clone(), endpoint.clone()));
+
+        storage.applications.save(app.clone()).await.unwrap();
+        storage.endpoints.save(endpoint).await.unwrap();
+        storage.events.save(event).await.unwrap();
+        storage.messages.save(message.clone()).await.unwrap();
+
+        let cmd = SentMessage::new(message.id).with_app_id(app.id);
+        queue.publish(AsyncMessage::SentMessage(cmd)).await.unwrap();
+
+        message
+    }
+
+    async fn dispatch(storage: &Storage, queue: Arc<dyn Queue>, until: impl Future<Output = ()>) {
+        let delivery_config = DeliveryConfig::init_from_hashmap(&HashMap::new()).unwrap();
+        let egress = EgressPolicy::new("127.0.0.0/8,localhost").unwrap();
+
+        timeout(
+            Duration::from_secs(5),
+            consume(
+                queue,
+                "test",
+                storage.clone(),
+                delivery_config,
+                egress,
+                until,
+            ),
+        )
+        .await
+        .expect("Message was not dispatched in time");
+    }
+
+    async fn attempted(storage: &Storage, message_id: MessageId) {
+        while storage
+            .messages
+            .get(message_id)
+            .await
+            .map_or(true, |message| message.attempts().is_empty())
+        {
+            sleep(Duration::from_millis(10)).await;
+        }
     }
 }
@@ -0,0 +1,403 @@
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+
+use ipnet::IpNet;
+use lazy_static::lazy_static;
+use log::debug;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect;
+use tokio::net::lookup_host;
+use url::{Host, Url};
+
+use crate::error::Error;
+use crate::error::Error::InvalidArgument;
+
+const MAX_REDIRECTS: usize = 10;
+
+lazy_static! {
+    static ref BLOCKED_NETWORKS: Vec<IpNet> = [
+        "0.0.0.0/8",
+        "10.0.0.0/8",
+        "100.64.0.0/10",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "172.16.0.0/12",
+        "192.0.0.0/24",
+        "192.168.0.0/16",
+        "198.18.0.0/15",
+        "224.0.0.0/4",
+        "240.0.0.0/4",
+        "::/96",
+        "::1/128",
+        "64:ff9b::/96",
+        "2001::/32",
+        "2002::/16",
+        "fc00::/7",
+        "fe80::/10",
+        "ff00::/8",
+    ]
+    .iter()
+    .map(|network| network.parse().unwrap())
+    .collect();
+    static ref BLOCKED_HOSTS: Vec<&'static str> = vec!["localhost", "metadata.google.internal"];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockedDestination(String);
+
+impl BlockedDestination {
+    fn new(destination: &str) -> Self {
+        Self(format!("'{}' is not allowed destination", destination))
+    }
+
+    pub fn find<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a BlockedDestination> {
+        let mut source = Some(err);
+
+        while let Some(err) = source {
+            if let Some(blocked) = err.downcast_ref::<BlockedDestination>() {
+                return Some(blocked);
+            }
+
+            source = err.source();
+        }
+
+        None
+    }
+}
+
+impl Display for BlockedDestination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for BlockedDestination {}
+
+#[derive(Debug, Clone, Default)]
+pub struct EgressPolicy {
+    allowed_networks: Vec<IpNet>,
+    allowed_hosts: Vec<String>,
+}
+
+impl EgressPolicy {
+    pub fn new(allowlist: &str) -> Result<Self, Error> {
+        let mut policy = Self::default();
+
+        for entry in allowlist
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            policy.allow(entry)?;
+        }
+
+        Ok(policy)
+    }
+
+    #[must_use]
+    pub fn with_allowed_host(mut self, url: &Url) -> Self {
+        match url.host() {
+            Some(Host::Domain(domain)) => self.allowed_hosts.push(domain.to_lowercase()),
+            Some(Host::Ipv4(ip)) => self.allowed_networks.push(IpNet::from(IpAddr::V4(ip))),
+            Some(Host::Ipv6(ip)) => self.allowed_networks.push(IpNet::from(IpAddr::V6(ip))),
+            None => {}
+        }
+
+        self
+    }
+
+    fn allow(&mut self, entry: &str) -> Result<(), Error> {
+        if let Ok(network) = entry.parse::<IpNet>() {
+            self.allowed_networks.push(network);
+        } else if let Ok(ip) = entry.parse::<IpAddr>() {
+            self.allowed_networks.push(IpNet::from(ip));
+        } else if Host::parse(entry).is_ok() {
+            self.allowed_hosts.push(entry.to_lowercase());
+        } else {
+            return Err(InvalidArgument(format!(
+                "'{}' is invalid allowlist entry",
+                entry
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_url(&self, url: &Url) -> Result<(), BlockedDestination> {
+        match url.host() {
+            Some(Host::Domain(domain)) => self.check_host(domain),
+            Some(Host::Ipv4(ip)) => self.check_ip(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => self.check_ip(IpAddr::V6(ip)),
+            None => Err(BlockedDestination::new(url.as_str())),
+        }
+    }
+
+    // A proxy resolves the target itself, so its addresses are checked up front
+    pub async fn check_resolved_url(&self, url: &Url) -> Result<(), BlockedDestination> {
+        self.check_url(url)?;
+
+        match url.host() {
+            Some(Host::Domain(domain)) => self.check_addresses(domain).await,
+            _ => Ok(()),
+        }
+    }
+
+    pub fn resolver(&self) -> EgressResolver {
+        EgressResolver {
+            policy: self.clone(),
+        }
+    }
+
+    pub fn redirect_policy(&self) -> redirect::Policy {
+        let policy = self.clone();
+
+        redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+
+            match policy.check_url(attempt.url()) {
+                Ok(_) => attempt.follow(),
+                Err(err) => attempt.error(err),
+            }
+        })
+    }
+
+    fn check_host(&self, host: &str) -> Result<(), BlockedDestination> {
+        let host = host.trim_end_matches('.').to_lowercase();
+
+        if self.is_allowed_host(&host) {
+            return Ok(());
+        }
+
+        let is_blocked = BLOCKED_HOSTS
+            .iter()
+            .any(|blocked| host == *blocked || host.ends_with(&format!(".{}", blocked)));
+
+        if is_blocked {
+            return Err(BlockedDestination::new(&host));
+        }
+
+        Ok(())
+    }
+
+    async fn check_addresses(&self, host: &str) -> Result<(), BlockedDestination> {
+        let host = host.trim_end_matches('.').to_lowercase();
+
+        if self.is_allowed_host(&host) {
+            return Ok(());
+        }
+
+        let Ok(addrs) = lookup_host((host.as_str(), 0)).await else {
+            debug!("Address of {} cannot be resolved", host);
+
+            return Err(BlockedDestination::new(&host));
+        };
+
+        for addr in addrs {
+            if !self.is_allowed_ip(addr.ip()) {
+                debug!("Resolved address {} of {} is blocked", addr.ip(), host);
+
+                return Err(BlockedDestination::new(&host));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_ip(&self, ip: IpAddr) -> Result<(), BlockedDestination> {
+        if self.is_allowed_ip(ip) {
+            return Ok(());
+        }
+
+        Err(BlockedDestination::new(&ip.to_string()))
+    }
+
+    fn is_allowed_host(&self, host: &str) -> bool {
+        self.allowed_hosts.iter().any(|allowed| allowed == host)
+    }
+
+    fn is_allowed_ip(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            IpAddr::V4(_) => ip,
+        };
+
+        if self.allowed_networks.iter().any(|n| n.contains(&ip)) {
+            return true;
+        }
+
+        !BLOCKED_NETWORKS.iter().any(|n| n.contains(&ip))
+    }
+}
+
+pub struct EgressResolver {
+    policy: EgressPolicy,
+}
+
+impl Resolve for EgressResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy.clone();
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = lookup_host((host.as_str(), 0)).await?.collect();
+
+            if policy.is_allowed_host(&host.to_lowercase()) {
+                return Ok(Box::new(addrs.into_iter()) as Addrs);
+            }
+
+            let allowed: Vec<SocketAddr> = addrs
+                .into_iter()
+                .filter(|addr| {
+                    let is_allowed = policy.is_allowed_ip(addr.ip());
+                    if !is_allowed {
+                        debug!("Resolved address {} of {} is blocked", addr.ip(), host);
+                    }
+
+                    is_allowed
+                })
+                .collect();
+
+            if allowed.is_empty() {
+                return Err(BlockedDestination::new(&host).into());
+            }
+
+            Ok(Box::new(allowed.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use reqwest::dns::{Name, Resolve};
+    use test_case::test_case;
+    use url::Url;
+
+    use crate::egress::{BlockedDestination, EgressPolicy};
+    use crate::error::Error::InvalidArgument;
+
+    #[test_case("http://127.0.0.1")]
+    #[test_case("http://127.1.2.3:8080")]
+    #[test_case("http://2130706433")]
+    #[test_case("http://0x7f000001")]
+    #[test_case("http://10.0.0.1")]
+    #[test_case("http://172.16.5.4")]
+    #[test_case("http://192.168.1.1")]
+    #[test_case("http://169.254.169.254/latest/meta-data")]
+    #[test_case("http://100.64.0.1")]
+    #[test_case("http://0.0.0.0")]
+    #[test_case("http://[::1]")]
+    #[test_case("http://[::ffff:127.0.0.1]")]
+    #[test_case("http://[::127.0.0.1]")]
+    #[test_case("http://[::a9fe:a9fe]")]
+    #[test_case("http://[64:ff9b::10.0.0.1]")]
+    #[test_case("http://[64:ff9b::a9fe:a9fe]")]
+    #[test_case("http://[2002:a9fe:a9fe::]")]
+    #[test_case("http://[2002:7f00:1::1]")]
+    #[test_case("http://[2001:0:4136:e378:8000:63bf:5601:5656]")]
+    #[test_case("http://[fd00:ec2::254]")]
+    #[test_case("http://[fe80::1]")]
+    #[test_case("http://localhost:8080")]
+    #[test_case("http://api.localhost")]
+    #[test_case("http://LOCALHOST.")]
+    #[test_case("http://metadata.google.internal")]
+    fn internal_destination_is_blocked(url: &str) {
+        let sut = EgressPolicy::default();
+
+        assert!(sut.check_url(&Url::parse(url).unwrap()).is_err());
+    }
+
+    #[test_case("https://example.com")]
+    #[test_case("http://8.8.8.8")]
+    #[test_case("http://[2001:4860:4860::8888]")]
+    fn public_destination_is_allowed(url: &str) {
+        let sut = EgressPolicy::default();
+
+        assert!(sut.check_url(&Url::parse(url).unwrap()).is_ok());
+    }
+
+    #[test_case("http://127.0.0.1:8080")]
+    #[test_case("http://10.1.2.3")]
+    #[test_case("http://localhost")]
+    #[test_case("http://[::1]")]
+    fn allowlisted_destination_is_allowed(url: &str) {
+        let sut = EgressPolicy::new("127.0.0.0/8, 10.1.0.0/16,localhost,::1").unwrap();
+
+        assert!(sut.check_url(&Url::parse(url).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn invalid_allowlist_entry_is_error() {
+        assert_eq!(
+            Err(InvalidArgument(
+                "'10.0.0.0/33' is invalid allowlist entry".to_string()
+            )),
+            EgressPolicy::new("10.0.0.0/33").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn blocked_destination_has_message() {
+        let sut = EgressPolicy::default();
+
+        assert_eq!(
+            Err(BlockedDestination(
+                "'169.254.169.254' is not allowed destination".to_string()
+            )),
+            sut.check_url(&Url::parse("http://169.254.169.254").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn host_resolving_to_blocked_address_is_blocked() {
+        let sut = EgressPolicy::default();
+
+        assert_eq!(
+            Err(BlockedDestination::new("localhost")),
+            sut.check_addresses("localhost").await
+        );
+    }
+
+    #[tokio::test]
+    async fn host_resolving_to_allowlisted_address_is_allowed() {
+        let sut = EgressPolicy::new("127.0.0.0/8,::1").unwrap();
+
+        assert_eq!(Ok(()), sut.check_addresses("localhost").await);
+    }
+
+    #[tokio::test]
+    async fn unresolvable_host_is_blocked() {
+        let sut = EgressPolicy::default();
+
+        assert_eq!(
+            Err(BlockedDestination::new("webhooks.invalid")),
+            sut.check_resolved_url(&Url::parse("http://webhooks.invalid").unwrap())
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn resolver_rejects_host_resolving_to_blocked_address() {
+        let sut = EgressPolicy::default().resolver();
+
+        let result = sut.resolve(Name::from_str("localhost").unwrap()).await;
+
+        let err = result.err().unwrap();
+        assert_eq!(
+            Some(&BlockedDestination::new("localhost")),
+            BlockedDestination::find(err.as_ref())
+        );
+    }
+
+    #[tokio::test]
+    async fn resolver_returns_allowlisted_addresses() {
+        let sut = EgressPolicy::new("127.0.0.0/8,::1").unwrap().resolver();
+
+        let result = sut.resolve(Name::from_str("localhost").unwrap()).await;
+
+        assert!(result.unwrap().all(|addr| addr.ip().is_loopback()));
+    }
+}
@@ -3,15 +3,44 @@ use std::fmt::{Display, Formatter};
 use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
+use lapin::Error as LapinError;
+use log::error;
 use serde_json::json;
 use sqlx::Error as SqlxError;
 use validator::{ValidationErrors, ValidationErrorsKind};
 
+// Serialization failure, deadlock, too many connections and server shutdown
+const TRANSIENT_SQLSTATES: [&str; 5] = ["40001", "40P01", "53300", "57P01", "57P03"];
+
 #[derive(Debug, PartialEq)]
 pub enum Error {
     InvalidArgument(String),
     EntityNotFound(String),
     Sqlx(String),
+    Connection(String),
+    Crypto(String),
+    Queue(String),
+}
+
+impl Error {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Error::Connection(_) | Error::Queue(_))
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Error::InvalidArgument(val)
+            | Error::EntityNotFound(val)
+            | Error::Sqlx(val)
+            | Error::Connection(val)
+            | Error::Crypto(val)
+            | Error::Queue(val) => val,
+        };
+
+        write!(f, "{msg}")
+    }
 }
 
 #[derive(Debug)]
@@ -84,7 +113,11 @@ impl From<Error> for ResponseError {
         match value {
             Error::EntityNotFound(msg) => ResponseError::NotFound(msg),
             Error::InvalidArgument(msg) => ResponseError::BadRequest(msg),
-            Error::Sqlx(_) => ResponseError::InternalError,
+            err @ (Error::Sqlx(_) | Error::Connection(_) | Error::Crypto(_) | Error::Queue(_)) => {
+                error!("Request failed: {:?}", err);
+
+                ResponseError::InternalError
+            }
         }
     }
 }
@@ -93,7 +126,43 @@ impl From<sqlx::Error> for Error {
     fn from(value: SqlxError) -> Self {
         match value {
             SqlxError::RowNotFound => Self::EntityNotFound("Entity not found".to_string()),
+            SqlxError::Io(_)
+            | SqlxError::Tls(_)
+            | SqlxError::PoolTimedOut
+            | SqlxError::PoolClosed
+            | SqlxError::WorkerCrashed => Self::Connection(value.to_string()),
+            SqlxError::Database(ref err)
+                if err
+                    .code()
+                    .is_some_and(|code| TRANSIENT_SQLSTATES.contains(&code.as_ref())) =>
+            {
+                Self::Connection(value.to_string())
+            }
             _ => Self::Sqlx(value.to_string()),
         }
     }
 }
+
+impl From<LapinError> for Error {
+    fn from(value: LapinError) -> Self {
+        Self::Queue(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Error as SqlxError;
+    use test_case::test_case;
+
+    use crate::error::Error;
+
+    #[test_case(SqlxError::PoolTimedOut, true)]
+    #[test_case(SqlxError::PoolClosed, true)]
+    #[test_case(SqlxError::Io(std::io::ErrorKind::ConnectionReset.into()), true)]
+    #[test_case(SqlxError::RowNotFound, false)]
+    #[test_case(SqlxError::ColumnNotFound("status".to_string()), false)]
+    #[test_case(SqlxError::Protocol("unexpected message".to_string()), false)]
+    fn only_connection_errors_are_transient(error: SqlxError, expected: bool) {
+        assert_eq!(expected, Error::from(error).is_transient());
+    }
+}
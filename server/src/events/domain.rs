@@ -1,22 +1,34 @@
-use std::fmt::{Display, Formatter};
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Formatter};
 use std::time::Duration;
 
 use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Serialize, Serializer};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use sqlx::postgres::PgRow;
 use sqlx::{Error, FromRow, Row};
 
-use crate::configuration::domain::{Endpoint, Topic};
+use crate::configuration::domain::{Endpoint, Priority, Topic};
+use crate::logs::payloads_enabled;
 use crate::sender::{SentResult, Status};
 use crate::time::Clock;
 use crate::types::{ApplicationId, AttemptId, EndpointId, EventId, MessageId};
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Payload {
     body: String,
 }
 
+impl Debug for Payload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if payloads_enabled() {
+            return write!(f, "{}", self.body);
+        }
+
+        write!(f, "[payload of {} bytes]", self.body.len())
+    }
+}
+
 impl From<Value> for Payload {
     fn from(value: Value) -> Self {
         Self {
@@ -42,6 +54,11 @@ impl Display for Payload {
     }
 }
 
+pub type Extensions = Map<String, Value>;
+
+// Delayed messages of the broker cannot wait longer than 2^32-1 ms
+pub const MAX_DELIVERY_DELAY: Duration = Duration::from_millis(u32::MAX as u64);
+
 #[derive(Debug, Clone)]
 pub struct Event {
     pub id: EventId,
@@ -49,6 +66,14 @@ pub struct Event {
     pub payload: Payload,
     pub topic: Topic,
     pub created_at: DateTime<Utc>,
+    pub source: Option<String>,
+    pub idempotency_key: Option<String>,
+    pub extensions: Extensions,
+    pub deliver_at: Option<DateTime<Utc>>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub priority: Priority,
+    pub published_at: Option<DateTime<Utc>>,
 }
 
 impl Event {
@@ -60,9 +85,120 @@ impl Event {
             payload,
             topic,
             created_at: clock.now(),
+            source: None,
+            idempotency_key: None,
+            extensions: Extensions::new(),
+            deliver_at: None,
+            cancelled_at: None,
+            expires_at: None,
+            priority: Priority::default(),
+            published_at: None,
         }
     }
 
+    #[must_use]
+    pub fn with_idempotency_key(mut self, source: String, idempotency_key: String) -> Self {
+        self.source = Some(source);
+        self.idempotency_key = Some(idempotency_key);
+        self
+    }
+
+    #[must_use]
+    pub fn with_extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    // Time reported by the producer cannot be in the future, otherwise processing time would be negative
+    #[must_use]
+    pub fn occurred_at(mut self, time: DateTime<Utc>) -> Self {
+        self.created_at = self.created_at.min(time);
+        self
+    }
+
+    #[must_use]
+    pub fn with_deliver_at(mut self, time: DateTime<Utc>) -> Self {
+        self.deliver_at = Some(time);
+        self
+    }
+
+    #[must_use]
+    pub fn is_scheduled(&self, clock: &Clock) -> bool {
+        self.deliver_at.is_some_and(|time| time > clock.now())
+    }
+
+    #[must_use]
+    pub fn delivery_delay(&self, clock: &Clock) -> Duration {
+        self.deliver_at
+            .and_then(|time| (time - clock.now()).to_std().ok())
+            .unwrap_or_default()
+    }
+
+    pub fn cancel(&mut self, clock: &Clock) -> Result<(), crate::error::Error> {
+        if self.is_cancelled() {
+            return Err(crate::error::Error::InvalidArgument(format!(
+                "Event {} is already cancelled",
+                self.id
+            )));
+        }
+
+        if !self.is_scheduled(clock) {
+            return Err(crate::error::Error::InvalidArgument(format!(
+                "Event {} is not scheduled for later delivery and cannot be cancelled",
+                self.id
+            )));
+        }
+
+        self.cancelled_at = Some(clock.now());
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled_at.is_some()
+    }
+
+    pub fn mark_published(&mut self, clock: &Clock) {
+        self.published_at = Some(clock.now());
+    }
+
+    #[must_use]
+    pub fn is_published(&self) -> bool {
+        self.published_at.is_some()
+    }
+
+    // Scheduled events live from the time they are due, so they cannot expire before being delivered
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        let start = self
+            .deliver_at
+            .map_or(self.created_at, |time| time.max(self.created_at));
+
+        self.expires_at = chrono::Duration::from_std(ttl)
+            .ok()
+            .and_then(|ttl| start.checked_add_signed(ttl));
+        self
+    }
+
+    #[must_use]
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    #[must_use]
+    pub fn is_expired(&self, clock: &Clock) -> bool {
+        self.expires_at.is_some_and(|time| time <= clock.now())
+    }
+
+    #[must_use]
+    pub fn expires_in(&self, clock: &Clock) -> Option<Duration> {
+        self.expires_at
+            .map(|time| (time - clock.now()).to_std().unwrap_or_default())
+    }
+
+    // Scheduled events are measured from the time they were due, not from the time they were created
     #[must_use]
     pub fn calculate_processing_time(&self, clock: &Clock) -> Duration {
         let now = clock.now();
@@ -72,7 +208,12 @@ impl Event {
             );
         }
 
-        let processing_time = now - self.created_at;
+        let start = match self.deliver_at {
+            Some(deliver_at) if deliver_at > self.created_at => deliver_at.min(now),
+            _ => self.created_at,
+        };
+
+        let processing_time = now - start;
 
         processing_time
             .to_std()
@@ -84,14 +225,28 @@ impl FromRow<'_, PgRow> for Event {
     fn from_row(row: &'_ PgRow) -> Result<Self, sqlx::Error> {
         let created_at: NaiveDateTime = row.try_get("created_at")?;
         let topic: String = row.try_get("topic")?;
-        let payload: Value = row.try_get("payload")?;
+        let payload: Option<Value> = row.try_get("payload")?;
+        let extensions: Value = row.try_get("extensions")?;
+        let deliver_at: Option<NaiveDateTime> = row.try_get("deliver_at")?;
+        let cancelled_at: Option<NaiveDateTime> = row.try_get("cancelled_at")?;
+        let expires_at: Option<NaiveDateTime> = row.try_get("expires_at")?;
+        let priority: String = row.try_get("priority")?;
+        let published_at: Option<NaiveDateTime> = row.try_get("published_at")?;
 
         Ok(Event {
             id: row.try_get("id")?,
             app_id: row.try_get("app_id")?,
             created_at: created_at.and_utc(),
             topic: Topic::try_from(topic).unwrap(),
-            payload: Payload::from(payload),
+            payload: Payload::from(payload.unwrap_or_default()),
+            source: row.try_get("source")?,
+            idempotency_key: row.try_get("idempotency_key")?,
+            extensions: extensions.as_object().cloned().unwrap_or_default(),
+            deliver_at: deliver_at.map(|time| time.and_utc()),
+            cancelled_at: cancelled_at.map(|time| time.and_utc()),
+            expires_at: expires_at.map(|time| time.and_utc()),
+            priority: Priority::try_from(priority).unwrap_or_default(),
+            published_at: published_at.map(|time| time.and_utc()),
         })
     }
 }
@@ -124,16 +279,28 @@ impl Message {
         }
     }
 
-    pub fn record_attempt(&mut self, result: SentResult, processing_time: Duration) -> AttemptLog {
+    pub fn record_attempt(
+        &mut self,
+        result: SentResult,
+        processing_time: Duration,
+        clock: &Clock,
+    ) -> AttemptLog {
         let id = self.attempts.push(result.status);
 
         AttemptLog::new(id, processing_time, result.response_time, result.body)
+            .with_response_headers(result.headers)
+            .with_created_at(clock.now())
     }
 
     #[must_use]
     pub fn attempts(&self) -> Vec<Attempt> {
         self.attempts.all()
     }
+
+    #[must_use]
+    pub fn is_delivered(&self) -> bool {
+        self.attempts.all().iter().any(Attempt::is_delivered)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -165,7 +332,12 @@ impl Attempt {
     fn is_delivered(&self) -> bool {
         match self.status {
             Status::Numeric(status) => (200..=299).contains(&status),
-            Status::Unknown(_) => false,
+            Status::Unknown(_)
+            | Status::AuthFailed(_)
+            | Status::Timeout(_)
+            | Status::Blocked(_)
+            | Status::Expired(_)
+            | Status::Misconfigured(_) => false,
         }
     }
 }
@@ -232,11 +404,15 @@ impl From<(MessageId, Vec<Attempt>)> for AttemptCollection {
     }
 }
 
+#[derive(Clone)]
 pub struct AttemptLog {
     id: AttemptId,
     processing_time: Duration,
     response_time: Duration,
     response_body: Option<String>,
+    response_headers: Option<Value>,
+    proxy: Option<String>,
+    created_at: DateTime<Utc>,
 }
 
 impl AttemptLog {
@@ -252,9 +428,30 @@ impl AttemptLog {
             processing_time,
             response_time,
             response_body,
+            response_headers: None,
+            proxy: None,
+            created_at: Clock::chrono().now(),
         }
     }
 
+    #[must_use]
+    pub fn with_response_headers(mut self, response_headers: Option<Value>) -> Self {
+        self.response_headers = response_headers;
+        self
+    }
+
+    #[must_use]
+    pub fn with_proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    #[must_use]
+    pub fn with_created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = created_at;
+        self
+    }
+
     #[must_use]
     pub fn attempt_id(&self) -> u16 {
         self.id.attempt_no()
@@ -276,10 +473,115 @@ impl AttemptLog {
     pub fn response_body(&self) -> Option<String> {
         self.response_body.clone()
     }
+
+    pub fn response_headers(&self) -> Option<Value> {
+        self.response_headers.clone()
+    }
+
+    pub fn proxy(&self) -> Option<String> {
+        self.proxy.clone()
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum StatsWindow {
+    Hour,
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl StatsWindow {
+    pub fn since(&self, clock: &Clock) -> DateTime<Utc> {
+        let duration = match self {
+            StatsWindow::Hour => chrono::Duration::hours(1),
+            StatsWindow::Day => chrono::Duration::days(1),
+            StatsWindow::Week => chrono::Duration::days(7),
+            StatsWindow::Month => chrono::Duration::days(30),
+        };
+
+        clock.now() - duration
+    }
+}
+
+impl Display for StatsWindow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            StatsWindow::Hour => "1h",
+            StatsWindow::Day => "24h",
+            StatsWindow::Week => "7d",
+            StatsWindow::Month => "30d",
+        };
+
+        write!(f, "{str}")
+    }
+}
+
+impl TryFrom<String> for StatsWindow {
+    type Error = crate::error::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "1h" => Ok(StatsWindow::Hour),
+            "24h" => Ok(StatsWindow::Day),
+            "7d" => Ok(StatsWindow::Week),
+            "30d" => Ok(StatsWindow::Month),
+            _ => Err(crate::error::Error::InvalidArgument(format!(
+                "'{}' is invalid stats window",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EndpointStats {
+    pub status_classes: BTreeMap<String, u64>,
+    pub response_time: Option<Percentiles>,
+    pub avg_processing_time: Option<Duration>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+}
+
+impl EndpointStats {
+    pub const SUCCESS_CLASS: &'static str = "2xx";
+
+    pub fn attempts(&self) -> u64 {
+        self.status_classes.values().sum()
+    }
+
+    pub fn success_rate(&self) -> Option<f64> {
+        let attempts = self.attempts();
+        if attempts == 0 {
+            return None;
+        }
+
+        let succeeded = self
+            .status_classes
+            .get(Self::SUCCESS_CLASS)
+            .copied()
+            .unwrap_or_default();
+
+        Some(succeeded as f64 / attempts as f64)
+    }
 }
 
 #[cfg(test)]
 mod message_test {
+    use std::time::Duration;
+
     use chrono::{DateTime, Utc};
     use serde_json::json;
     use test_case::test_case;
@@ -317,6 +619,94 @@ mod message_test {
         assert_eq!(expected_id_ms, processing_time.as_millis());
     }
 
+    #[test_case("2014-11-28T12:00:05Z", "2014-11-28T12:00:05Z"; "time in past")]
+    #[test_case("2014-11-28T12:00:15Z", "2014-11-28T12:00:09Z"; "time in future")]
+    fn occurred_at_cannot_be_after_creation(time: &str, expected: &str) {
+        let sut = MessageObjectMother::with_created_at_str(dt!("2014-11-28T12:00:09Z"))
+            .occurred_at(dt!(time));
+
+        assert_eq!(dt!(expected), sut.created_at);
+    }
+
+    #[test_case("2014-11-28T12:00:30Z", "2014-11-28T12:00:40Z", 10_000; "from scheduled time")]
+    #[test_case("2014-11-28T12:00:30Z", "2014-11-28T12:00:29Z", 0; "dispatched before scheduled time")]
+    #[test_case("2014-11-28T12:00:00Z", "2014-11-28T12:00:10Z", 1000; "scheduled in past")]
+    fn processing_time_of_scheduled_event(deliver_at: &str, now: &str, expected_ms: u128) {
+        let sut = MessageObjectMother::with_created_at_str(dt!("2014-11-28T12:00:09Z"))
+            .with_deliver_at(dt!(deliver_at));
+
+        let processing_time = sut.calculate_processing_time(&Fixed(dt!(now)));
+
+        assert_eq!(expected_ms, processing_time.as_millis());
+    }
+
+    #[test_case("2014-11-28T12:01:09Z", 60; "in future")]
+    #[test_case("2014-11-28T12:00:00Z", 0; "in past")]
+    fn delivery_delay_of_scheduled_event(deliver_at: &str, expected_secs: u64) {
+        let sut = MessageObjectMother::with_created_at_str(dt!("2014-11-28T12:00:09Z"))
+            .with_deliver_at(dt!(deliver_at));
+
+        let delay = sut.delivery_delay(&Fixed(dt!("2014-11-28T12:00:09Z")));
+
+        assert_eq!(Duration::from_secs(expected_secs), delay);
+    }
+
+    #[test]
+    fn scheduled_event_is_cancelled_once() {
+        let now = dt!("2014-11-28T12:00:09Z");
+        let mut sut = MessageObjectMother::with_created_at_str(now)
+            .with_deliver_at(dt!("2014-11-28T13:00:00Z"));
+
+        assert!(sut.cancel(&Fixed(now)).is_ok());
+        assert_eq!(Some(now), sut.cancelled_at);
+        assert!(sut.cancel(&Fixed(now)).is_err());
+    }
+
+    #[test_case("2014-11-28T12:00:38Z", false, 1; "before ttl")]
+    #[test_case("2014-11-28T12:00:39Z", true, 0; "at ttl")]
+    #[test_case("2014-11-28T12:05:00Z", true, 0; "after ttl")]
+    fn event_expires_after_ttl(now: &str, expired: bool, expires_in_secs: u64) {
+        let sut = MessageObjectMother::with_created_at_str(dt!("2014-11-28T12:00:09Z"))
+            .with_ttl(Duration::from_secs(30));
+
+        assert_eq!(expired, sut.is_expired(&Fixed(dt!(now))));
+        assert_eq!(
+            Some(Duration::from_secs(expires_in_secs)),
+            sut.expires_in(&Fixed(dt!(now)))
+        );
+    }
+
+    #[test_case("2014-11-28T12:10:00Z", "2014-11-28T12:10:30Z"; "scheduled later")]
+    #[test_case("2014-11-28T11:00:00Z", "2014-11-28T12:00:39Z"; "scheduled in the past")]
+    fn ttl_of_scheduled_event_starts_when_it_is_due(deliver_at: &str, expires_at: &str) {
+        let sut = MessageObjectMother::with_created_at_str(dt!("2014-11-28T12:00:09Z"))
+            .with_deliver_at(dt!(deliver_at))
+            .with_ttl(Duration::from_secs(30));
+
+        assert_eq!(Some(dt!(expires_at)), sut.expires_at);
+        assert!(!sut.is_expired(&Fixed(dt!("2014-11-28T12:00:38Z"))));
+    }
+
+    #[test]
+    fn event_without_ttl_never_expires() {
+        let sut = MessageObjectMother::with_created_at_str(dt!("2014-11-28T12:00:09Z"));
+
+        assert!(!sut.is_expired(&Fixed(dt!("2099-11-28T12:00:09Z"))));
+        assert_eq!(None, sut.expires_in(&Fixed(dt!("2099-11-28T12:00:09Z"))));
+    }
+
+    #[test]
+    fn due_event_cannot_be_cancelled() {
+        let now = dt!("2014-11-28T12:00:09Z");
+        let mut immediate = MessageObjectMother::with_created_at_str(now);
+        let mut due = MessageObjectMother::with_created_at_str(now)
+            .with_deliver_at(dt!("2014-11-28T12:00:00Z"));
+
+        assert!(immediate.cancel(&Fixed(now)).is_err());
+        assert!(due.cancel(&Fixed(now)).is_err());
+        assert!(!due.is_cancelled());
+    }
+
     struct MessageObjectMother;
 
     impl MessageObjectMother {
@@ -356,6 +746,9 @@ mod attempt_test {
     #[test_case(Status::Numeric(400), false)]
     #[test_case(Status::Numeric(502), false)]
     #[test_case(Status::Unknown("test".to_string()), false)]
+    #[test_case(Status::AuthFailed("test".to_string()), false)]
+    #[test_case(Status::Timeout("test".to_string()), false)]
+    #[test_case(Status::Blocked("test".to_string()), false)]
     fn attempt_is_delivered(status: Status, expected: bool) {
         let attempt_id = AttemptId::new(MessageId::new(), 1).unwrap();
         let sut = Attempt::new(attempt_id, status);
@@ -417,3 +810,70 @@ mod attempt_collection_test {
         assert_eq!(4, iter.next().unwrap().id.attempt_no());
     }
 }
+
+#[cfg(test)]
+mod payload_tests {
+    use serde_json::json;
+
+    use crate::events::domain::Payload;
+
+    #[test]
+    fn payload_contents_are_not_debug_printed() {
+        let payload = Payload::from(json!({"password": "secret"}));
+
+        assert_eq!("[payload of 21 bytes]", format!("{:?}", payload));
+    }
+}
+
+#[cfg(test)]
+mod stats_test {
+    use std::collections::BTreeMap;
+
+    use chrono::{DateTime, Utc};
+    use test_case::test_case;
+
+    use crate::error::Error::InvalidArgument;
+    use crate::events::domain::{EndpointStats, StatsWindow};
+    use crate::tests::dt;
+    use crate::time::Clock;
+
+    #[test_case("1h", "2014-11-28T11:00:00Z")]
+    #[test_case("24h", "2014-11-27T12:00:00Z")]
+    #[test_case("7d", "2014-11-21T12:00:00Z")]
+    #[test_case("30d", "2014-10-29T12:00:00Z")]
+    fn window_starts_before_now(window: &str, expected: &str) {
+        let clock = Clock::fixed(dt!("2014-11-28T12:00:00Z"));
+        let sut = StatsWindow::try_from(window.to_string()).unwrap();
+
+        assert_eq!(dt!(expected), sut.since(&clock));
+        assert_eq!(window, sut.to_string());
+    }
+
+    #[test]
+    fn unknown_window_is_invalid() {
+        assert_eq!(
+            Err(InvalidArgument("'2w' is invalid stats window".to_string())),
+            StatsWindow::try_from("2w".to_string())
+        );
+    }
+
+    #[test]
+    fn success_rate_is_share_of_successful_attempts() {
+        let sut = EndpointStats {
+            status_classes: BTreeMap::from([
+                ("2xx".to_string(), 6),
+                ("5xx".to_string(), 1),
+                ("timeout".to_string(), 1),
+            ]),
+            ..EndpointStats::default()
+        };
+
+        assert_eq!(8, sut.attempts());
+        assert_eq!(Some(0.75), sut.success_rate());
+    }
+
+    #[test]
+    fn success_rate_is_unknown_without_attempts() {
+        assert_eq!(None, EndpointStats::default().success_rate());
+    }
+}
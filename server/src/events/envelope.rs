@@ -0,0 +1,250 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use serde_json::{json, Value};
+
+use crate::configuration::domain::DeliveryFormat;
+use crate::events::domain::{Event, Payload};
+
+const CLOUD_EVENTS_SPEC_VERSION: &str = "1.0";
+
+pub struct Envelope {
+    headers: HeaderMap,
+    body: Payload,
+}
+
+impl Envelope {
+    #[must_use]
+    pub fn new(format: &DeliveryFormat, event: &Event, attempt: usize) -> Self {
+        let mut envelope = Self {
+            headers: HeaderMap::new(),
+            body: event.payload.clone(),
+        };
+
+        envelope.header("webhook-id", event.id.to_string());
+        envelope.header("webhook-topic", event.topic.to_string());
+        envelope.header("webhook-attempt", attempt.to_string());
+        envelope.header(
+            "webhook-timestamp",
+            event.created_at.timestamp().to_string(),
+        );
+
+        match format {
+            DeliveryFormat::Raw => {}
+            DeliveryFormat::Envelope => {
+                envelope.body = Payload::from(json!({
+                    "id": event.id.to_string(),
+                    "type": event.topic.to_string(),
+                    "timestamp": event.created_at.to_rfc3339(),
+                    "data": event.payload,
+                }));
+            }
+            DeliveryFormat::CloudEventsStructured => {
+                let mut body = event.extensions.clone();
+                body.extend(
+                    json!({
+                        "specversion": CLOUD_EVENTS_SPEC_VERSION,
+                        "id": event.id.to_string(),
+                        "source": Self::source(event),
+                        "type": event.topic.to_string(),
+                        "time": event.created_at.to_rfc3339(),
+                        "datacontenttype": "application/json",
+                        "data": event.payload,
+                    })
+                    .as_object()
+                    .cloned()
+                    .unwrap(),
+                );
+
+                envelope.header(CONTENT_TYPE.as_str(), "application/cloudevents+json");
+                envelope.body = Payload::from(Value::Object(body));
+            }
+            DeliveryFormat::CloudEventsBinary => {
+                for (name, value) in &event.extensions {
+                    let value = match value {
+                        Value::String(str) => str.clone(),
+                        other => other.to_string(),
+                    };
+
+                    envelope.header(&format!("ce-{name}"), value);
+                }
+
+                envelope.header("ce-specversion", CLOUD_EVENTS_SPEC_VERSION);
+                envelope.header("ce-id", event.id.to_string());
+                envelope.header("ce-source", Self::source(event));
+                envelope.header("ce-type", event.topic.to_string());
+                envelope.header("ce-time", event.created_at.to_rfc3339());
+            }
+        }
+
+        envelope
+    }
+
+    #[must_use]
+    pub fn headers(&self) -> HeaderMap {
+        self.headers.clone()
+    }
+
+    #[must_use]
+    pub fn body(&self) -> Payload {
+        self.body.clone()
+    }
+
+    fn source(event: &Event) -> String {
+        format!("/application/{}", event.app_id)
+    }
+
+    fn header<V>(&mut self, name: &str, value: V)
+    where
+        V: AsRef<str>,
+    {
+        let name = HeaderName::from_bytes(name.as_bytes());
+        let value = HeaderValue::from_str(value.as_ref());
+
+        if let (Ok(name), Ok(value)) = (name, value) {
+            self.headers.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use serde_json::{json, Value};
+
+    use crate::configuration::domain::{DeliveryFormat, Topic};
+    use crate::events::domain::{Event, Payload};
+    use crate::events::envelope::Envelope;
+    use crate::tests::dt;
+    use crate::time::Clock;
+    use crate::types::ApplicationId;
+
+    #[test]
+    fn metadata_headers_are_added_in_every_format() {
+        let event = event();
+
+        for format in [
+            DeliveryFormat::Raw,
+            DeliveryFormat::Envelope,
+            DeliveryFormat::CloudEventsStructured,
+            DeliveryFormat::CloudEventsBinary,
+        ] {
+            let headers = Envelope::new(&format, &event, 3).headers();
+
+            assert_eq!(event.id.to_string(), headers["webhook-id"]);
+            assert_eq!("contact.created", headers["webhook-topic"]);
+            assert_eq!("3", headers["webhook-attempt"]);
+            assert_eq!("1417176009", headers["webhook-timestamp"]);
+        }
+    }
+
+    #[test]
+    fn raw_format_sends_only_payload() {
+        let sut = Envelope::new(&DeliveryFormat::Raw, &event(), 1);
+
+        assert_eq!(json!({"foo": "bar"}), body(&sut));
+        assert!(sut.headers().get("content-type").is_none());
+    }
+
+    #[test]
+    fn envelope_format_wraps_payload_with_metadata() {
+        let event = event();
+        let sut = Envelope::new(&DeliveryFormat::Envelope, &event, 1);
+
+        assert_eq!(
+            json!({
+                "id": event.id.to_string(),
+                "type": "contact.created",
+                "timestamp": "2014-11-28T12:00:09+00:00",
+                "data": {"foo": "bar"}
+            }),
+            body(&sut)
+        );
+    }
+
+    #[test]
+    fn cloud_events_structured_format_sends_whole_event_in_body() {
+        let event = event();
+        let sut = Envelope::new(&DeliveryFormat::CloudEventsStructured, &event, 1);
+
+        assert_eq!(
+            json!({
+                "specversion": "1.0",
+                "id": event.id.to_string(),
+                "source": format!("/application/{}", event.app_id),
+                "type": "contact.created",
+                "time": "2014-11-28T12:00:09+00:00",
+                "datacontenttype": "application/json",
+                "data": {"foo": "bar"}
+            }),
+            body(&sut)
+        );
+        assert_eq!(
+            "application/cloudevents+json",
+            sut.headers()["content-type"]
+        );
+    }
+
+    #[test]
+    fn cloud_events_binary_format_sends_attributes_in_headers() {
+        let event = event();
+        let sut = Envelope::new(&DeliveryFormat::CloudEventsBinary, &event, 1);
+        let headers = sut.headers();
+
+        assert_eq!(json!({"foo": "bar"}), body(&sut));
+        assert_eq!("1.0", headers["ce-specversion"]);
+        assert_eq!(event.id.to_string(), headers["ce-id"]);
+        assert_eq!(
+            format!("/application/{}", event.app_id),
+            headers["ce-source"]
+        );
+        assert_eq!("contact.created", headers["ce-type"]);
+        assert_eq!("2014-11-28T12:00:09+00:00", headers["ce-time"]);
+    }
+
+    #[test]
+    fn cloud_events_formats_forward_extensions() {
+        let extensions = json!({"traceparent": "00-abc-01", "priority": 5})
+            .as_object()
+            .cloned()
+            .unwrap();
+        let event = event().with_extensions(extensions);
+
+        let structured = Envelope::new(&DeliveryFormat::CloudEventsStructured, &event, 1);
+        let body = body(&structured);
+
+        assert_eq!("00-abc-01", body["traceparent"]);
+        assert_eq!(5, body["priority"]);
+
+        let binary = Envelope::new(&DeliveryFormat::CloudEventsBinary, &event, 1);
+        let headers = binary.headers();
+
+        assert_eq!("00-abc-01", headers["ce-traceparent"]);
+        assert_eq!("5", headers["ce-priority"]);
+    }
+
+    #[test]
+    fn extensions_cannot_override_core_attributes() {
+        let extensions = json!({"id": "overridden"}).as_object().cloned().unwrap();
+        let event = event().with_extensions(extensions);
+
+        let structured = Envelope::new(&DeliveryFormat::CloudEventsStructured, &event, 1);
+        let binary = Envelope::new(&DeliveryFormat::CloudEventsBinary, &event, 1);
+
+        assert_eq!(event.id.to_string(), body(&structured)["id"]);
+        assert_eq!(event.id.to_string(), binary.headers()["ce-id"]);
+    }
+
+    fn event() -> Event {
+        let created_at: DateTime<Utc> = dt!("2014-11-28T12:00:09Z");
+
+        Event::new(
+            ApplicationId::new(),
+            Payload::from(json!({"foo": "bar"})),
+            Topic::new("contact.created").unwrap(),
+            &Clock::fixed(created_at),
+        )
+    }
+
+    fn body(envelope: &Envelope) -> Value {
+        serde_json::to_value(envelope.body()).unwrap()
+    }
+}
@@ -1,43 +1,226 @@
-use actix_web::web::{Data, Json, Path};
-use actix_web::{HttpResponse, Responder, Result};
+use std::time::Duration;
+
+use actix_web::web::{Bytes, Data, Path};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, Responder, Result};
 use log::debug;
+use serde::de::DeserializeOwned;
 
-use crate::amqp::Publisher;
 use crate::cmd::{AsyncMessage, SentMessage};
-use crate::configuration::domain::{Endpoint, Topic};
-use crate::error::ResponseError;
-use crate::events::domain::{Event, Message, Payload};
-use crate::events::models::{CreateEventRequest, CreateEventResponse};
+use crate::configuration::domain::{Application, Endpoint, Priority, Topic};
+use crate::error::{Error, ResponseError};
+use crate::events::domain::{Event, Message, Payload, MAX_DELIVERY_DELAY};
+use crate::events::models::{CloudEventRequest, CreateEventRequest, CreateEventResponse};
+use crate::metrics;
+use crate::queue::Queue;
 use crate::storage::Storage;
 use crate::time::Clock;
-use crate::types::ApplicationId;
+use crate::types::{ApplicationId, EventId};
+
+const CLOUD_EVENTS_CONTENT_TYPE: &str = "application/cloudevents+json";
+const CLOUD_EVENTS_BATCH_CONTENT_TYPE: &str = "application/cloudevents-batch+json";
 
 pub async fn create_event_handler(
     storage: Data<Storage>,
-    dispatcher: Data<Publisher>,
-    request: Json<CreateEventRequest>,
+    dispatcher: Data<dyn Queue>,
+    request: HttpRequest,
+    body: Bytes,
     path: Path<String>,
 ) -> Result<impl Responder, ResponseError> {
     let app_id = ApplicationId::try_from(path.into_inner())?;
     let app = storage.applications.get(&app_id).await?;
-    let topic = Topic::new(request.topic.clone())?;
     let clock = Clock::chrono();
-    let event = Event::new(
-        app.id,
-        Payload::from(request.payload.clone()),
-        topic.clone(),
-        &clock,
-    );
 
-    storage.events.save(event.clone()).await;
+    match request.content_type() {
+        CLOUD_EVENTS_CONTENT_TYPE => {
+            let cloud_event: CloudEventRequest = parse(&body)?;
+            let event = cloud_event.into_event(app.id, &clock)?;
+            let event = with_topic_defaults(event, &app);
+            let event = create_event(&storage, dispatcher.get_ref(), event, &clock).await?;
+
+            Ok(HttpResponse::Ok().json(CreateEventResponse::from(event)))
+        }
+        CLOUD_EVENTS_BATCH_CONTENT_TYPE => {
+            let cloud_events: Vec<CloudEventRequest> = parse(&body)?;
+            let events: Vec<Event> = cloud_events
+                .into_iter()
+                .map(|e| e.into_event(app.id, &clock))
+                .map(|e| e.map(|e| with_topic_defaults(e, &app)))
+                .collect::<Result<_, _>>()?;
+
+            let mut responses = Vec::with_capacity(events.len());
+            for event in events {
+                let event = create_event(&storage, dispatcher.get_ref(), event, &clock).await?;
+
+                responses.push(CreateEventResponse::from(event));
+            }
+
+            Ok(HttpResponse::Ok().json(responses))
+        }
+        _ => {
+            let request: CreateEventRequest = parse(&body)?;
+            let topic = Topic::new(request.topic.clone())?;
+            let mut event = Event::new(app.id, Payload::from(request.payload), topic, &clock);
+
+            if let Some(deliver_at) = request.deliver_at {
+                event = event.with_deliver_at(deliver_at);
+
+                if event.delivery_delay(&clock) > MAX_DELIVERY_DELAY {
+                    return Err(ResponseError::BadRequest(format!(
+                        "Event cannot be scheduled more than {} days ahead",
+                        MAX_DELIVERY_DELAY.as_secs() / 86400
+                    )));
+                }
+            }
+
+            event = with_topic_defaults(event, &app);
+
+            if let Some(priority) = request.priority {
+                event = event.with_priority(Priority::try_from(priority)?);
+            }
+
+            event = match request.ttl_secs {
+                Some(0) => {
+                    return Err(ResponseError::BadRequest(
+                        "TTL should be greater than 0".to_string(),
+                    ))
+                }
+                Some(secs) => event.with_ttl(Duration::from_secs(u64::from(secs))),
+                None => event,
+            };
+
+            let event = create_event(&storage, dispatcher.get_ref(), event, &clock).await?;
+
+            Ok(HttpResponse::Ok().json(CreateEventResponse::from(event)))
+        }
+    }
+}
+
+pub async fn cancel_event_handler(
+    storage: Data<Storage>,
+    path: Path<(String, String)>,
+) -> Result<impl Responder, ResponseError> {
+    let (app_id, event_id) = path.into_inner();
+
+    let app_id = ApplicationId::try_from(app_id)?;
+    let app = storage.applications.get(&app_id).await?;
+
+    let event_id = EventId::try_from(event_id)?;
+    let mut event = storage.events.get(event_id).await?;
+
+    if !event.app_id.eq(&app.id) {
+        return Err(ResponseError::NotFound("Event not found".to_string()));
+    }
+
+    event.cancel(&Clock::chrono())?;
+    storage.events.cancel(&event).await?;
+
+    debug!("Event {} cancelled", event.id);
+
+    Ok(HttpResponse::NoContent())
+}
+
+fn with_topic_defaults(mut event: Event, app: &Application) -> Event {
+    if let Some(ttl) = app.topic_ttls.for_topic(&event.topic) {
+        event = event.with_ttl(ttl);
+    }
+
+    if let Some(priority) = app.topic_priorities.for_topic(&event.topic) {
+        event = event.with_priority(priority);
+    }
+
+    event
+}
+
+fn parse<T>(body: &Bytes) -> Result<T, ResponseError>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_slice(body).map_err(|e| ResponseError::BadRequest(e.to_string()))
+}
+
+async fn create_event(
+    storage: &Storage,
+    dispatcher: &dyn Queue,
+    event: Event,
+    clock: &Clock,
+) -> Result<Event, Error> {
+    let stored = storage.events.save(event.clone()).await?;
+    if stored.id != event.id {
+        debug!(
+            "Event with idempotency key {:?} already exists: {}",
+            event.idempotency_key, stored.id
+        );
+
+        if stored.is_published() {
+            return Ok(stored);
+        }
+
+        // The request which stored the event failed before all its messages were published
+        let messages = storage.messages.for_event(stored.id).await?;
+
+        return publish(storage, dispatcher, stored, messages, clock).await;
+    }
+
+    metrics::event_ingested(&event);
+
+    debug!(app_id:% = event.app_id, event_id:% = event.id; "Event created: {:?}", event);
+
+    publish(storage, dispatcher, event, Vec::new(), clock).await
+}
+
+// Messages which were already attempted are not published again
+async fn publish(
+    storage: &Storage,
+    dispatcher: &dyn Queue,
+    mut event: Event,
+    messages: Vec<Message>,
+    clock: &Clock,
+) -> Result<Event, Error> {
+    let fanned_out = fan_out(storage, &event, &messages).await?;
+    let delay = event.delivery_delay(clock);
+
+    for msg in messages
+        .into_iter()
+        .filter(|msg| msg.attempts().is_empty())
+        .chain(fanned_out)
+    {
+        let cmd = SentMessage::new(msg.id)
+            .with_priority(event.priority)
+            .with_app_id(event.app_id);
+        let message = AsyncMessage::SentMessage(cmd);
+
+        if delay.is_zero() {
+            dispatcher.publish(message).await?;
+        } else {
+            dispatcher.publish_delayed(message, delay).await?;
+        }
+        metrics::message_fanned_out(&event);
+
+        debug!("Message {} published on the queue", msg.id);
+    }
 
-    debug!("Message created: {:?}", event,);
+    event.mark_published(clock);
+    storage.events.mark_published(&event).await?;
 
-    let endpoints: Vec<Endpoint> = storage.endpoints.for_topic(&app_id, &event.topic).await;
+    Ok(event)
+}
+
+async fn fan_out(
+    storage: &Storage,
+    event: &Event,
+    existing: &[Message],
+) -> Result<Vec<Message>, Error> {
+    let endpoints: Vec<Endpoint> = storage
+        .endpoints
+        .for_topic(&event.app_id, &event.topic)
+        .await?;
     let endpoints_count = endpoints.len();
 
-    let active_endpoints: Vec<Endpoint> =
-        endpoints.into_iter().filter(Endpoint::is_active).collect();
+    let active_endpoints: Vec<Endpoint> = endpoints
+        .into_iter()
+        .filter(Endpoint::is_active)
+        .filter(|endpoint| existing.iter().all(|msg| msg.endpoint_id != endpoint.id))
+        .collect();
 
     debug!(
         "in app {} - {} ({}) endpoints found for event {}",
@@ -47,20 +230,145 @@ pub async fn create_event_handler(
         event.id
     );
 
+    let mut messages = Vec::with_capacity(active_endpoints.len());
+
     for endpoint in active_endpoints {
         debug!("{} sending to {}", event.id, endpoint.url);
 
-        let msg = Message::from((event.clone(), endpoint.clone()));
+        let msg = Message::from((event.clone(), endpoint));
 
-        storage.messages.save(msg.clone()).await;
+        storage.messages.save(msg.clone()).await?;
+        messages.push(msg);
+    }
 
-        let cmd = SentMessage::new(msg.id);
-        let message = AsyncMessage::SentMessage(cmd);
+    Ok(messages)
+}
 
-        dispatcher.publish(message).await;
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
 
-        debug!("Message {} published on the queue", msg.id);
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    use crate::cmd::AsyncMessage;
+    use crate::configuration::domain::{Application, DeliveryFormat, Endpoint, Topic, TopicsList};
+    use crate::error::Error;
+    use crate::events::domain::{Event, Payload};
+    use crate::events::handlers::{create_event, fan_out};
+    use crate::memory_queue::InMemoryQueue;
+    use crate::queue::{Queue, QueueConsumer};
+    use crate::storage::Storage;
+    use crate::time::Clock;
+
+    #[tokio::test]
+    async fn event_is_fanned_out_to_active_endpoints_of_topic() {
+        let storage = Storage::in_memory();
+        let app = Application::new("app".to_string(), DeliveryFormat::default());
+        let topic = Topic::try_from("contact.created").unwrap();
+        let active = endpoint(&app, "contact.created");
+        let mut disabled = endpoint(&app, "contact.created");
+        disabled.disable_manually();
+        let other_topic = endpoint(&app, "contact.deleted");
+
+        for endpoint in [active.clone(), disabled, other_topic] {
+            storage.endpoints.save(endpoint).await.unwrap();
+        }
+
+        let event = Event::new(
+            app.id,
+            Payload::from(json!({"foo": "bar"})),
+            topic,
+            &Clock::chrono(),
+        );
+
+        let messages = fan_out(&storage, &event, &[]).await.unwrap();
+
+        assert_eq!(1, messages.len());
+        assert_eq!(active.id, messages[0].endpoint_id);
+
+        let saved = storage.messages.get(messages[0].id).await.unwrap();
+        assert_eq!(event.id, saved.event_id);
+        assert_eq!(active.id, saved.endpoint_id);
+    }
+
+    #[tokio::test]
+    async fn retried_event_is_published_when_publishing_failed() {
+        let storage = Storage::in_memory();
+        let app = Application::new("app".to_string(), DeliveryFormat::default());
+        storage
+            .endpoints
+            .save(endpoint(&app, "contact.created"))
+            .await
+            .unwrap();
+        let event = || {
+            Event::new(
+                app.id,
+                Payload::from(json!({"foo": "bar"})),
+                Topic::try_from("contact.created").unwrap(),
+                &Clock::chrono(),
+            )
+            .with_idempotency_key("/crm".to_string(), "key".to_string())
+        };
+        let queue = InMemoryQueue::new();
+
+        let failed = create_event(&storage, &UnavailableQueue, event(), &Clock::chrono()).await;
+        let retried = create_event(&storage, &queue, event(), &Clock::chrono())
+            .await
+            .unwrap();
+        create_event(&storage, &queue, event(), &Clock::chrono())
+            .await
+            .unwrap();
+
+        assert!(failed.is_err());
+        assert!(storage.events.get(retried.id).await.unwrap().is_published());
+        assert_eq!(
+            1,
+            storage.messages.for_event(retried.id).await.unwrap().len()
+        );
+        assert_eq!(1, queue.depth().await.unwrap());
     }
 
-    Ok(HttpResponse::Ok().json(CreateEventResponse::from(event)))
+    fn endpoint(app: &Application, topic: &'static str) -> Endpoint {
+        Endpoint::new(
+            "https://example.com/webhook",
+            app.id,
+            TopicsList::from(vec![topic]),
+        )
+    }
+
+    struct UnavailableQueue;
+
+    #[async_trait]
+    impl Queue for UnavailableQueue {
+        fn kind(&self) -> &'static str {
+            "unavailable"
+        }
+
+        async fn publish(&self, _message: AsyncMessage) -> Result<(), Error> {
+            Err(Error::Queue("Queue is unavailable".to_string()))
+        }
+
+        async fn publish_delayed(
+            &self,
+            _message: AsyncMessage,
+            _delay: Duration,
+        ) -> Result<(), Error> {
+            Err(Error::Queue("Queue is unavailable".to_string()))
+        }
+
+        async fn consumer(&self, _consumer_tag: &str) -> Box<dyn QueueConsumer> {
+            unimplemented!()
+        }
+
+        async fn ping(&self) -> Result<(), String> {
+            Err("Queue is unavailable".to_string())
+        }
+
+        async fn depth(&self) -> Result<u64, Error> {
+            Ok(0)
+        }
+
+        async fn close(&self) {}
+    }
 }
@@ -1,4 +1,5 @@
 pub mod domain;
+pub mod envelope;
 pub mod handlers;
 mod models;
 pub mod storage;
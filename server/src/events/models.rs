@@ -1,12 +1,84 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::events::domain::Event;
+use crate::configuration::domain::Topic;
+use crate::error::Error;
+use crate::error::Error::InvalidArgument;
+use crate::events::domain::{Event, Extensions, Payload};
+use crate::time::Clock;
+use crate::types::ApplicationId;
 
 #[derive(Deserialize)]
 pub struct CreateEventRequest {
     pub payload: Value,
     pub topic: String,
+    pub deliver_at: Option<DateTime<Utc>>,
+    pub ttl_secs: Option<u32>,
+    pub priority: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CloudEventRequest {
+    specversion: String,
+    id: String,
+    source: String,
+    #[serde(rename = "type")]
+    event_type: String,
+    time: Option<DateTime<Utc>>,
+    datacontenttype: Option<String>,
+    data: Option<Value>,
+    data_base64: Option<String>,
+    // Core attributes that are not mapped to the event. Everything else is an extension.
+    #[allow(dead_code)]
+    subject: Option<String>,
+    #[allow(dead_code)]
+    dataschema: Option<String>,
+    #[serde(flatten)]
+    extensions: Extensions,
+}
+
+impl CloudEventRequest {
+    const SPEC_VERSION: &'static str = "1.0";
+
+    pub fn into_event(self, app_id: ApplicationId, clock: &Clock) -> Result<Event, Error> {
+        if self.specversion != Self::SPEC_VERSION {
+            return Err(InvalidArgument(format!(
+                "Unsupported CloudEvents specversion '{}'",
+                self.specversion
+            )));
+        }
+
+        if self.id.trim().is_empty() || self.source.trim().is_empty() {
+            return Err(InvalidArgument(
+                "CloudEvents 'id' and 'source' attributes cannot be empty".to_string(),
+            ));
+        }
+
+        let is_json = match &self.datacontenttype {
+            Some(content_type) => content_type.contains("json"),
+            None => true,
+        };
+
+        if self.data_base64.is_some() || !is_json {
+            return Err(InvalidArgument(
+                "Only JSON data is supported in CloudEvents".to_string(),
+            ));
+        }
+
+        let topic = Topic::new(self.event_type)?;
+        let payload = Payload::from(self.data.unwrap_or(Value::Null));
+
+        let mut event = Event::new(app_id, payload, topic, clock)
+            .with_idempotency_key(self.source, self.id)
+            .with_extensions(self.extensions);
+
+        if let Some(time) = self.time {
+            event = event.occurred_at(time);
+        }
+
+        Ok(event)
+    }
 }
 
 #[derive(Serialize)]
@@ -21,3 +93,101 @@ impl From<Event> for CreateEventResponse {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use serde_json::{json, Value};
+
+    use crate::configuration::domain::Topic;
+    use crate::error::Error::InvalidArgument;
+    use crate::events::models::CloudEventRequest;
+    use crate::tests::dt;
+    use crate::time::Clock;
+    use crate::types::ApplicationId;
+
+    #[test]
+    fn cloud_event_is_mapped_to_event() {
+        let now: DateTime<Utc> = dt!("2024-11-12T10:00:00Z");
+        let request = cloud_event(json!({
+            "specversion": "1.0",
+            "id": "A234-1234-1234",
+            "source": "/mycontext",
+            "type": "contact.created",
+            "time": "2024-11-12T09:59:00Z",
+            "datacontenttype": "application/json",
+            "subject": "123",
+            "comexampleextension": "value",
+            "traceparent": "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "data": {"foo": "bar"}
+        }));
+
+        let event = request
+            .into_event(ApplicationId::new(), &Clock::fixed(now))
+            .unwrap();
+
+        assert_eq!(Topic::new("contact.created").unwrap(), event.topic);
+        assert_eq!(
+            json!({"foo": "bar"}),
+            serde_json::to_value(event.payload).unwrap()
+        );
+        assert_eq!(Some("A234-1234-1234".to_string()), event.idempotency_key);
+        assert_eq!(Some("/mycontext".to_string()), event.source);
+        assert_eq!(dt!("2024-11-12T09:59:00Z"), event.created_at);
+        assert_eq!(
+            json!({
+                "comexampleextension": "value",
+                "traceparent": "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+            }),
+            Value::Object(event.extensions)
+        );
+    }
+
+    #[test]
+    fn cloud_event_without_time_is_created_now() {
+        let now: DateTime<Utc> = dt!("2024-11-12T10:00:00Z");
+        let request = cloud_event(json!({
+            "specversion": "1.0",
+            "id": "1",
+            "source": "/mycontext",
+            "type": "contact.created"
+        }));
+
+        let event = request
+            .into_event(ApplicationId::new(), &Clock::fixed(now))
+            .unwrap();
+
+        assert_eq!(now, event.created_at);
+        assert_eq!(Value::Null, serde_json::to_value(event.payload).unwrap());
+    }
+
+    #[test_case::test_case(
+        json!({"specversion": "0.3", "id": "1", "source": "/s", "type": "contact.created"}),
+        "Unsupported CloudEvents specversion '0.3'"; "unsupported spec version"
+    )]
+    #[test_case::test_case(
+        json!({"specversion": "1.0", "id": "", "source": "/s", "type": "contact.created"}),
+        "CloudEvents 'id' and 'source' attributes cannot be empty"; "empty id"
+    )]
+    #[test_case::test_case(
+        json!({"specversion": "1.0", "id": "1", "source": "/s", "type": "contact created"}),
+        "Invalid topic name"; "invalid type"
+    )]
+    #[test_case::test_case(
+        json!({"specversion": "1.0", "id": "1", "source": "/s", "type": "contact.created", "data_base64": "Zm9v"}),
+        "Only JSON data is supported in CloudEvents"; "binary data"
+    )]
+    #[test_case::test_case(
+        json!({"specversion": "1.0", "id": "1", "source": "/s", "type": "contact.created", "datacontenttype": "text/xml", "data": "<foo/>"}),
+        "Only JSON data is supported in CloudEvents"; "xml data"
+    )]
+    fn invalid_cloud_event(value: Value, error: &str) {
+        let result = cloud_event(value).into_event(ApplicationId::new(), &Clock::chrono());
+
+        assert_eq!(InvalidArgument(error.to_string()), result.err().unwrap());
+    }
+
+    fn cloud_event(value: Value) -> CloudEventRequest {
+        serde_json::from_value(value).unwrap()
+    }
+}
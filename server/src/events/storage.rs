@@ -1,10 +1,59 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde_json::json;
-use sqlx::{query, query_as, FromRow, PgPool, Row};
+use sqlx::{query, query_as, query_scalar, FromRow, PgPool, Row};
 
+use crate::configuration::storage::not_found;
 use crate::error::Error;
-use crate::events::domain::{Attempt, AttemptCollection, AttemptLog, Event, Message};
+use crate::events::domain::{
+    Attempt, AttemptCollection, AttemptLog, EndpointStats, Event, Message, Percentiles,
+};
 use crate::sender::Status;
-use crate::types::{EndpointId, EventId, MessageId};
+use crate::telemetry::WithDbSpan;
+use crate::types::{ApplicationId, EndpointId, EventId, MessageId};
+
+#[async_trait]
+pub trait EventRepository: Send + Sync {
+    // Returns the event stored earlier when its source and idempotency key are already taken
+    async fn save(&self, event: Event) -> Result<Event, Error>;
+
+    async fn get_by_idempotency_key(
+        &self,
+        app_id: &ApplicationId,
+        source: &str,
+        idempotency_key: &str,
+    ) -> Result<Event, Error>;
+
+    async fn get(&self, event_id: EventId) -> Result<Event, Error>;
+
+    async fn cancel(&self, event: &Event) -> Result<(), Error>;
+
+    async fn mark_published(&self, event: &Event) -> Result<(), Error>;
+}
+
+#[async_trait]
+pub trait MessageRepository: Send + Sync {
+    async fn save(&self, message: Message) -> Result<(), Error>;
+
+    async fn get(&self, message_id: MessageId) -> Result<Message, Error>;
+
+    async fn for_event(&self, event_id: EventId) -> Result<Vec<Message>, Error>;
+}
+
+#[async_trait]
+pub trait AttemptLogRepository: Send + Sync {
+    async fn save(&self, attempt_log: AttemptLog) -> Result<(), Error>;
+
+    async fn endpoint_stats(
+        &self,
+        endpoint_id: &EndpointId,
+        since: DateTime<Utc>,
+    ) -> Result<EndpointStats, Error>;
+}
 
 pub struct EventStorage {
     pool: PgPool,
@@ -15,12 +64,17 @@ impl EventStorage {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+}
 
-    pub async fn save(&self, event: Event) {
-        query(
+#[async_trait]
+impl EventRepository for EventStorage {
+    async fn save(&self, event: Event) -> Result<Event, Error> {
+        let inserted = query(
             r"
-            INSERT INTO events (id, app_id, payload, topic, created_at)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO events (id, app_id, payload, topic, created_at, idempotency_key, extensions, deliver_at, expires_at, priority, source)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (app_id, source, idempotency_key) DO NOTHING
+            RETURNING id
         ",
         )
         .bind(event.id)
@@ -28,12 +82,49 @@ impl EventStorage {
         .bind(json!(event.payload))
         .bind(event.topic.to_string())
         .bind(event.created_at.naive_utc())
-        .execute(&self.pool)
-        .await
-        .unwrap();
+        .bind(event.idempotency_key.clone())
+        .bind(json!(event.extensions))
+        .bind(event.deliver_at.map(|time| time.naive_utc()))
+        .bind(event.expires_at.map(|time| time.naive_utc()))
+        .bind(event.priority.to_string())
+        .bind(event.source.clone())
+        .fetch_optional(&self.pool)
+        .with_db_span("INSERT events")
+        .await?;
+
+        if inserted.is_some() {
+            return Ok(event);
+        }
+
+        match (&event.source, &event.idempotency_key) {
+            (Some(source), Some(key)) => {
+                self.get_by_idempotency_key(&event.app_id, source, key)
+                    .await
+            }
+            _ => Err(Error::Sqlx(format!("Event {} cannot be saved", event.id))),
+        }
+    }
+
+    async fn get_by_idempotency_key(
+        &self,
+        app_id: &ApplicationId,
+        source: &str,
+        idempotency_key: &str,
+    ) -> Result<Event, Error> {
+        Ok(query_as::<_, Event>(
+            r"
+            SELECT * FROM events WHERE app_id = $1 AND source = $2 AND idempotency_key = $3
+        ",
+        )
+        .bind(app_id)
+        .bind(source)
+        .bind(idempotency_key)
+        .fetch_one(&self.pool)
+        .with_db_span("SELECT events")
+        .await?)
     }
 
-    pub async fn get(&self, event_id: EventId) -> Result<Event, Error> {
+    async fn get(&self, event_id: EventId) -> Result<Event, Error> {
         Ok(query_as::<_, Event>(
             r"
             SELECT * FROM events WHERE id = $1
@@ -41,8 +132,39 @@ impl EventStorage {
         )
         .bind(event_id)
         .fetch_one(&self.pool)
+        .with_db_span("SELECT events")
         .await?)
     }
+
+    async fn cancel(&self, event: &Event) -> Result<(), Error> {
+        query(
+            r"
+            UPDATE events SET cancelled_at = $2 WHERE id = $1 AND cancelled_at IS NULL
+        ",
+        )
+        .bind(event.id)
+        .bind(event.cancelled_at.map(|time| time.naive_utc()))
+        .execute(&self.pool)
+        .with_db_span("UPDATE events")
+        .await?;
+
+        Ok(())
+    }
+
+    async fn mark_published(&self, event: &Event) -> Result<(), Error> {
+        query(
+            r"
+            UPDATE events SET published_at = $2 WHERE id = $1
+        ",
+        )
+        .bind(event.id)
+        .bind(event.published_at.map(|time| time.naive_utc()))
+        .execute(&self.pool)
+        .with_db_span("UPDATE events")
+        .await?;
+
+        Ok(())
+    }
 }
 
 pub struct MessageStorage {
@@ -53,9 +175,12 @@ impl MessageStorage {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+}
 
-    pub async fn save(&self, message: Message) {
-        let mut tx = self.pool.begin().await.unwrap();
+#[async_trait]
+impl MessageRepository for MessageStorage {
+    async fn save(&self, message: Message) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
 
         query(
             r"
@@ -68,15 +193,15 @@ impl MessageStorage {
         .bind(message.event_id)
         .bind(message.endpoint_id)
         .execute(&mut *tx)
-        .await
-        .unwrap();
+        .with_db_span("INSERT messages")
+        .await?;
 
         // todo optimize
         for attempt in message.attempts() {
             query(
                 r"
-            INSERT INTO attempts (message_id, attempt, status_numeric, status_unknown)
-            VALUES ($1, $2, $3, $4)
+            INSERT INTO attempts (message_id, attempt, status_numeric, status_unknown, status_kind)
+            VALUES ($1, $2, $3, $4, $5)
             ON CONFLICT DO NOTHING
         ",
             )
@@ -84,21 +209,34 @@ impl MessageStorage {
             .bind(attempt.attempt_id() as i16)
             .bind(match attempt.status() {
                 Status::Numeric(val) => Some(val as i16),
-                Status::Unknown(_) => None,
+                Status::Unknown(_)
+                | Status::AuthFailed(_)
+                | Status::Timeout(_)
+                | Status::Blocked(_)
+                | Status::Expired(_)
+                | Status::Misconfigured(_) => None,
             })
             .bind(match attempt.status() {
                 Status::Numeric(_) => None,
-                Status::Unknown(val) => Some(val),
+                Status::Unknown(val)
+                | Status::AuthFailed(val)
+                | Status::Timeout(val)
+                | Status::Blocked(val)
+                | Status::Expired(val)
+                | Status::Misconfigured(val) => Some(val),
             })
+            .bind(attempt.status().kind())
             .execute(&mut *tx)
-            .await
-            .unwrap();
+            .with_db_span("INSERT attempts")
+            .await?;
         }
 
-        tx.commit().await.unwrap();
+        tx.commit().await?;
+
+        Ok(())
     }
 
-    pub async fn get(&self, message_id: MessageId) -> Result<Message, Error> {
+    async fn get(&self, message_id: MessageId) -> Result<Message, Error> {
         let row = query(
             r"
             SELECT * FROM messages WHERE id = $1
@@ -106,6 +244,7 @@ impl MessageStorage {
         )
         .bind(message_id)
         .fetch_one(&self.pool)
+        .with_db_span("SELECT messages")
         .await?;
 
         let event_id: EventId = row.try_get("event_id")?;
@@ -118,13 +257,13 @@ impl MessageStorage {
         )
         .bind(message_id)
         .fetch_all(&self.pool)
-        .await
-        .unwrap_or_default();
+        .with_db_span("SELECT attempts")
+        .await?;
 
         let attempts: Vec<Attempt> = attempt_rows
             .iter()
-            .map(|p| Attempt::from_row(p).unwrap())
-            .collect();
+            .map(Attempt::from_row)
+            .collect::<Result<_, _>>()?;
         let collection = AttemptCollection::from((message_id, attempts));
 
         Ok(Message {
@@ -134,6 +273,25 @@ impl MessageStorage {
             attempts: collection,
         })
     }
+
+    async fn for_event(&self, event_id: EventId) -> Result<Vec<Message>, Error> {
+        let ids: Vec<MessageId> = query_scalar(
+            r"
+            SELECT id FROM messages WHERE event_id = $1
+        ",
+        )
+        .bind(event_id)
+        .fetch_all(&self.pool)
+        .with_db_span("SELECT messages")
+        .await?;
+
+        let mut messages = Vec::with_capacity(ids.len());
+        for id in ids {
+            messages.push(self.get(id).await?);
+        }
+
+        Ok(messages)
+    }
 }
 
 pub struct AttemptLogStorage {
@@ -144,15 +302,18 @@ impl AttemptLogStorage {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+}
 
-    pub async fn save(&self, attempt_log: AttemptLog) {
+#[async_trait]
+impl AttemptLogRepository for AttemptLogStorage {
+    async fn save(&self, attempt_log: AttemptLog) -> Result<(), Error> {
         let processing_time = attempt_log.processing_time();
         let response_time = attempt_log.response_time();
 
         query(
             r"
-            INSERT INTO attempt_logs (message_id, attempt, processing_time, response_time, response_body)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO attempt_logs (message_id, attempt, processing_time, response_time, response_body, response_headers, proxy, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         ",
         )
             .bind(attempt_log.message_id())
@@ -160,8 +321,508 @@ impl AttemptLogStorage {
             .bind(processing_time.as_millis() as i64)
             .bind(response_time.as_millis() as i64)
             .bind(attempt_log.response_body())
+            .bind(attempt_log.response_headers())
+            .bind(attempt_log.proxy())
+            .bind(attempt_log.created_at().naive_utc())
             .execute(&self.pool)
+            .with_db_span("INSERT attempt_logs")
+            .await?;
+
+        Ok(())
+    }
+
+    async fn endpoint_stats(
+        &self,
+        endpoint_id: &EndpointId,
+        since: DateTime<Utc>,
+    ) -> Result<EndpointStats, Error> {
+        let classes = query_as::<_, (String, i64, NaiveDateTime)>(
+            r"
+            SELECT CASE
+                       WHEN a.status_kind IS NOT NULL THEN a.status_kind
+                       WHEN a.status_numeric IS NOT NULL THEN (a.status_numeric / 100)::TEXT || 'xx'
+                       ELSE 'unknown'
+                       END            AS status_class,
+                   COUNT(*)           AS attempts,
+                   MAX(l.created_at)  AS last_attempt_at
+            FROM attempt_logs l
+                     JOIN messages m ON m.id = l.message_id
+                     JOIN attempts a ON a.message_id = l.message_id AND a.attempt = l.attempt
+            WHERE m.endpoint_id = $1
+              AND l.created_at >= $2
+            GROUP BY status_class
+        ",
+        )
+        .bind(endpoint_id)
+        .bind(since.naive_utc())
+        .fetch_all(&self.pool)
+        .with_db_span("SELECT attempt_logs")
+        .await?;
+
+        let (p50, p95, p99, avg_processing_time) =
+            query_as::<_, (Option<f64>, Option<f64>, Option<f64>, Option<f64>)>(
+                r"
+            SELECT PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY l.response_time) FILTER (WHERE a.status_numeric IS NOT NULL),
+                   PERCENTILE_CONT(0.95) WITHIN GROUP (ORDER BY l.response_time) FILTER (WHERE a.status_numeric IS NOT NULL),
+                   PERCENTILE_CONT(0.99) WITHIN GROUP (ORDER BY l.response_time) FILTER (WHERE a.status_numeric IS NOT NULL),
+                   AVG(l.processing_time)::FLOAT8
+            FROM attempt_logs l
+                     JOIN messages m ON m.id = l.message_id
+                     JOIN attempts a ON a.message_id = l.message_id AND a.attempt = l.attempt
+            WHERE m.endpoint_id = $1
+              AND l.created_at >= $2
+        ",
+            )
+            .bind(endpoint_id)
+            .bind(since.naive_utc())
+            .fetch_one(&self.pool)
+            .with_db_span("SELECT attempt_logs")
+            .await?;
+
+        Ok(collect_stats(
+            classes
+                .into_iter()
+                .map(|(class, attempts, last)| (class, attempts as u64, last.and_utc())),
+            p50.zip(p95)
+                .zip(p99)
+                .map(|((p50, p95), p99)| (p50, p95, p99)),
+            avg_processing_time,
+        ))
+    }
+}
+
+// Response and processing times are in milliseconds, as they are stored
+fn collect_stats(
+    classes: impl IntoIterator<Item = (String, u64, DateTime<Utc>)>,
+    response_time: Option<(f64, f64, f64)>,
+    avg_processing_time: Option<f64>,
+) -> EndpointStats {
+    let mut stats = EndpointStats::default();
+
+    for (class, attempts, last_attempt_at) in classes {
+        let last = if class == EndpointStats::SUCCESS_CLASS {
+            &mut stats.last_success_at
+        } else {
+            &mut stats.last_failure_at
+        };
+        *last = (*last).max(Some(last_attempt_at));
+
+        stats.status_classes.insert(class, attempts);
+    }
+
+    stats.response_time = response_time.map(|(p50, p95, p99)| Percentiles {
+        p50: Duration::from_secs_f64(p50 / 1000.0),
+        p95: Duration::from_secs_f64(p95 / 1000.0),
+        p99: Duration::from_secs_f64(p99 / 1000.0),
+    });
+    stats.avg_processing_time = avg_processing_time.map(|ms| Duration::from_secs_f64(ms / 1000.0));
+
+    stats
+}
+
+// Linear interpolation between the closest ranks, as PERCENTILE_CONT does
+fn percentile(sorted: &[f64], fraction: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = fraction * last as f64;
+    let (lower, upper) = (sorted[rank.floor() as usize], sorted[rank.ceil() as usize]);
+
+    Some(lower + (upper - lower) * rank.fract())
+}
+
+#[derive(Default)]
+pub struct InMemoryEventStorage {
+    events: Mutex<HashMap<EventId, Event>>,
+}
+
+#[async_trait]
+impl EventRepository for InMemoryEventStorage {
+    async fn save(&self, event: Event) -> Result<Event, Error> {
+        let mut events = self.events.lock().unwrap();
+
+        let duplicate = events.values().find(|e| {
+            e.app_id == event.app_id
+                && e.source.is_some()
+                && e.source == event.source
+                && e.idempotency_key.is_some()
+                && e.idempotency_key == event.idempotency_key
+        });
+
+        if let Some(duplicate) = duplicate {
+            return Ok(duplicate.clone());
+        }
+
+        events.insert(event.id, event.clone());
+
+        Ok(event)
+    }
+
+    async fn get_by_idempotency_key(
+        &self,
+        app_id: &ApplicationId,
+        source: &str,
+        idempotency_key: &str,
+    ) -> Result<Event, Error> {
+        self.events
+            .lock()
+            .unwrap()
+            .values()
+            .find(|e| {
+                e.app_id == *app_id
+                    && e.source.as_deref() == Some(source)
+                    && e.idempotency_key.as_deref() == Some(idempotency_key)
+            })
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn get(&self, event_id: EventId) -> Result<Event, Error> {
+        self.events
+            .lock()
+            .unwrap()
+            .get(&event_id)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn cancel(&self, event: &Event) -> Result<(), Error> {
+        let mut events = self.events.lock().unwrap();
+        let stored = events.get_mut(&event.id).ok_or_else(not_found)?;
+        stored.cancelled_at = stored.cancelled_at.or(event.cancelled_at);
+
+        Ok(())
+    }
+
+    async fn mark_published(&self, event: &Event) -> Result<(), Error> {
+        let mut events = self.events.lock().unwrap();
+        let stored = events.get_mut(&event.id).ok_or_else(not_found)?;
+        stored.published_at = event.published_at;
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryMessageStorage {
+    messages: Arc<Mutex<HashMap<MessageId, Message>>>,
+}
+
+impl InMemoryMessageStorage {
+    #[must_use]
+    pub fn attempt_logs(&self) -> InMemoryAttemptLogStorage {
+        InMemoryAttemptLogStorage {
+            attempt_logs: Mutex::default(),
+            messages: self.messages.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl MessageRepository for InMemoryMessageStorage {
+    async fn save(&self, message: Message) -> Result<(), Error> {
+        self.messages.lock().unwrap().insert(message.id, message);
+
+        Ok(())
+    }
+
+    async fn get(&self, message_id: MessageId) -> Result<Message, Error> {
+        self.messages
+            .lock()
+            .unwrap()
+            .get(&message_id)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn for_event(&self, event_id: EventId) -> Result<Vec<Message>, Error> {
+        Ok(self
+            .messages
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|message| message.event_id == event_id)
+            .cloned()
+            .collect())
+    }
+}
+
+// Shares messages with the message storage it was created by, to link logs to endpoints
+pub struct InMemoryAttemptLogStorage {
+    attempt_logs: Mutex<Vec<AttemptLog>>,
+    messages: Arc<Mutex<HashMap<MessageId, Message>>>,
+}
+
+#[async_trait]
+impl AttemptLogRepository for InMemoryAttemptLogStorage {
+    async fn save(&self, attempt_log: AttemptLog) -> Result<(), Error> {
+        self.attempt_logs.lock().unwrap().push(attempt_log);
+
+        Ok(())
+    }
+
+    async fn endpoint_stats(
+        &self,
+        endpoint_id: &EndpointId,
+        since: DateTime<Utc>,
+    ) -> Result<EndpointStats, Error> {
+        let messages = self.messages.lock().unwrap();
+        let attempt_logs = self.attempt_logs.lock().unwrap();
+
+        let mut classes: HashMap<String, (u64, DateTime<Utc>)> = HashMap::new();
+        let mut response_times = Vec::new();
+        let mut processing_times = Vec::new();
+
+        for log in attempt_logs.iter().filter(|log| log.created_at() >= since) {
+            let Some(message) = messages
+                .get(&log.message_id())
+                .filter(|message| message.endpoint_id == *endpoint_id)
+            else {
+                continue;
+            };
+
+            processing_times.push(log.processing_time().as_secs_f64() * 1000.0);
+
+            let Some(attempt) = message
+                .attempts()
+                .into_iter()
+                .find(|attempt| attempt.attempt_id() == log.attempt_id())
+            else {
+                continue;
+            };
+
+            // Attempts without an HTTP response have no response time to measure
+            if let Status::Numeric(_) = attempt.status() {
+                response_times.push(log.response_time().as_secs_f64() * 1000.0);
+            }
+
+            let (attempts, last) = classes
+                .entry(attempt.status().class())
+                .or_insert((0, log.created_at()));
+            *attempts += 1;
+            *last = (*last).max(log.created_at());
+        }
+
+        response_times.sort_by(f64::total_cmp);
+        let response_time = percentile(&response_times, 0.5)
+            .zip(percentile(&response_times, 0.95))
+            .zip(percentile(&response_times, 0.99))
+            .map(|((p50, p95), p99)| (p50, p95, p99));
+        let avg_processing_time = (!processing_times.is_empty())
+            .then(|| processing_times.iter().sum::<f64>() / processing_times.len() as f64);
+
+        Ok(collect_stats(
+            classes
+                .into_iter()
+                .map(|(class, (attempts, last))| (class, attempts, last)),
+            response_time,
+            avg_processing_time,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+    use serde_json::json;
+    use test_case::test_case;
+
+    use crate::configuration::domain::{Endpoint, Topic, TopicsList};
+    use crate::error::Error::EntityNotFound;
+    use crate::events::domain::{Event, Message, Payload};
+    use crate::events::storage::{
+        percentile, AttemptLogRepository, EventRepository, InMemoryEventStorage,
+        InMemoryMessageStorage, MessageRepository,
+    };
+    use crate::sender::{SentResult, Status};
+    use crate::tests::dt;
+    use crate::time::Clock;
+    use crate::types::{ApplicationId, EventId};
+
+    #[tokio::test]
+    async fn in_memory_event_is_found_by_idempotency_key_of_its_app() {
+        let storage = InMemoryEventStorage::default();
+        let app_id = ApplicationId::new();
+        let event = event(app_id, "/crm", "key");
+
+        storage.save(event.clone()).await.unwrap();
+
+        assert_eq!(
+            event.id,
+            storage
+                .get_by_idempotency_key(&app_id, "/crm", "key")
+                .await
+                .unwrap()
+                .id
+        );
+        assert!(storage
+            .get_by_idempotency_key(&ApplicationId::new(), "/crm", "key")
+            .await
+            .is_err());
+        assert!(storage
+            .get_by_idempotency_key(&app_id, "/billing", "key")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn in_memory_duplicate_event_is_not_saved() {
+        let storage = InMemoryEventStorage::default();
+        let app_id = ApplicationId::new();
+        let first = event(app_id, "/crm", "key");
+
+        let saved = storage.save(first.clone()).await.unwrap();
+        let duplicate = storage.save(event(app_id, "/crm", "key")).await.unwrap();
+        let other_source = storage
+            .save(event(app_id, "/billing", "key"))
             .await
             .unwrap();
+
+        assert_eq!(first.id, saved.id);
+        assert_eq!(first.id, duplicate.id);
+        assert_ne!(first.id, other_source.id);
+    }
+
+    #[tokio::test]
+    async fn missing_in_memory_event_is_not_found() {
+        let storage = InMemoryEventStorage::default();
+
+        assert_eq!(
+            EntityNotFound("Entity not found".to_string()),
+            storage.get(EventId::new()).await.err().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn in_memory_event_keeps_its_first_cancellation() {
+        let storage = InMemoryEventStorage::default();
+        let mut event = event(ApplicationId::new(), "/crm", "key");
+        storage.save(event.clone()).await.unwrap();
+
+        event.cancelled_at = Some(dt!("2024-11-20T10:00:00Z"));
+        storage.cancel(&event).await.unwrap();
+        event.cancelled_at = Some(dt!("2024-11-20T11:00:00Z"));
+        storage.cancel(&event).await.unwrap();
+
+        assert_eq!(
+            Some(dt!("2024-11-20T10:00:00Z")),
+            storage.get(event.id).await.unwrap().cancelled_at
+        );
+    }
+
+    #[tokio::test]
+    async fn in_memory_stats_are_computed_from_attempts_of_endpoint() {
+        let messages = InMemoryMessageStorage::default();
+        let attempt_logs = messages.attempt_logs();
+        let event = event(ApplicationId::new(), "/crm", "key");
+        let (tracked, other) = (endpoint(&event), endpoint(&event));
+
+        for (endpoint, statuses) in [(&tracked, vec![500, 503, 200]), (&other, vec![404])] {
+            let mut message = Message::from((event.clone(), endpoint.clone()));
+
+            for (i, status) in statuses.into_iter().enumerate() {
+                let clock =
+                    Clock::fixed(dt!("2024-11-20T10:00:00Z") + chrono::Duration::minutes(i as i64));
+                let log = message.record_attempt(
+                    result(Status::Numeric(status), (i as u64 + 1) * 100),
+                    Duration::from_millis(50),
+                    &clock,
+                );
+                attempt_logs.save(log).await.unwrap();
+            }
+
+            messages.save(message).await.unwrap();
+        }
+
+        let stats = attempt_logs
+            .endpoint_stats(&tracked.id, dt!("2024-11-20T10:01:00Z"))
+            .await
+            .unwrap();
+
+        assert_eq!(2, stats.attempts());
+        assert_eq!(Some(&1), stats.status_classes.get("2xx"));
+        assert_eq!(Some(&1), stats.status_classes.get("5xx"));
+        assert_eq!(None, stats.status_classes.get("4xx"));
+        assert_eq!(Some(dt!("2024-11-20T10:02:00Z")), stats.last_success_at);
+        assert_eq!(Some(dt!("2024-11-20T10:01:00Z")), stats.last_failure_at);
+        assert_eq!(Duration::from_millis(250), stats.response_time.unwrap().p50);
+        assert_eq!(Some(Duration::from_millis(50)), stats.avg_processing_time);
+    }
+
+    #[tokio::test]
+    async fn in_memory_response_time_excludes_attempts_without_response() {
+        let messages = InMemoryMessageStorage::default();
+        let attempt_logs = messages.attempt_logs();
+        let event = event(ApplicationId::new(), "/crm", "key");
+        let endpoint = endpoint(&event);
+        let mut message = Message::from((event, endpoint.clone()));
+
+        for status in [
+            Status::Timeout("Timeout".to_string()),
+            Status::Blocked("Blocked".to_string()),
+            Status::Numeric(200),
+        ] {
+            let response_time = if let Status::Numeric(_) = status {
+                300
+            } else {
+                0
+            };
+            let log = message.record_attempt(
+                result(status, response_time),
+                Duration::from_millis(50),
+                &Clock::fixed(dt!("2024-11-20T10:00:00Z")),
+            );
+            attempt_logs.save(log).await.unwrap();
+        }
+        messages.save(message).await.unwrap();
+
+        let stats = attempt_logs
+            .endpoint_stats(&endpoint.id, dt!("2024-11-20T09:00:00Z"))
+            .await
+            .unwrap();
+
+        assert_eq!(3, stats.attempts());
+        assert_eq!(Duration::from_millis(300), stats.response_time.unwrap().p50);
+        assert_eq!(Duration::from_millis(300), stats.response_time.unwrap().p95);
+    }
+
+    #[test_case(&[], 0.5, None)]
+    #[test_case(&[100.0], 0.99, Some(100.0))]
+    #[test_case(&[100.0, 200.0, 300.0, 400.0], 0.5, Some(250.0))]
+    #[test_case(&[100.0, 200.0, 300.0, 400.0], 0.95, Some(385.0))]
+    fn percentile_is_interpolated(sorted: &[f64], fraction: f64, expected: Option<f64>) {
+        let actual = percentile(sorted, fraction);
+
+        assert_eq!(
+            expected.map(|value| value.round()),
+            actual.map(|value| value.round())
+        );
+    }
+
+    fn event(app_id: ApplicationId, source: &str, idempotency_key: &str) -> Event {
+        Event::new(
+            app_id,
+            Payload::from(json!({"foo": "bar"})),
+            Topic::try_from("contact.created").unwrap(),
+            &Clock::chrono(),
+        )
+        .with_idempotency_key(source.to_string(), idempotency_key.to_string())
+    }
+
+    fn endpoint(event: &Event) -> Endpoint {
+        Endpoint::new(
+            "https://example.com/webhook",
+            event.app_id,
+            TopicsList::from(vec!["contact.created"]),
+        )
+    }
+
+    fn result(status: Status, response_time_ms: u64) -> SentResult {
+        SentResult {
+            status,
+            response_time: Duration::from_millis(response_time_ms),
+            body: None,
+            headers: None,
+        }
     }
 }
@@ -0,0 +1,638 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::{debug, error};
+use tokio::select;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::cmd::AsyncMessage;
+use crate::config::DeliveryConfig;
+use crate::configuration::domain::Priority;
+use crate::configuration::storage::InFlightRepository;
+use crate::error::Error;
+use crate::metrics;
+use crate::queue::{Acknowledger, Queue, QueueConsumer, QueueDelivery};
+use crate::storage::Storage;
+use crate::time::Clock;
+use crate::types::ApplicationId;
+
+const LIMITS_TTL: Duration = Duration::from_secs(60);
+// Leases of a dispatcher which stopped without settling its deliveries are not counted for longer
+const IN_FLIGHT_TTL: Duration = Duration::from_secs(300);
+
+type Tenant = Option<ApplicationId>;
+
+pub struct TenantScheduler<T> {
+    buckets: HashMap<Tenant, VecDeque<(Priority, T)>>,
+    turns: VecDeque<Tenant>,
+    len: usize,
+}
+
+impl<T> TenantScheduler<T> {
+    pub fn new() -> Self {
+        Self {
+            buckets: HashMap::new(),
+            turns: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn others_waiting(&self, tenant: &Tenant) -> bool {
+        self.buckets.keys().any(|other| other != tenant)
+    }
+
+    pub fn push(&mut self, tenant: Tenant, priority: Priority, item: T) {
+        let bucket = self.buckets.entry(tenant).or_insert_with(|| {
+            self.turns.push_back(tenant);
+
+            VecDeque::new()
+        });
+
+        bucket.push_back((priority, item));
+        self.len += 1;
+    }
+
+    pub fn peek(&self) -> Option<(&Tenant, &T)> {
+        let (turn, index) = self.turn()?;
+        let tenant = &self.turns[turn];
+
+        Some((tenant, &self.buckets[tenant][index].1))
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let (turn, index) = self.turn()?;
+        let tenant = self.turns.remove(turn)?;
+
+        let bucket = self.buckets.get_mut(&tenant)?;
+        let (_, item) = bucket.remove(index)?;
+        self.len -= 1;
+
+        if bucket.is_empty() {
+            self.buckets.remove(&tenant);
+        } else {
+            self.turns.push_back(tenant);
+        }
+
+        Some(item)
+    }
+
+    // Tenants take turns, but a tenant holding more urgent work than the others goes first
+    fn turn(&self) -> Option<(usize, usize)> {
+        let best = |bucket: &VecDeque<(Priority, T)>| {
+            bucket
+                .iter()
+                .map(|(priority, _)| priority.rank())
+                .max()
+                .unwrap_or(i16::MIN)
+        };
+
+        let rank = self.buckets.values().map(best).max()?;
+        let turn = self
+            .turns
+            .iter()
+            .position(|tenant| best(&self.buckets[tenant]) == rank)?;
+        let index = self.buckets[&self.turns[turn]]
+            .iter()
+            .position(|(priority, _)| priority.rank() == rank)?;
+
+        Some((turn, index))
+    }
+
+    pub fn drain(&mut self) -> Vec<T> {
+        self.turns.clear();
+        self.len = 0;
+
+        self.buckets
+            .drain()
+            .flat_map(|(_, bucket)| bucket.into_iter().map(|(_, item)| item))
+            .collect()
+    }
+}
+
+impl<T> Default for TenantScheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct FairConsumer {
+    queue_name: String,
+    queue: Arc<dyn Queue>,
+    deliveries: mpsc::Receiver<QueueDelivery>,
+    stop: Option<oneshot::Sender<()>>,
+    feeder: Option<JoinHandle<()>>,
+    scheduler: TenantScheduler<QueueDelivery>,
+    limits: HashMap<ApplicationId, (Option<usize>, Instant)>,
+    prefetch: usize,
+    app_concurrency: Option<usize>,
+    defer_delay: Duration,
+    clock: Clock,
+}
+
+impl FairConsumer {
+    pub fn new(
+        mut consumer: Box<dyn QueueConsumer>,
+        queue: Arc<dyn Queue>,
+        delivery_config: &DeliveryConfig,
+    ) -> Self {
+        let queue_name = consumer.queue().to_string();
+        let prefetch = delivery_config.prefetch();
+        let (sender, deliveries) = mpsc::channel(prefetch);
+        let (stop, mut stopped) = oneshot::channel::<()>();
+
+        let feeder = tokio::spawn(async move {
+            loop {
+                let permit = select! {
+                    biased;
+                    _ = &mut stopped => break,
+                    permit = sender.reserve() => match permit {
+                        Ok(permit) => permit,
+                        Err(_) => break,
+                    },
+                };
+
+                let delivery = select! {
+                    biased;
+                    _ = &mut stopped => break,
+                    delivery = consumer.next() => delivery,
+                };
+
+                let Some(delivery) = delivery else {
+                    break;
+                };
+
+                permit.send(delivery);
+            }
+
+            consumer.cancel().await;
+        });
+
+        Self {
+            queue_name,
+            queue,
+            deliveries,
+            stop: Some(stop),
+            feeder: Some(feeder),
+            scheduler: TenantScheduler::new(),
+            limits: HashMap::new(),
+            prefetch,
+            app_concurrency: delivery_config.app_concurrency(),
+            defer_delay: delivery_config.defer_delay(),
+            clock: Clock::chrono(),
+        }
+    }
+
+    pub fn queue(&self) -> &str {
+        &self.queue_name
+    }
+
+    // Deliveries stay in the scheduler until handed out, so dropping `next` doesn't lose them
+    pub async fn next(&mut self, storage: &Storage) -> Option<QueueDelivery> {
+        loop {
+            if self.scheduler.is_empty() {
+                let delivery = self.deliveries.recv().await?;
+                self.schedule(delivery);
+            }
+
+            while self.scheduler.len() < self.prefetch {
+                let Ok(delivery) = self.deliveries.try_recv() else {
+                    break;
+                };
+
+                self.schedule(delivery);
+            }
+
+            let Some(app_id) = self.scheduler.peek().and_then(|(tenant, _)| *tenant) else {
+                return self.scheduler.pop();
+            };
+
+            let Some(max) = self.max_concurrency(&app_id, storage).await else {
+                return self.scheduler.pop();
+            };
+
+            // The limit applies only while other applications are waiting, so a lone application uses the whole dispatcher
+            let contended = self.scheduler.others_waiting(&Some(app_id));
+            let now = self.clock.now();
+            let expires_at = now + chrono::Duration::from_std(IN_FLIGHT_TTL).unwrap();
+            let lease = storage
+                .in_flight
+                .acquire(&app_id, contended.then_some(max), now, expires_at)
+                .await;
+
+            match lease {
+                Ok(Some(lease)) => {
+                    let in_flight = storage.in_flight.clone();
+
+                    return self.scheduler.pop().map(|delivery| {
+                        delivery.map_acknowledger(|acknowledger| {
+                            Box::new(LeasedAcknowledger {
+                                acknowledger,
+                                in_flight,
+                                lease,
+                            })
+                        })
+                    });
+                }
+                Ok(None) => {
+                    if let Err(err) = self.defer(&app_id, max).await {
+                        error!(
+                            "Message of application {} cannot be deferred: {:?}",
+                            app_id, err
+                        );
+
+                        return self.scheduler.pop();
+                    }
+                }
+                Err(err) => {
+                    error!(
+                        "Deliveries in flight of application {} cannot be counted: {:?}",
+                        app_id, err
+                    );
+
+                    return self.scheduler.pop();
+                }
+            }
+        }
+    }
+
+    pub async fn cancel(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+
+        if let Some(feeder) = self.feeder.take() {
+            if let Err(err) = feeder.await {
+                error!("Consumer of {} cannot be stopped: {}", self.queue_name, err);
+            }
+        }
+
+        self.deliveries.close();
+        while let Ok(delivery) = self.deliveries.try_recv() {
+            delivery.requeue().await;
+        }
+
+        for delivery in self.scheduler.drain() {
+            delivery.requeue().await;
+        }
+    }
+
+    // Undecodable deliveries are passed on, so the dispatcher can dead-letter them
+    fn schedule(&mut self, delivery: QueueDelivery) {
+        match AsyncMessage::decode(&delivery.data) {
+            Ok(message) => self
+                .scheduler
+                .push(message.app_id(), message.priority(), delivery),
+            Err(_) => self.scheduler.push(None, Priority::default(), delivery),
+        }
+    }
+
+    async fn defer(&mut self, app_id: &ApplicationId, max: usize) -> Result<(), Error> {
+        let message = match self.scheduler.peek() {
+            Some((_, delivery)) => AsyncMessage::decode(&delivery.data)?,
+            None => return Ok(()),
+        };
+
+        self.queue
+            .publish_delayed(message, self.defer_delay)
+            .await?;
+        metrics::message_deferred(app_id);
+
+        if let Some(delivery) = self.scheduler.pop() {
+            delivery.ack().await;
+        }
+
+        debug!(
+            "Application {} has {} deliveries in flight, message deferred by {:?}",
+            app_id, max, self.defer_delay
+        );
+
+        Ok(())
+    }
+
+    async fn max_concurrency(
+        &mut self,
+        app_id: &ApplicationId,
+        storage: &Storage,
+    ) -> Option<usize> {
+        if let Some((max, fetched_at)) = self.limits.get(app_id) {
+            if fetched_at.elapsed() < LIMITS_TTL {
+                return *max;
+            }
+        }
+
+        match storage.applications.get(app_id).await {
+            Ok(app) => {
+                let max = app
+                    .max_concurrency
+                    .map(|max| max as usize)
+                    .or(self.app_concurrency);
+                self.limits.insert(*app_id, (max, Instant::now()));
+
+                max
+            }
+            Err(_) => self.app_concurrency,
+        }
+    }
+}
+
+struct LeasedAcknowledger {
+    acknowledger: Box<dyn Acknowledger>,
+    in_flight: Arc<dyn InFlightRepository>,
+    lease: i64,
+}
+
+impl LeasedAcknowledger {
+    async fn release(&self) {
+        if let Err(err) = self.in_flight.release(self.lease).await {
+            error!(
+                "Lease {} of delivery cannot be released: {:?}",
+                self.lease, err
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl Acknowledger for LeasedAcknowledger {
+    async fn ack(&self) {
+        self.acknowledger.ack().await;
+        self.release().await;
+    }
+
+    async fn requeue(&self) {
+        self.acknowledger.requeue().await;
+        self.release().await;
+    }
+
+    async fn reject(&self) {
+        self.acknowledger.reject().await;
+        self.release().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+    use std::future::pending;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use envconfig::Envconfig;
+    use opentelemetry::Context;
+    use tokio::time::{sleep, timeout};
+
+    use crate::cmd::{AsyncMessage, SentMessage};
+    use crate::config::DeliveryConfig;
+    use crate::configuration::domain::{Application, DeliveryFormat, Priority};
+    use crate::error::Error;
+    use crate::fairness::{FairConsumer, TenantScheduler};
+    use crate::queue::{Acknowledger, Queue, QueueConsumer, QueueDelivery};
+    use crate::storage::Storage;
+    use crate::types::{ApplicationId, MessageId};
+
+    #[test]
+    fn applications_take_turns() {
+        let (noisy, quiet) = (Some(ApplicationId::new()), Some(ApplicationId::new()));
+        let mut sut = TenantScheduler::new();
+
+        for i in 1..=3 {
+            sut.push(noisy, Priority::Normal, format!("noisy-{i}"));
+        }
+        sut.push(quiet, Priority::Normal, "quiet-1".to_string());
+
+        let order: Vec<String> = std::iter::from_fn(|| sut.pop()).collect();
+
+        assert_eq!(vec!["noisy-1", "quiet-1", "noisy-2", "noisy-3"], order);
+        assert!(sut.is_empty());
+    }
+
+    #[test]
+    fn urgent_work_is_served_first() {
+        let (first, second) = (Some(ApplicationId::new()), Some(ApplicationId::new()));
+        let mut sut = TenantScheduler::new();
+
+        sut.push(first, Priority::Low, "first-low");
+        sut.push(first, Priority::Normal, "first-normal");
+        sut.push(second, Priority::High, "second-high");
+
+        let order: Vec<&str> = std::iter::from_fn(|| sut.pop()).collect();
+
+        assert_eq!(vec!["second-high", "first-normal", "first-low"], order);
+    }
+
+    #[test]
+    fn peeked_item_is_popped_next() {
+        let (app, other) = (Some(ApplicationId::new()), Some(ApplicationId::new()));
+        let mut sut = TenantScheduler::new();
+
+        sut.push(app, Priority::Normal, 1);
+        sut.push(other, Priority::High, 2);
+
+        assert_eq!(Some((&other, &2)), sut.peek());
+        assert_eq!(Some(2), sut.pop());
+        assert_eq!(Some((&app, &1)), sut.peek());
+    }
+
+    #[test]
+    fn other_applications_are_waiting_while_they_hold_items() {
+        let (app, other) = (Some(ApplicationId::new()), Some(ApplicationId::new()));
+        let mut sut = TenantScheduler::new();
+
+        sut.push(app, Priority::Normal, 1);
+        sut.push(app, Priority::Normal, 2);
+
+        assert!(!sut.others_waiting(&app));
+        assert!(sut.others_waiting(&other));
+
+        sut.push(None, Priority::Normal, 3);
+
+        assert!(sut.others_waiting(&app));
+        assert_eq!(3, sut.drain().len());
+        assert!(sut.is_empty());
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_requeued_when_next_is_dropped_while_deferring() {
+        let (storage, app) = given_capped_app_with_delivery_in_flight().await;
+        let other = ApplicationId::new();
+
+        let acks = Arc::new(Mutex::new(Vec::new()));
+        let mut sut = fair_consumer(vec![
+            delivery(app, acks.clone()),
+            delivery(other, acks.clone()),
+        ])
+        .await;
+
+        let next = timeout(Duration::from_millis(50), sut.next(&storage)).await;
+        sut.cancel().await;
+
+        assert!(next.is_err());
+        assert_eq!(vec!["requeue", "requeue"], *acks.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn capped_application_is_not_deferred_while_others_are_not_waiting() {
+        let (storage, app) = given_capped_app_with_delivery_in_flight().await;
+
+        let acks = Arc::new(Mutex::new(Vec::new()));
+        let mut sut = fair_consumer(vec![
+            delivery(app, acks.clone()),
+            delivery(app, acks.clone()),
+        ])
+        .await;
+
+        for _ in 0..2 {
+            let next = timeout(Duration::from_millis(50), sut.next(&storage)).await;
+
+            assert!(next.is_ok_and(|delivery| delivery.is_some()));
+        }
+        sut.cancel().await;
+
+        assert!(acks.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn lease_is_released_when_delivery_is_settled() {
+        let storage = Storage::in_memory();
+        let app = Application::new("app".to_string(), DeliveryFormat::default())
+            .with_max_concurrency(Some(1));
+        storage.applications.save(app.clone()).await.unwrap();
+
+        let acks = Arc::new(Mutex::new(Vec::new()));
+        let mut sut = fair_consumer(vec![delivery(app.id, acks.clone())]).await;
+
+        let delivery = sut.next(&storage).await.unwrap();
+        assert_eq!(None, acquire(&storage, &app.id).await);
+
+        delivery.ack().await;
+        assert!(acquire(&storage, &app.id).await.is_some());
+
+        sut.cancel().await;
+        assert_eq!(vec!["ack"], *acks.lock().unwrap());
+    }
+
+    async fn given_capped_app_with_delivery_in_flight() -> (Storage, ApplicationId) {
+        let storage = Storage::in_memory();
+        let app = Application::new("app".to_string(), DeliveryFormat::default())
+            .with_max_concurrency(Some(1));
+        storage.applications.save(app.clone()).await.unwrap();
+        acquire(&storage, &app.id).await.unwrap();
+
+        (storage, app.id)
+    }
+
+    async fn acquire(storage: &Storage, app_id: &ApplicationId) -> Option<i64> {
+        let now = Utc::now();
+
+        storage
+            .in_flight
+            .acquire(app_id, Some(1), now, now + chrono::Duration::minutes(5))
+            .await
+            .unwrap()
+    }
+
+    async fn fair_consumer(deliveries: Vec<QueueDelivery>) -> FairConsumer {
+        let sut = FairConsumer::new(
+            Box::new(FakeConsumer(deliveries.into())),
+            Arc::new(StalledQueue),
+            &DeliveryConfig::init_from_hashmap(&HashMap::new()).unwrap(),
+        );
+        sleep(Duration::from_millis(10)).await;
+
+        sut
+    }
+
+    fn delivery(app_id: ApplicationId, acks: Arc<Mutex<Vec<&'static str>>>) -> QueueDelivery {
+        let message = SentMessage::new(MessageId::new()).with_app_id(app_id);
+
+        QueueDelivery::new(
+            AsyncMessage::SentMessage(message).encode(),
+            Context::new(),
+            Box::new(RecordingAcknowledger(acks)),
+        )
+    }
+
+    struct FakeConsumer(VecDeque<QueueDelivery>);
+
+    #[async_trait]
+    impl QueueConsumer for FakeConsumer {
+        fn queue(&self) -> &str {
+            "fake"
+        }
+
+        async fn next(&mut self) -> Option<QueueDelivery> {
+            match self.0.pop_front() {
+                Some(delivery) => Some(delivery),
+                None => pending().await,
+            }
+        }
+
+        async fn cancel(&mut self) {}
+    }
+
+    struct StalledQueue;
+
+    #[async_trait]
+    impl Queue for StalledQueue {
+        fn kind(&self) -> &'static str {
+            "stalled"
+        }
+
+        async fn publish(&self, _message: AsyncMessage) -> Result<(), Error> {
+            pending().await
+        }
+
+        async fn publish_delayed(
+            &self,
+            _message: AsyncMessage,
+            _delay: Duration,
+        ) -> Result<(), Error> {
+            pending().await
+        }
+
+        async fn consumer(&self, _consumer_tag: &str) -> Box<dyn QueueConsumer> {
+            unimplemented!()
+        }
+
+        async fn ping(&self) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn depth(&self) -> Result<u64, Error> {
+            Ok(0)
+        }
+
+        async fn close(&self) {}
+    }
+
+    struct RecordingAcknowledger(Arc<Mutex<Vec<&'static str>>>);
+
+    #[async_trait]
+    impl Acknowledger for RecordingAcknowledger {
+        async fn ack(&self) {
+            self.0.lock().unwrap().push("ack");
+        }
+
+        async fn requeue(&self) {
+            self.0.lock().unwrap().push("requeue");
+        }
+
+        async fn reject(&self) {
+            self.0.lock().unwrap().push("reject");
+        }
+    }
+}
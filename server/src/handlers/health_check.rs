@@ -1,5 +1,18 @@
+use actix_web::web::Data;
 use actix_web::HttpResponse;
 
+use crate::health::HealthCheck;
+
 pub async fn health_check() -> HttpResponse {
     HttpResponse::NoContent().finish()
 }
+
+pub async fn readiness(health: Data<HealthCheck>) -> HttpResponse {
+    let report = health.readiness().await;
+
+    if report.is_ready() {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
@@ -0,0 +1,17 @@
+use actix_web::web::Data;
+use actix_web::HttpResponse;
+use log::error;
+
+use crate::metrics::{queue_depth_measured, render, CONTENT_TYPE};
+use crate::queue::Queue;
+
+pub async fn metrics(queue: Option<Data<dyn Queue>>) -> HttpResponse {
+    if let Some(queue) = queue {
+        match queue.depth().await {
+            Ok(depth) => queue_depth_measured(depth),
+            Err(err) => error!("Queue depth cannot be measured: {:?}", err),
+        }
+    }
+
+    HttpResponse::Ok().content_type(CONTENT_TYPE).body(render())
+}
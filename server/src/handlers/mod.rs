@@ -1 +1,2 @@
 pub mod health_check;
+pub mod metrics;
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sqlx::PgPool;
+use tokio::time::timeout;
+
+use crate::queue::Queue;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DependencyReport {
+    pub status: HealthStatus,
+    pub latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ReadinessReport {
+    pub status: HealthStatus,
+    pub checks: BTreeMap<&'static str, DependencyReport>,
+}
+
+impl ReadinessReport {
+    fn new(checks: BTreeMap<&'static str, DependencyReport>) -> Self {
+        let status = if checks.values().all(|c| c.status == HealthStatus::Up) {
+            HealthStatus::Up
+        } else {
+            HealthStatus::Down
+        };
+
+        Self { status, checks }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status == HealthStatus::Up
+    }
+}
+
+pub struct HealthCheck {
+    pool: Option<PgPool>,
+    queue: Option<Arc<dyn Queue>>,
+}
+
+impl HealthCheck {
+    pub fn new(pool: PgPool, queue: Arc<dyn Queue>) -> Self {
+        Self {
+            pool: Some(pool),
+            queue: Some(queue),
+        }
+    }
+
+    pub fn without_queue(pool: PgPool) -> Self {
+        Self {
+            pool: Some(pool),
+            queue: None,
+        }
+    }
+
+    pub fn without_database(queue: Arc<dyn Queue>) -> Self {
+        Self {
+            pool: None,
+            queue: Some(queue),
+        }
+    }
+
+    pub async fn readiness(&self) -> ReadinessReport {
+        let (postgres, queue) = tokio::join!(self.check_postgres(), self.check_queue());
+
+        ReadinessReport::new(postgres.into_iter().chain(queue).collect())
+    }
+
+    async fn check_queue(&self) -> Option<(&'static str, DependencyReport)> {
+        let queue = self.queue.as_ref()?;
+
+        Some((queue.kind(), check(CHECK_TIMEOUT, queue.ping()).await))
+    }
+
+    async fn check_postgres(&self) -> Option<(&'static str, DependencyReport)> {
+        let pool = self.pool.as_ref()?;
+        let report = check(CHECK_TIMEOUT, async {
+            sqlx::query("SELECT 1")
+                .execute(pool)
+                .await
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        })
+        .await;
+
+        Some(("postgres", report))
+    }
+}
+
+async fn check<F>(limit: Duration, ping: F) -> DependencyReport
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let start = Instant::now();
+    let result = timeout(limit, ping)
+        .await
+        .unwrap_or_else(|_| Err(format!("Timed out after {}ms", limit.as_millis())));
+
+    DependencyReport {
+        status: match result {
+            Ok(_) => HealthStatus::Up,
+            Err(_) => HealthStatus::Down,
+        },
+        latency_ms: start.elapsed().as_millis(),
+        error: result.err(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::future::pending;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::health::{check, DependencyReport, HealthStatus, ReadinessReport};
+
+    #[test]
+    fn report_is_down_when_any_dependency_is_down() {
+        let report = ReadinessReport::new(BTreeMap::from([
+            ("postgres", report(HealthStatus::Up, None)),
+            (
+                "amqp",
+                report(HealthStatus::Down, Some("Channel is Closed")),
+            ),
+        ]));
+
+        assert!(!report.is_ready());
+        assert_eq!(
+            json!({
+                "status": "down",
+                "checks": {
+                    "amqp": {"status": "down", "latency_ms": 1, "error": "Channel is Closed"},
+                    "postgres": {"status": "up", "latency_ms": 1},
+                },
+            }),
+            serde_json::to_value(&report).unwrap()
+        );
+    }
+
+    #[test]
+    fn report_is_up_when_all_dependencies_are_up() {
+        let report = ReadinessReport::new(BTreeMap::from([
+            ("postgres", report(HealthStatus::Up, None)),
+            ("amqp", report(HealthStatus::Up, None)),
+        ]));
+
+        assert!(report.is_ready());
+    }
+
+    #[tokio::test]
+    async fn hanging_check_is_timed_out() {
+        let report = check(Duration::from_millis(10), pending()).await;
+
+        assert_eq!(HealthStatus::Down, report.status);
+        assert_eq!(Some("Timed out after 10ms".to_string()), report.error);
+    }
+
+    fn report(status: HealthStatus, error: Option<&str>) -> DependencyReport {
+        DependencyReport {
+            status,
+            latency_ms: 1,
+            error: error.map(str::to_string),
+        }
+    }
+}
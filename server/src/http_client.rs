@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::debug;
+use reqwest::{Client, ClientBuilder};
+
+use crate::config::DeliveryConfig;
+use crate::configuration::domain::{Application, Endpoint, OutboundProxy, TlsConfig};
+use crate::egress::EgressPolicy;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ClientKey {
+    tls: TlsConfig,
+    connect_timeout: Option<Duration>,
+    proxy: Option<OutboundProxy>,
+}
+
+pub struct HttpClients {
+    config: DeliveryConfig,
+    egress: EgressPolicy,
+    proxy: Option<OutboundProxy>,
+    default: Client,
+    clients: HashMap<ClientKey, Client>,
+}
+
+impl HttpClients {
+    pub fn new(config: DeliveryConfig, egress: EgressPolicy) -> Result<Self, reqwest::Error> {
+        let proxy = config.proxy();
+        let egress = match &proxy {
+            Some(proxy) => egress.with_allowed_host(proxy.url()),
+            None => egress,
+        };
+        let default = Self::builder(&config, &egress, proxy.as_ref())?.build()?;
+
+        Ok(Self {
+            config,
+            egress,
+            proxy,
+            default,
+            clients: HashMap::new(),
+        })
+    }
+
+    pub fn default_client(&self) -> Client {
+        self.default.clone()
+    }
+
+    pub fn proxy_for(&self, app: &Application, endpoint: &Endpoint) -> Option<OutboundProxy> {
+        app.proxy
+            .clone()
+            .or_else(|| self.proxy.clone())
+            .filter(|proxy| !proxy.is_bypassed(&endpoint.url))
+    }
+
+    pub fn get(
+        &mut self,
+        endpoint: &Endpoint,
+        proxy: Option<&OutboundProxy>,
+    ) -> Result<Client, reqwest::Error> {
+        let key = ClientKey {
+            tls: endpoint.tls.clone(),
+            connect_timeout: endpoint.timeouts.connect,
+            proxy: proxy.cloned(),
+        };
+
+        if key.tls.is_default() && key.connect_timeout.is_none() && key.proxy == self.proxy {
+            return Ok(self.default_client());
+        }
+
+        if let Some(client) = self.clients.get(&key) {
+            return Ok(client.clone());
+        }
+
+        debug!("Building http client for endpoint {}", endpoint.id);
+
+        let client = self.build(&key)?;
+        self.clients.insert(key, client.clone());
+
+        Ok(client)
+    }
+
+    fn build(&self, key: &ClientKey) -> Result<Client, reqwest::Error> {
+        let mut builder = Self::builder(&self.config, &self.egress, key.proxy.as_ref())?;
+
+        if let Some(connect_timeout) = key.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+
+        if let Some(identity) = key.tls.identity()? {
+            builder = builder.identity(identity);
+        }
+
+        for certificate in key.tls.ca_certificates()? {
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        builder.build()
+    }
+
+    fn builder(
+        config: &DeliveryConfig,
+        egress: &EgressPolicy,
+        proxy: Option<&OutboundProxy>,
+    ) -> Result<ClientBuilder, reqwest::Error> {
+        let builder = Client::builder()
+            .dns_resolver(Arc::new(egress.resolver()))
+            .redirect(egress.redirect_policy())
+            .connect_timeout(config.connect_timeout())
+            .timeout(config.request_timeout())
+            .pool_idle_timeout(config.pool_idle_timeout())
+            .pool_max_idle_per_host(config.pool_max_idle_per_host())
+            .tcp_keepalive(config.pool_idle_timeout());
+
+        match proxy {
+            Some(proxy) => Ok(builder.proxy(proxy.to_proxy()?)),
+            None => Ok(builder.no_proxy()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use envconfig::Envconfig;
+
+    use crate::config::DeliveryConfig;
+    use crate::configuration::domain::{
+        Application, DeliveryFormat, Endpoint, OutboundProxy, Timeouts, TlsConfig, TopicsList,
+    };
+    use crate::egress::EgressPolicy;
+    use crate::http_client::HttpClients;
+    use crate::types::ApplicationId;
+
+    const CA_CERTIFICATE: &str = include_str!("../tests/fixtures/tls/ca.crt");
+    const CLIENT_CERTIFICATE: &str = include_str!("../tests/fixtures/tls/client.crt");
+    const CLIENT_KEY: &str = include_str!("../tests/fixtures/tls/client.key");
+
+    #[test]
+    fn client_is_built_once_for_the_same_config() {
+        let mut sut = HttpClients::new(config(&[]), EgressPolicy::default()).unwrap();
+        let tls = TlsConfig::new(
+            Some(CLIENT_CERTIFICATE),
+            Some(CLIENT_KEY),
+            Some(CA_CERTIFICATE),
+        )
+        .unwrap();
+
+        sut.get(&endpoint("https://localhost").with_tls(tls.clone()), None)
+            .unwrap();
+        sut.get(&endpoint("https://localhost").with_tls(tls), None)
+            .unwrap();
+        sut.get(&endpoint("https://localhost"), None).unwrap();
+
+        assert_eq!(1, sut.clients.len());
+    }
+
+    #[test]
+    fn endpoint_with_connect_timeout_has_own_client() {
+        let mut sut = HttpClients::new(config(&[]), EgressPolicy::default()).unwrap();
+
+        sut.get(
+            &endpoint("https://localhost").with_timeouts(Timeouts::new(Some(100), None)),
+            None,
+        )
+        .unwrap();
+        sut.get(
+            &endpoint("https://localhost").with_timeouts(Timeouts::new(None, Some(100))),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(1, sut.clients.len());
+    }
+
+    #[test]
+    fn global_proxy_is_used_unless_endpoint_is_bypassed() {
+        let sut = HttpClients::new(
+            config(&[
+                ("DELIVERY_PROXY_URL", "http://proxy.internal:3128"),
+                ("DELIVERY_NO_PROXY", "partner.com"),
+            ]),
+            EgressPolicy::default(),
+        )
+        .unwrap();
+        let app = Application::new("app".to_string(), DeliveryFormat::Raw);
+
+        let proxy = sut.proxy_for(&app, &endpoint("https://example.com"));
+        let bypassed = sut.proxy_for(&app, &endpoint("https://api.partner.com"));
+
+        assert_eq!(
+            Some("http://proxy.internal:3128/".to_string()),
+            proxy.map(|p| p.address())
+        );
+        assert_eq!(None, bypassed);
+    }
+
+    #[test]
+    fn application_proxy_overrides_global_proxy() {
+        let mut sut = HttpClients::new(
+            config(&[("DELIVERY_PROXY_URL", "http://proxy.internal:3128")]),
+            EgressPolicy::default(),
+        )
+        .unwrap();
+        let app = Application::new("app".to_string(), DeliveryFormat::Raw).with_proxy(Some(
+            OutboundProxy::new("http://egress.example.com:8080", None, None, Vec::new()).unwrap(),
+        ));
+        let endpoint = endpoint("https://example.com");
+
+        let proxy = sut.proxy_for(&app, &endpoint);
+        sut.get(&endpoint, proxy.as_ref()).unwrap();
+
+        assert_eq!(
+            Some("http://egress.example.com:8080/".to_string()),
+            proxy.map(|p| p.address())
+        );
+        assert_eq!(1, sut.clients.len());
+    }
+
+    fn config(vars: &[(&str, &str)]) -> DeliveryConfig {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        DeliveryConfig::init_from_hashmap(&vars).unwrap()
+    }
+
+    fn endpoint(url: &str) -> Endpoint {
+        Endpoint::new(
+            url,
+            ApplicationId::new(),
+            TopicsList::from(vec!["contact.created"]),
+        )
+    }
+}
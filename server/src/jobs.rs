@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::error;
+use opentelemetry::trace::SpanKind;
+use opentelemetry::Context;
+use serde_json::{json, Value};
+use sqlx::{query, query_as, PgPool};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep};
+
+use crate::cmd::AsyncMessage;
+use crate::config::QueueConfig;
+use crate::configuration::domain::Priority;
+use crate::error::Error;
+use crate::queue::{Acknowledger, LaneScheduler, Queue, QueueConsumer, QueueDelivery};
+use crate::telemetry::{end_span, extract_map, inject_map, start_span, WithDbSpan};
+use crate::time::Clock;
+
+const SENT_MESSAGE_QUEUE: &str = "sent_message";
+const DEAD_LETTER_SUFFIX: &str = "-dead-letter";
+
+pub struct JobQueue {
+    pool: PgPool,
+    poll_interval: Duration,
+    visibility_timeout: Duration,
+    clock: Clock,
+}
+
+impl JobQueue {
+    pub fn new(pool: PgPool, config: &QueueConfig) -> Self {
+        Self {
+            pool,
+            poll_interval: config.poll_interval(),
+            visibility_timeout: config.visibility_timeout(),
+            clock: Clock::chrono(),
+        }
+    }
+
+    fn resolve_queue(message: &AsyncMessage) -> &'static str {
+        match message {
+            AsyncMessage::SentMessage(_) => SENT_MESSAGE_QUEUE,
+        }
+    }
+
+    async fn insert(&self, message: AsyncMessage, delay: Duration) -> Result<(), Error> {
+        let queue = Self::resolve_queue(&message);
+        let cx = start_span(
+            format!("{} publish", queue),
+            SpanKind::Producer,
+            &Context::current(),
+        );
+        let run_at = self.clock.now() + chrono::Duration::from_std(delay).unwrap();
+
+        let result = query(
+            r"
+            INSERT INTO jobs (queue, payload, trace_context, run_at, priority)
+            VALUES ($1, $2, $3, $4, $5)
+        ",
+        )
+        .bind(queue)
+        .bind(message.to_value())
+        .bind(json!(inject_map(&cx)))
+        .bind(run_at.naive_utc())
+        .bind(message.priority().rank())
+        .execute(&self.pool)
+        .with_db_span("INSERT jobs")
+        .await;
+
+        end_span(&cx, result.as_ref().err().map(ToString::to_string));
+
+        result.map(|_| ()).map_err(Error::from)
+    }
+}
+
+#[async_trait]
+impl Queue for JobQueue {
+    fn kind(&self) -> &'static str {
+        "jobs"
+    }
+
+    async fn publish(&self, message: AsyncMessage) -> Result<(), Error> {
+        self.insert(message, Duration::ZERO).await
+    }
+
+    async fn publish_delayed(&self, message: AsyncMessage, delay: Duration) -> Result<(), Error> {
+        self.insert(message, delay).await
+    }
+
+    async fn consumer(&self, _consumer_tag: &str) -> Box<dyn QueueConsumer> {
+        Box::new(JobConsumer {
+            pool: self.pool.clone(),
+            queue: SENT_MESSAGE_QUEUE.to_string(),
+            poll_interval: self.poll_interval,
+            visibility_timeout: self.visibility_timeout,
+            clock: Clock::chrono(),
+            scheduler: LaneScheduler::default(),
+        })
+    }
+
+    async fn ping(&self) -> Result<(), String> {
+        query("SELECT 1 FROM jobs LIMIT 1")
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    async fn depth(&self) -> Result<u64, Error> {
+        let (depth,) = query_as::<_, (i64,)>(
+            r"
+            SELECT count(*) FROM jobs
+            WHERE queue = $1
+              AND (locked_until IS NULL OR locked_until <= $2)
+        ",
+        )
+        .bind(SENT_MESSAGE_QUEUE)
+        .bind(self.clock.now().naive_utc())
+        .fetch_one(&self.pool)
+        .with_db_span("SELECT jobs")
+        .await?;
+
+        Ok(depth as u64)
+    }
+
+    async fn close(&self) {}
+}
+
+struct JobConsumer {
+    pool: PgPool,
+    queue: String,
+    poll_interval: Duration,
+    visibility_timeout: Duration,
+    clock: Clock,
+    scheduler: LaneScheduler,
+}
+
+impl JobConsumer {
+    // The preferred lane of this turn goes first and the others follow, so no lane waits while another has jobs
+    async fn fetch(&mut self) -> Result<Option<QueueDelivery>, Error> {
+        for lane in self.scheduler.order() {
+            let delivery = self.fetch_lane(Priority::ALL[lane]).await?;
+
+            if delivery.is_some() {
+                return Ok(delivery);
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn fetch_lane(&self, priority: Priority) -> Result<Option<QueueDelivery>, Error> {
+        let now = self.clock.now();
+        let locked_until = now + chrono::Duration::from_std(self.visibility_timeout).unwrap();
+
+        let job = query_as::<_, (i64, Value, Option<Value>)>(
+            r"
+            UPDATE jobs SET locked_until = $3
+            WHERE id = (
+                SELECT id FROM jobs
+                WHERE queue = $1
+                  AND priority = $4
+                  AND run_at <= $2
+                  AND (locked_until IS NULL OR locked_until <= $2)
+                ORDER BY run_at, id
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, payload, trace_context
+        ",
+        )
+        .bind(&self.queue)
+        .bind(now.naive_utc())
+        .bind(locked_until.naive_utc())
+        .bind(priority.rank())
+        .fetch_optional(&self.pool)
+        .with_db_span("UPDATE jobs")
+        .await?;
+
+        Ok(job.map(|(id, payload, trace_context)| {
+            let trace_context: HashMap<String, String> = trace_context
+                .and_then(|value| serde_json::from_value(value).ok())
+                .unwrap_or_default();
+
+            QueueDelivery::new(
+                payload.to_string().into_bytes(),
+                extract_map(&trace_context),
+                Box::new(JobAcknowledger {
+                    pool: self.pool.clone(),
+                    id,
+                    heartbeat: self.hold(id),
+                }),
+            )
+        }))
+    }
+
+    // Jobs wait in the dispatcher's buffer before they are processed, so their lock is extended
+    // until they are acked, requeued or dropped
+    fn hold(&self, id: i64) -> JoinHandle<()> {
+        let pool = self.pool.clone();
+        let visibility_timeout = self.visibility_timeout;
+
+        tokio::spawn(async move {
+            let clock = Clock::chrono();
+            let mut heartbeat = interval(visibility_timeout / 2);
+            heartbeat.tick().await;
+
+            loop {
+                heartbeat.tick().await;
+
+                let locked_until =
+                    clock.now() + chrono::Duration::from_std(visibility_timeout).unwrap();
+                let result = query(
+                    r"
+                    UPDATE jobs SET locked_until = $2
+                    WHERE id = $1
+                      AND locked_until IS NOT NULL
+                ",
+                )
+                .bind(id)
+                .bind(locked_until.naive_utc())
+                .execute(&pool)
+                .with_db_span("UPDATE jobs")
+                .await;
+
+                if let Err(err) = result {
+                    error!("Lock of job {} cannot be extended: {}", id, err);
+                }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl QueueConsumer for JobConsumer {
+    fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    async fn next(&mut self) -> Option<QueueDelivery> {
+        loop {
+            match self.fetch().await {
+                Ok(Some(delivery)) => return Some(delivery),
+                Ok(None) => {}
+                Err(err) => error!("Jobs cannot be fetched: {:?}", err),
+            }
+
+            sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn cancel(&mut self) {}
+}
+
+struct JobAcknowledger {
+    pool: PgPool,
+    id: i64,
+    heartbeat: JoinHandle<()>,
+}
+
+impl Drop for JobAcknowledger {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+    }
+}
+
+#[async_trait]
+impl Acknowledger for JobAcknowledger {
+    async fn ack(&self) {
+        self.heartbeat.abort();
+
+        let result = query("DELETE FROM jobs WHERE id = $1")
+            .bind(self.id)
+            .execute(&self.pool)
+            .with_db_span("DELETE jobs")
+            .await;
+
+        if let Err(err) = result {
+            error!("Job {} cannot be acked: {}", self.id, err);
+        }
+    }
+
+    async fn requeue(&self) {
+        self.heartbeat.abort();
+
+        let result = query("UPDATE jobs SET locked_until = NULL WHERE id = $1")
+            .bind(self.id)
+            .execute(&self.pool)
+            .with_db_span("UPDATE jobs")
+            .await;
+
+        if let Err(err) = result {
+            error!("Job {} cannot be requeued: {}", self.id, err);
+        }
+    }
+
+    async fn reject(&self) {
+        self.heartbeat.abort();
+
+        let result =
+            query("UPDATE jobs SET queue = queue || $2, locked_until = NULL WHERE id = $1")
+                .bind(self.id)
+                .bind(DEAD_LETTER_SUFFIX)
+                .execute(&self.pool)
+                .with_db_span("UPDATE jobs")
+                .await;
+
+        if let Err(err) = result {
+            error!("Job {} cannot be dead-lettered: {}", self.id, err);
+        }
+    }
+}
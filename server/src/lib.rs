@@ -1,18 +1,31 @@
 pub mod amqp;
 pub mod app;
+pub mod capture;
 pub mod circuit_breaker;
 pub mod cmd;
 pub mod config;
 pub mod configuration;
+pub mod crypto;
 pub mod dispatch_consumer;
+pub mod egress;
 mod error;
 pub mod events;
+pub mod fairness;
 pub mod handlers;
+pub mod health;
+mod http_client;
+pub mod jobs;
 pub mod logs;
+pub mod memory_queue;
+pub mod metrics;
+mod oauth2;
+pub mod queue;
+pub mod retention;
 pub mod retry;
 pub mod routes;
 mod sender;
 pub mod storage;
+pub mod telemetry;
 #[cfg(test)]
 mod tests;
 pub mod time;
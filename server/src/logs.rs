@@ -1,19 +1,285 @@
-use log::LevelFilter;
+use std::cell::RefCell;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::Utc;
+use log::kv::{Key, Value, VisitSource};
+use log::{LevelFilter, Record};
 use log4rs::append::console::ConsoleAppender;
-use log4rs::config::{Appender, Root};
+use log4rs::config::{Appender, Logger, Root};
 use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::{Encode, Write};
 use log4rs::Config;
+use serde_json::{Map, Value as JsonValue};
+
+use crate::config::LogConfig;
+use crate::error::Error;
+use crate::error::Error::InvalidArgument;
+
+const TEXT_PATTERN: &str = "{d(%+)(utc)} [{f}:{L}] {h({l})} {M}:{m}";
+
+static LOG_PAYLOADS: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    static LOG_FIELDS: RefCell<Vec<(&'static str, String)>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(InvalidArgument(format!("'{}' is invalid log format", s))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LogLevels {
+    root: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl FromStr for LogLevels {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut levels = Self {
+            root: LevelFilter::Info,
+            modules: Vec::new(),
+        };
+
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => levels
+                    .modules
+                    .push((module.trim().to_string(), parse_level(level)?)),
+                None => levels.root = parse_level(directive)?,
+            }
+        }
+
+        Ok(levels)
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, Error> {
+    LevelFilter::from_str(level.trim())
+        .map_err(|_| InvalidArgument(format!("'{}' is invalid log level", level.trim())))
+}
+
+pub fn init_log(config: &LogConfig) {
+    let levels: LogLevels = config.levels().parse().unwrap();
+    let format: LogFormat = config.format().parse().unwrap();
+
+    LOG_PAYLOADS.store(config.payloads(), Ordering::Relaxed);
+
+    let encoder: Box<dyn Encode> = match format {
+        LogFormat::Text => Box::new(TextEncoder::default()),
+        LogFormat::Json => Box::new(JsonEncoder),
+    };
+    let stdout = ConsoleAppender::builder().encoder(encoder).build();
 
-pub fn init_log() {
-    let stdout = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(
-            "{d(%+)(utc)} [{f}:{L}] {h({l})} {M}:{m}{n}",
-        )))
-        .build();
     let config = Config::builder()
         .appender(Appender::builder().build("stdout", Box::new(stdout)))
-        .build(Root::builder().appender("stdout").build(LevelFilter::Debug))
+        .loggers(
+            levels
+                .modules
+                .into_iter()
+                .map(|(module, level)| Logger::builder().build(module, level)),
+        )
+        .build(Root::builder().appender("stdout").build(levels.root))
         .unwrap();
 
     log4rs::init_config(config).unwrap();
 }
+
+pub fn payloads_enabled() -> bool {
+    LOG_PAYLOADS.load(Ordering::Relaxed)
+}
+
+pub async fn with_log_fields<F>(future: F) -> F::Output
+where
+    F: Future,
+{
+    LOG_FIELDS.scope(RefCell::new(Vec::new()), future).await
+}
+
+pub fn add_log_field(key: &'static str, value: impl ToString) {
+    let _ = LOG_FIELDS.try_with(|fields| {
+        let mut fields = fields.borrow_mut();
+        let value = value.to_string();
+
+        match fields.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => *existing = value,
+            None => fields.push((key, value)),
+        }
+    });
+}
+
+fn fields(record: &Record) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = LOG_FIELDS
+        .try_with(|fields| {
+            fields
+                .borrow()
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut visitor = FieldsVisitor(Vec::new());
+    let _ = record.key_values().visit(&mut visitor);
+
+    for (key, value) in visitor.0 {
+        fields.retain(|(k, _)| k != &key);
+        fields.push((key, value));
+    }
+
+    fields
+}
+
+struct FieldsVisitor(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for FieldsVisitor {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        self.0.push((key.to_string(), value.to_string()));
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct TextEncoder(PatternEncoder);
+
+impl Default for TextEncoder {
+    fn default() -> Self {
+        Self(PatternEncoder::new(TEXT_PATTERN))
+    }
+}
+
+impl Encode for TextEncoder {
+    fn encode(&self, w: &mut dyn Write, record: &Record) -> anyhow::Result<()> {
+        self.0.encode(w, record)?;
+
+        for (key, value) in fields(record) {
+            write!(w, " {}={}", key, value)?;
+        }
+
+        writeln!(w)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct JsonEncoder;
+
+impl JsonEncoder {
+    fn to_json(record: &Record) -> JsonValue {
+        let mut line = Map::new();
+
+        line.insert("time".to_string(), Utc::now().to_rfc3339().into());
+        line.insert("level".to_string(), record.level().as_str().into());
+        line.insert("target".to_string(), record.target().into());
+        line.insert("message".to_string(), record.args().to_string().into());
+
+        for (key, value) in fields(record) {
+            line.insert(key, value.into());
+        }
+
+        JsonValue::Object(line)
+    }
+}
+
+impl Encode for JsonEncoder {
+    fn encode(&self, w: &mut dyn Write, record: &Record) -> anyhow::Result<()> {
+        writeln!(w, "{}", Self::to_json(record))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, LevelFilter, Record};
+    use test_case::test_case;
+
+    use crate::error::Error::InvalidArgument;
+    use crate::logs::{add_log_field, with_log_fields, JsonEncoder, LogFormat, LogLevels};
+
+    #[test]
+    fn levels_are_parsed_per_module() {
+        let levels: LogLevels = "warn, server::dispatch_consumer=debug,lapin=error"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            LogLevels {
+                root: LevelFilter::Warn,
+                modules: vec![
+                    ("server::dispatch_consumer".to_string(), LevelFilter::Debug),
+                    ("lapin".to_string(), LevelFilter::Error),
+                ],
+            },
+            levels
+        );
+    }
+
+    #[test]
+    fn invalid_level_is_error() {
+        assert_eq!(
+            Err(InvalidArgument("'loud' is invalid log level".to_string())),
+            "lapin=loud".parse::<LogLevels>()
+        );
+    }
+
+    #[test_case("text", LogFormat::Text)]
+    #[test_case("JSON", LogFormat::Json)]
+    fn format_is_parsed(value: &str, expected: LogFormat) {
+        assert_eq!(Ok(expected), value.parse::<LogFormat>());
+    }
+
+    #[tokio::test]
+    async fn json_line_contains_record_and_context_fields() {
+        let kv = [("attempt", 2)];
+
+        let json = with_log_fields(async {
+            add_log_field("app_id", "app_1");
+            add_log_field("msg_id", "msg_1");
+
+            JsonEncoder::to_json(
+                &Record::builder()
+                    .level(Level::Info)
+                    .target("server::dispatch_consumer")
+                    .args(format_args!("message consumed"))
+                    .key_values(&kv)
+                    .build(),
+            )
+        })
+        .await;
+
+        assert_eq!("INFO", json["level"]);
+        assert_eq!("message consumed", json["message"]);
+        assert_eq!("app_1", json["app_id"]);
+        assert_eq!("msg_1", json["msg_id"]);
+        assert_eq!("2", json["attempt"]);
+    }
+
+    #[test]
+    fn fields_are_not_added_outside_of_scope() {
+        add_log_field("app_id", "app_1");
+
+        let json = JsonEncoder::to_json(&Record::builder().args(format_args!("ready")).build());
+
+        assert!(json.get("app_id").is_none());
+    }
+}
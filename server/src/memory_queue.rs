@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::error;
+use opentelemetry::Context;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+use crate::cmd::AsyncMessage;
+use crate::error::Error;
+use crate::queue::{Acknowledger, Queue, QueueConsumer, QueueDelivery};
+
+const SENT_MESSAGE_QUEUE: &str = "sent_message";
+
+#[derive(Default)]
+struct Messages {
+    ready: Mutex<VecDeque<Vec<u8>>>,
+    published: Notify,
+}
+
+impl Messages {
+    fn push(&self, data: Vec<u8>) {
+        self.ready.lock().unwrap().push_back(data);
+        self.published.notify_one();
+    }
+
+    async fn pop(&self) -> Vec<u8> {
+        loop {
+            let data = self.ready.lock().unwrap().pop_front();
+            if let Some(data) = data {
+                return data;
+            }
+
+            self.published.notified().await;
+        }
+    }
+}
+
+// Messages are lost when the process stops, so it only suits development and tests
+#[derive(Default)]
+pub struct InMemoryQueue {
+    messages: Arc<Messages>,
+}
+
+impl InMemoryQueue {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Queue for InMemoryQueue {
+    fn kind(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn publish(&self, message: AsyncMessage) -> Result<(), Error> {
+        self.messages.push(message.encode());
+
+        Ok(())
+    }
+
+    async fn publish_delayed(&self, message: AsyncMessage, delay: Duration) -> Result<(), Error> {
+        let messages = self.messages.clone();
+        let data = message.encode();
+
+        tokio::spawn(async move {
+            sleep(delay).await;
+            messages.push(data);
+        });
+
+        Ok(())
+    }
+
+    async fn consumer(&self, _consumer_tag: &str) -> Box<dyn QueueConsumer> {
+        Box::new(InMemoryConsumer {
+            messages: self.messages.clone(),
+        })
+    }
+
+    async fn ping(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn depth(&self) -> Result<u64, Error> {
+        Ok(self.messages.ready.lock().unwrap().len() as u64)
+    }
+
+    async fn close(&self) {}
+}
+
+struct InMemoryConsumer {
+    messages: Arc<Messages>,
+}
+
+#[async_trait]
+impl QueueConsumer for InMemoryConsumer {
+    fn queue(&self) -> &str {
+        SENT_MESSAGE_QUEUE
+    }
+
+    async fn next(&mut self) -> Option<QueueDelivery> {
+        let data = self.messages.pop().await;
+
+        Some(QueueDelivery::new(
+            data.clone(),
+            Context::new(),
+            Box::new(InMemoryAcknowledger {
+                messages: self.messages.clone(),
+                data,
+            }),
+        ))
+    }
+
+    async fn cancel(&mut self) {}
+}
+
+struct InMemoryAcknowledger {
+    messages: Arc<Messages>,
+    data: Vec<u8>,
+}
+
+#[async_trait]
+impl Acknowledger for InMemoryAcknowledger {
+    async fn ack(&self) {}
+
+    async fn requeue(&self) {
+        self.messages.push(self.data.clone());
+    }
+
+    async fn reject(&self) {
+        error!(
+            "Rejected message is dropped: {}",
+            String::from_utf8_lossy(&self.data)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use crate::cmd::{AsyncMessage, SentMessage};
+    use crate::memory_queue::InMemoryQueue;
+    use crate::queue::Queue;
+    use crate::types::MessageId;
+
+    #[tokio::test]
+    async fn requeued_message_is_consumed_again() {
+        let queue = InMemoryQueue::new();
+        let message = AsyncMessage::SentMessage(SentMessage::new(MessageId::new()));
+        let data = message.encode();
+        let mut consumer = queue.consumer("test").await;
+
+        queue.publish(message).await.unwrap();
+        assert_eq!(1, queue.depth().await.unwrap());
+
+        consumer.next().await.unwrap().requeue().await;
+        let delivery = consumer.next().await.unwrap();
+        delivery.ack().await;
+
+        assert_eq!(data, delivery.data);
+        assert_eq!(0, queue.depth().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn delayed_message_is_consumed_when_due() {
+        let queue = InMemoryQueue::new();
+        let message = AsyncMessage::SentMessage(SentMessage::new(MessageId::new()));
+        let mut consumer = queue.consumer("test").await;
+
+        queue
+            .publish_delayed(message, Duration::from_millis(50))
+            .await
+            .unwrap();
+
+        assert!(timeout(Duration::from_millis(10), consumer.next())
+            .await
+            .is_err());
+        assert!(timeout(Duration::from_millis(200), consumer.next())
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,233 @@
+use lazy_static::lazy_static;
+use prometheus::core::Collector;
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+
+use crate::circuit_breaker::State;
+use crate::events::domain::{AttemptLog, Event};
+use crate::retention::PurgeStats;
+use crate::sender::Status;
+use crate::types::ApplicationId;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+lazy_static! {
+    static ref REGISTRY: Registry =
+        Registry::new_custom(Some("webhooks".to_string()), None).unwrap();
+    static ref EVENTS_INGESTED: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new("events_ingested_total", "Events accepted by the server"),
+            &["app_id", "topic"],
+        )
+        .unwrap()
+    );
+    static ref MESSAGES_FANNED_OUT: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new(
+                "messages_fanned_out_total",
+                "Messages created for endpoints"
+            ),
+            &["app_id"],
+        )
+        .unwrap()
+    );
+    static ref DELIVERY_ATTEMPTS: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new(
+                "delivery_attempts_total",
+                "Delivery attempts by status class"
+            ),
+            &["status_class"],
+        )
+        .unwrap()
+    );
+    static ref RESPONSE_TIME: Histogram = register(
+        Histogram::with_opts(HistogramOpts::new(
+            "response_time_seconds",
+            "Time of waiting for the endpoint response"
+        ))
+        .unwrap()
+    );
+    static ref PROCESSING_TIME: Histogram = register(
+        Histogram::with_opts(
+            HistogramOpts::new(
+                "processing_time_seconds",
+                "Time from event occurrence to delivery attempt"
+            )
+            .buckets(exponential_buckets(0.01, 4.0, 10).unwrap())
+        )
+        .unwrap()
+    );
+    static ref QUEUE_DEPTH: IntGauge = register(
+        IntGauge::new(
+            "queue_depth",
+            "Messages waiting in the queue, measured when metrics are scraped"
+        )
+        .unwrap()
+    );
+    static ref MESSAGES_DEFERRED: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new(
+                "messages_deferred_total",
+                "Messages postponed because their application reached its concurrency"
+            ),
+            &["app_id"],
+        )
+        .unwrap()
+    );
+    static ref CIRCUIT_BREAKER_TRANSITIONS: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new(
+                "circuit_breaker_transitions_total",
+                "Circuit breaker state changes of endpoints"
+            ),
+            &["state"],
+        )
+        .unwrap()
+    );
+    static ref PURGED_RECORDS: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new(
+                "purged_records_total",
+                "Records deleted or cleared by the retention purger"
+            ),
+            &["kind"],
+        )
+        .unwrap()
+    );
+    static ref LAST_PURGE_RECORDS: IntGaugeVec = register(
+        IntGaugeVec::new(
+            Opts::new(
+                "last_purge_records",
+                "Records deleted or cleared by the latest purger run"
+            ),
+            &["kind"],
+        )
+        .unwrap()
+    );
+}
+
+fn register<T>(collector: T) -> T
+where
+    T: Collector + Clone + 'static,
+{
+    REGISTRY.register(Box::new(collector.clone())).unwrap();
+
+    collector
+}
+
+pub fn event_ingested(event: &Event) {
+    EVENTS_INGESTED
+        .with_label_values(&[&event.app_id.to_string(), &event.topic.to_string()])
+        .inc();
+}
+
+pub fn message_fanned_out(event: &Event) {
+    MESSAGES_FANNED_OUT
+        .with_label_values(&[&event.app_id.to_string()])
+        .inc();
+}
+
+pub fn attempt_recorded(status: &Status, log: &AttemptLog) {
+    DELIVERY_ATTEMPTS
+        .with_label_values(&[&status.class()])
+        .inc();
+    RESPONSE_TIME.observe(log.response_time().as_secs_f64());
+    PROCESSING_TIME.observe(log.processing_time().as_secs_f64());
+}
+
+pub fn queue_depth_measured(depth: u64) {
+    QUEUE_DEPTH.set(depth as i64);
+}
+
+pub fn message_deferred(app_id: &ApplicationId) {
+    MESSAGES_DEFERRED
+        .with_label_values(&[&app_id.to_string()])
+        .inc();
+}
+
+pub fn circuit_breaker_changed(state: State) {
+    let state = match state {
+        State::Closed => "closed",
+        State::Open => "open",
+    };
+
+    CIRCUIT_BREAKER_TRANSITIONS
+        .with_label_values(&[state])
+        .inc();
+}
+
+pub fn data_purged(stats: &PurgeStats) {
+    let records = [
+        ("events", stats.events),
+        ("messages", stats.messages),
+        ("attempts", stats.attempts),
+        ("attempt_logs", stats.attempt_logs),
+        ("payloads", stats.payloads),
+        ("response_bodies", stats.response_bodies),
+    ];
+
+    for (kind, count) in records {
+        PURGED_RECORDS.with_label_values(&[kind]).inc_by(count);
+        LAST_PURGE_RECORDS
+            .with_label_values(&[kind])
+            .set(count as i64);
+    }
+}
+
+pub fn render() -> String {
+    lazy_static::initialize(&EVENTS_INGESTED);
+    lazy_static::initialize(&MESSAGES_FANNED_OUT);
+    lazy_static::initialize(&DELIVERY_ATTEMPTS);
+    lazy_static::initialize(&RESPONSE_TIME);
+    lazy_static::initialize(&PROCESSING_TIME);
+    lazy_static::initialize(&QUEUE_DEPTH);
+    lazy_static::initialize(&MESSAGES_DEFERRED);
+    lazy_static::initialize(&CIRCUIT_BREAKER_TRANSITIONS);
+    lazy_static::initialize(&PURGED_RECORDS);
+    lazy_static::initialize(&LAST_PURGE_RECORDS);
+
+    let mut buffer = Vec::new();
+
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .unwrap();
+
+    String::from_utf8(buffer).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::circuit_breaker::State;
+    use crate::metrics::{circuit_breaker_changed, data_purged, render};
+    use crate::retention::PurgeStats;
+
+    #[test]
+    fn metrics_are_rendered_in_prometheus_format() {
+        circuit_breaker_changed(State::Closed);
+
+        let metrics = render();
+
+        assert!(metrics.contains("# TYPE webhooks_circuit_breaker_transitions_total counter"));
+        assert!(metrics.contains("webhooks_circuit_breaker_transitions_total{state=\"closed\"}"));
+        assert!(metrics.contains("# TYPE webhooks_processing_time_seconds histogram"));
+    }
+
+    #[test]
+    fn purged_records_are_counted_per_kind() {
+        data_purged(&PurgeStats {
+            messages: 3,
+            payloads: 2,
+            ..PurgeStats::default()
+        });
+
+        let metrics = render();
+
+        assert!(metrics.contains("# TYPE webhooks_purged_records_total counter"));
+        assert!(metrics.contains("webhooks_last_purge_records{kind=\"messages\"} 3"));
+        assert!(metrics.contains("webhooks_last_purge_records{kind=\"payloads\"} 2"));
+        assert!(metrics.contains("webhooks_last_purge_records{kind=\"events\"} 0"));
+    }
+}
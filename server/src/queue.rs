@@ -0,0 +1,198 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use opentelemetry::Context;
+use sqlx::PgPool;
+
+use crate::amqp::AmqpQueue;
+use crate::cmd::AsyncMessage;
+use crate::config::{AMQPConfig, QueueConfig};
+use crate::configuration::domain::Priority;
+use crate::error::Error;
+use crate::error::Error::InvalidArgument;
+use crate::jobs::JobQueue;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueueBackend {
+    Amqp,
+    Postgres,
+}
+
+impl FromStr for QueueBackend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "amqp" | "rabbitmq" => Ok(Self::Amqp),
+            "postgres" => Ok(Self::Postgres),
+            _ => Err(InvalidArgument(format!("'{}' is invalid queue backend", s))),
+        }
+    }
+}
+
+#[async_trait]
+pub trait Queue: Send + Sync {
+    fn kind(&self) -> &'static str;
+
+    async fn publish(&self, message: AsyncMessage) -> Result<(), Error>;
+
+    async fn publish_delayed(&self, message: AsyncMessage, delay: Duration) -> Result<(), Error>;
+
+    async fn consumer(&self, consumer_tag: &str) -> Box<dyn QueueConsumer>;
+
+    async fn ping(&self) -> Result<(), String>;
+
+    async fn depth(&self) -> Result<u64, Error>;
+
+    async fn close(&self);
+}
+
+#[async_trait]
+pub trait QueueConsumer: Send {
+    fn queue(&self) -> &str;
+
+    async fn next(&mut self) -> Option<QueueDelivery>;
+
+    async fn cancel(&mut self);
+}
+
+#[async_trait]
+pub trait Acknowledger: Send + Sync {
+    async fn ack(&self);
+
+    async fn requeue(&self);
+
+    async fn reject(&self);
+}
+
+pub struct QueueDelivery {
+    pub data: Vec<u8>,
+    pub parent: Context,
+    acknowledger: Box<dyn Acknowledger>,
+}
+
+impl QueueDelivery {
+    pub fn new(data: Vec<u8>, parent: Context, acknowledger: Box<dyn Acknowledger>) -> Self {
+        Self {
+            data,
+            parent,
+            acknowledger,
+        }
+    }
+
+    #[must_use]
+    pub fn map_acknowledger(
+        self,
+        f: impl FnOnce(Box<dyn Acknowledger>) -> Box<dyn Acknowledger>,
+    ) -> Self {
+        Self {
+            acknowledger: f(self.acknowledger),
+            ..self
+        }
+    }
+
+    pub async fn ack(&self) {
+        self.acknowledger.ack().await
+    }
+
+    pub async fn requeue(&self) {
+        self.acknowledger.requeue().await
+    }
+
+    pub async fn reject(&self) {
+        self.acknowledger.reject().await
+    }
+}
+
+pub async fn connect_queue(
+    config: &QueueConfig,
+    amqp_config: AMQPConfig,
+    pool: PgPool,
+) -> Arc<dyn Queue> {
+    let backend: QueueBackend = config.backend().parse().unwrap();
+
+    match backend {
+        QueueBackend::Amqp => Arc::new(AmqpQueue::connect(amqp_config).await),
+        QueueBackend::Postgres => Arc::new(JobQueue::new(pool, config)),
+    }
+}
+
+fn lane_weight(priority: Priority) -> i32 {
+    match priority {
+        Priority::High => 4,
+        Priority::Normal => 2,
+        Priority::Low => 1,
+    }
+}
+
+// Smooth weighted round-robin over lanes of Priority::ALL, so a busy lane cannot starve the lanes below it
+#[derive(Default)]
+pub(crate) struct LaneScheduler {
+    credits: [i32; 3],
+}
+
+impl LaneScheduler {
+    pub(crate) fn order(&mut self) -> [usize; 3] {
+        let total: i32 = Priority::ALL.into_iter().map(lane_weight).sum();
+
+        for (credit, priority) in self.credits.iter_mut().zip(Priority::ALL) {
+            *credit += lane_weight(priority);
+        }
+
+        let mut order = [0, 1, 2];
+        order.sort_by_key(|&lane| std::cmp::Reverse(self.credits[lane]));
+        self.credits[order[0]] -= total;
+
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::configuration::domain::Priority;
+    use crate::error::Error::InvalidArgument;
+    use crate::queue::{LaneScheduler, QueueBackend};
+
+    #[test_case("amqp", QueueBackend::Amqp)]
+    #[test_case("RabbitMQ", QueueBackend::Amqp)]
+    #[test_case(" postgres ", QueueBackend::Postgres)]
+    fn backend_is_parsed(value: &str, expected: QueueBackend) {
+        assert_eq!(Ok(expected), value.parse::<QueueBackend>());
+    }
+
+    #[test]
+    fn unknown_backend_is_error() {
+        assert_eq!(
+            Err(InvalidArgument(
+                "'kafka' is invalid queue backend".to_string()
+            )),
+            "kafka".parse::<QueueBackend>()
+        );
+    }
+
+    #[test]
+    fn lanes_are_preferred_by_weight() {
+        let mut scheduler = LaneScheduler::default();
+
+        let preferred: Vec<Priority> = (0..7)
+            .map(|_| Priority::ALL[scheduler.order()[0]])
+            .collect();
+
+        assert_eq!(
+            vec![
+                Priority::High,
+                Priority::Normal,
+                Priority::High,
+                Priority::Low,
+                Priority::High,
+                Priority::Normal,
+                Priority::High,
+            ],
+            preferred
+        );
+    }
+}
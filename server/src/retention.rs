@@ -0,0 +1,214 @@
+use std::fmt::{Display, Formatter};
+
+use log::{error, info};
+use sqlx::{query_as, PgPool};
+use tokio::time::interval;
+
+use crate::config::RetentionConfig;
+use crate::error::Error;
+use crate::metrics;
+use crate::time::Clock;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PurgeStats {
+    pub events: u64,
+    pub messages: u64,
+    pub attempts: u64,
+    pub attempt_logs: u64,
+    pub payloads: u64,
+    pub response_bodies: u64,
+}
+
+impl PurgeStats {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Display for PurgeStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "events: {}, messages: {}, attempts: {}, attempt logs: {}, payloads: {}, response bodies: {}",
+            self.events,
+            self.messages,
+            self.attempts,
+            self.attempt_logs,
+            self.payloads,
+            self.response_bodies
+        )
+    }
+}
+
+pub struct Purger {
+    pool: PgPool,
+    config: RetentionConfig,
+    clock: Clock,
+}
+
+impl Purger {
+    pub fn new(pool: PgPool, config: RetentionConfig) -> Self {
+        Self {
+            pool,
+            config,
+            clock: Clock::chrono(),
+        }
+    }
+
+    pub async fn run(&self) {
+        let mut interval = interval(self.config.interval());
+
+        loop {
+            interval.tick().await;
+
+            match self.purge().await {
+                Ok(stats) => {
+                    metrics::data_purged(&stats);
+
+                    if stats.is_empty() {
+                        info!("Nothing to purge");
+                    } else {
+                        info!("Purged expired data ({})", stats);
+                    }
+                }
+                Err(err) => error!("Purging expired data failed: {:?}", err),
+            }
+        }
+    }
+
+    pub async fn purge(&self) -> Result<PurgeStats, Error> {
+        let mut stats = PurgeStats::default();
+        let batch_size = u64::from(self.config.batch_size());
+
+        loop {
+            let (events, messages, attempts, attempt_logs) = self.delete_expired_events().await?;
+
+            stats.events += events;
+            stats.messages += messages;
+            stats.attempts += attempts;
+            stats.attempt_logs += attempt_logs;
+
+            if events < batch_size {
+                break;
+            }
+        }
+
+        loop {
+            let (payloads, response_bodies) = self.clear_expired_payloads().await?;
+
+            stats.payloads += payloads;
+            stats.response_bodies += response_bodies;
+
+            if payloads < batch_size {
+                break;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    async fn delete_expired_events(&self) -> Result<(u64, u64, u64, u64), Error> {
+        let (events, messages, attempts, attempt_logs) = query_as::<_, (i64, i64, i64, i64)>(
+            r"
+            WITH expired AS (
+                SELECT e.id FROM events e
+                JOIN applications a ON a.id = e.app_id
+                WHERE COALESCE(a.retention_days, $1) IS NOT NULL
+                  AND GREATEST(e.created_at, COALESCE(e.deliver_at, e.created_at))
+                      < $2 - make_interval(days => COALESCE(a.retention_days, $1))
+                ORDER BY e.created_at
+                LIMIT $3
+                FOR UPDATE OF e SKIP LOCKED
+            ),
+            deleted_messages AS (
+                DELETE FROM messages m USING expired WHERE m.event_id = expired.id RETURNING m.id
+            ),
+            deleted_attempts AS (
+                DELETE FROM attempts t USING deleted_messages WHERE t.message_id = deleted_messages.id RETURNING 1
+            ),
+            deleted_attempt_logs AS (
+                DELETE FROM attempt_logs l USING deleted_messages WHERE l.message_id = deleted_messages.id RETURNING 1
+            ),
+            deleted_events AS (
+                DELETE FROM events e USING expired WHERE e.id = expired.id RETURNING 1
+            )
+            SELECT
+                (SELECT COUNT(*) FROM deleted_events),
+                (SELECT COUNT(*) FROM deleted_messages),
+                (SELECT COUNT(*) FROM deleted_attempts),
+                (SELECT COUNT(*) FROM deleted_attempt_logs)
+            ",
+        )
+        .bind(self.config.days().map(|days| days as i32))
+        .bind(self.clock.now().naive_utc())
+        .bind(i64::from(self.config.batch_size()))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((
+            events as u64,
+            messages as u64,
+            attempts as u64,
+            attempt_logs as u64,
+        ))
+    }
+
+    async fn clear_expired_payloads(&self) -> Result<(u64, u64), Error> {
+        let (payloads, response_bodies) = query_as::<_, (i64, i64)>(
+            r"
+            WITH expired AS (
+                SELECT e.id FROM events e
+                JOIN applications a ON a.id = e.app_id
+                WHERE e.payload IS NOT NULL
+                  AND COALESCE(a.payload_retention_days, $1) IS NOT NULL
+                  AND GREATEST(e.created_at, COALESCE(e.deliver_at, e.created_at))
+                      < $2 - make_interval(days => COALESCE(a.payload_retention_days, $1))
+                ORDER BY e.created_at
+                LIMIT $3
+                FOR UPDATE OF e SKIP LOCKED
+            ),
+            cleared_payloads AS (
+                UPDATE events e SET payload = NULL FROM expired WHERE e.id = expired.id RETURNING e.id
+            ),
+            cleared_response_bodies AS (
+                UPDATE attempt_logs l SET response_body = NULL
+                FROM messages m, cleared_payloads c
+                WHERE m.event_id = c.id AND l.message_id = m.id AND l.response_body IS NOT NULL
+                RETURNING 1
+            )
+            SELECT
+                (SELECT COUNT(*) FROM cleared_payloads),
+                (SELECT COUNT(*) FROM cleared_response_bodies)
+            ",
+        )
+        .bind(self.config.payload_days().map(|days| days as i32))
+        .bind(self.clock.now().naive_utc())
+        .bind(i64::from(self.config.batch_size()))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((payloads as u64, response_bodies as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::retention::PurgeStats;
+
+    #[test]
+    fn stats_are_displayed() {
+        let stats = PurgeStats {
+            events: 1,
+            messages: 2,
+            attempts: 3,
+            attempt_logs: 4,
+            payloads: 5,
+            response_bodies: 6,
+        };
+
+        assert_eq!(
+            "events: 1, messages: 2, attempts: 3, attempt logs: 4, payloads: 5, response bodies: 6",
+            stats.to_string()
+        );
+    }
+}
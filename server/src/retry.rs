@@ -1,7 +1,11 @@
+use std::future::Future;
 use std::time::Duration;
 
+use log::warn;
 use rand::{thread_rng, Rng};
+use tokio::time::sleep;
 
+use crate::error::Error;
 use crate::retry::RetryPolicyConfig::{Constant, Exponential};
 
 type ShouldRetryPolicyType = dyn ShouldRetryPolicy + Sync + Send;
@@ -250,15 +254,39 @@ impl Default for RetryPolicyBuilder {
     }
 }
 
+pub async fn retry_transient<T, F, Fut>(retry: &Retry, mut operation: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        match operation().await {
+            Err(err) if err.is_transient() && retry.is_retryable(attempt) => {
+                let delay = retry.get_waiting_time(attempt);
+                warn!("Transient failure, retrying in {:?}: {:?}", delay, err);
+
+                sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::time::Duration;
 
     use test_case::test_case;
 
+    use crate::error::Error;
     use crate::retry::{
-        ConstantConfig, ConstantRetryPolicy, ExponentialConfig, ExponentialRetryPolicy,
-        RandomGenerator, RandomGeneratorType, RandomizeDecoratedRetryPolicy, RetryPolicy,
+        retry_transient, ConstantConfig, ConstantRetryPolicy, ExponentialConfig,
+        ExponentialRetryPolicy, RandomGenerator, RandomGeneratorType,
+        RandomizeDecoratedRetryPolicy, RetryPolicy, RetryPolicyBuilder,
     };
 
     // todo write tests for builder with the same cases
@@ -309,6 +337,39 @@ mod tests {
         RandomizeDecoratedRetryPolicy::new(random_generator, constant, factor)
     }
 
+    #[test_case(Error::Connection("connection reset".to_string()), 3, Ok(()); "transient failure is retried")]
+    #[test_case(Error::Queue("channel closed".to_string()), 5, Err(Error::Queue("channel closed".to_string())); "retries are exhausted")]
+    #[test_case(Error::EntityNotFound("Entity not found".to_string()), 1, Err(Error::EntityNotFound("Entity not found".to_string())); "permanent failure is not retried")]
+    #[tokio::test]
+    async fn transient_failures_are_retried(
+        error: Error,
+        expected_calls: usize,
+        expected: Result<(), Error>,
+    ) {
+        let retry = RetryPolicyBuilder::new()
+            .max_retries(4)
+            .constant(Duration::from_millis(1))
+            .build()
+            .unwrap();
+        let calls = AtomicUsize::new(0);
+
+        let result = retry_transient(&retry, || async {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+
+            match &error {
+                Error::Connection(_) if call >= 2 => Ok(()),
+                Error::Connection(msg) => Err(Error::Connection(msg.clone())),
+                Error::Queue(msg) => Err(Error::Queue(msg.clone())),
+                Error::EntityNotFound(msg) => Err(Error::EntityNotFound(msg.clone())),
+                _ => unreachable!(),
+            }
+        })
+        .await;
+
+        assert_eq!(expected, result);
+        assert_eq!(expected_calls, calls.load(Ordering::SeqCst));
+    }
+
     struct MinRandomGenerator {}
 
     impl RandomGenerator for MinRandomGenerator {
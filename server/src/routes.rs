@@ -2,18 +2,24 @@ use actix_web::web;
 
 use crate::configuration::handlers::{
     create_application_handler, create_endpoint_handler, disable_endpoint_handler,
-    enable_endpoint_handler,
+    enable_endpoint_handler, endpoint_stats_handler, update_endpoint_handler,
 };
-use crate::events::handlers::create_event_handler;
-use crate::handlers::health_check::health_check;
+use crate::events::handlers::{cancel_event_handler, create_event_handler};
+use crate::handlers::health_check::{health_check, readiness};
 
 pub fn routes(cfg: &mut web::ServiceConfig) {
     cfg.route("/health_check", web::get().to(health_check));
+    cfg.route("/health/live", web::get().to(health_check));
+    cfg.route("/health/ready", web::get().to(readiness));
     cfg.route("/application", web::post().to(create_application_handler));
     cfg.route(
         "/application/{app_id}/endpoint",
         web::post().to(create_endpoint_handler),
     );
+    cfg.route(
+        "/application/{app_id}/endpoint/{endpoint_id}",
+        web::patch().to(update_endpoint_handler),
+    );
     cfg.route(
         "/application/{app_id}/endpoint/{endpoint_id}/disable",
         web::post().to(disable_endpoint_handler),
@@ -22,8 +28,16 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
         "/application/{app_id}/endpoint/{endpoint_id}/enable",
         web::post().to(enable_endpoint_handler),
     );
+    cfg.route(
+        "/application/{app_id}/endpoint/{endpoint_id}/stats",
+        web::get().to(endpoint_stats_handler),
+    );
     cfg.route(
         "application/{app_id}/event",
         web::post().to(create_event_handler),
     );
+    cfg.route(
+        "application/{app_id}/event/{event_id}",
+        web::delete().to(cancel_event_handler),
+    );
 }
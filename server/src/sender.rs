@@ -1,22 +1,72 @@
 use std::time::{Duration, Instant};
 
 use log::debug;
+use opentelemetry::trace::{SpanKind, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
 use reqwest::StatusCode;
+use serde_json::Value as JsonValue;
 use sqlx::postgres::PgRow;
 use sqlx::{Error, FromRow, Row};
 use url::Url;
 
+use crate::capture::{CapturePolicy, CapturedResponse};
+use crate::egress::{BlockedDestination, EgressPolicy};
 use crate::events::domain::Payload;
-use crate::sender::Status::{Numeric, Unknown};
+use crate::sender::Status::{
+    AuthFailed, Blocked, Expired, Misconfigured, Numeric, Timeout, Unknown,
+};
+use crate::telemetry::{end_span, inject_http_headers, start_span};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Status {
     Numeric(u16),
     Unknown(String),
+    AuthFailed(String),
+    Timeout(String),
+    Blocked(String),
+    Expired(String),
+    Misconfigured(String),
+}
+
+impl Status {
+    pub fn kind(&self) -> Option<&'static str> {
+        match self {
+            Numeric(_) | Unknown(_) => None,
+            AuthFailed(_) => Some("auth_failed"),
+            Timeout(_) => Some("timeout"),
+            Blocked(_) => Some("blocked"),
+            Expired(_) => Some("expired"),
+            Misconfigured(_) => Some("misconfigured"),
+        }
+    }
+
+    pub fn class(&self) -> String {
+        match self {
+            Numeric(code) => format!("{}xx", code / 100),
+            status => status.kind().unwrap_or("unknown").to_string(),
+        }
+    }
 }
 
 impl FromRow<'_, PgRow> for Status {
     fn from_row(row: &'_ PgRow) -> Result<Self, Error> {
+        let kind: Option<String> = row.try_get("status_kind")?;
+        if let Some(kind) = kind {
+            let message: String = row
+                .try_get::<Option<String>, _>("status_unknown")?
+                .unwrap_or_default();
+
+            match kind.as_str() {
+                "auth_failed" => return Ok(AuthFailed(message)),
+                "timeout" => return Ok(Timeout(message)),
+                "blocked" => return Ok(Blocked(message)),
+                "expired" => return Ok(Expired(message)),
+                "misconfigured" => return Ok(Misconfigured(message)),
+                _ => {}
+            }
+        }
+
         let numeric: Option<i16> = row.try_get("status_numeric")?;
         if let Some(val) = numeric {
             return Ok(Numeric(val as u16));
@@ -35,14 +85,16 @@ pub struct SentResult {
     pub status: Status,
     pub response_time: Duration,
     pub body: Option<String>,
+    pub headers: Option<JsonValue>,
 }
 
 impl SentResult {
-    fn with_body(status: Status, response_time: Duration, body: String) -> Self {
+    fn with_response(status: Status, response_time: Duration, response: CapturedResponse) -> Self {
         Self {
             status,
             response_time,
-            body: Some(body),
+            body: Some(response.body),
+            headers: Some(response.headers),
         }
     }
 
@@ -51,56 +103,184 @@ impl SentResult {
             status,
             response_time,
             body: None,
+            headers: None,
         }
     }
+
+    pub fn auth_failed(message: String) -> Self {
+        Self::without_body(AuthFailed(message), Duration::ZERO)
+    }
+
+    pub fn expired(message: String) -> Self {
+        Self::without_body(Expired(message), Duration::ZERO)
+    }
+
+    pub fn misconfigured(message: String) -> Self {
+        Self::without_body(Misconfigured(message), Duration::ZERO)
+    }
+
+    pub fn is_unauthorized(&self) -> bool {
+        self.status == Numeric(401)
+    }
 }
 
+#[derive(Clone)]
 pub struct Sender {
     payload: Payload,
     url: Url,
+    headers: HeaderMap,
+    client: Option<reqwest::Client>,
+    timeout: Option<Duration>,
+    egress: Option<EgressPolicy>,
+    proxied: bool,
+    capture: CapturePolicy,
 }
 
 impl Sender {
     #[must_use]
     pub fn new(payload: Payload, url: Url) -> Self {
-        Self { payload, url }
+        Self {
+            payload,
+            url,
+            headers: HeaderMap::new(),
+            client: None,
+            timeout: None,
+            egress: None,
+            proxied: false,
+            capture: CapturePolicy::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    #[must_use]
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    #[must_use]
+    pub fn with_egress_policy(mut self, egress: EgressPolicy) -> Self {
+        self.egress = Some(egress);
+        self
+    }
+
+    #[must_use]
+    pub fn with_proxy(mut self, proxied: bool) -> Self {
+        self.proxied = proxied;
+        self
+    }
+
+    #[must_use]
+    pub fn with_capture_policy(mut self, capture: CapturePolicy) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    pub fn with_bearer_token(mut self, token: &str) -> Result<Self, String> {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| "Access token is not valid header value".to_string())?;
+        value.set_sensitive(true);
+
+        self.headers.insert(AUTHORIZATION, value);
+        Ok(self)
     }
 
     pub async fn send(&self) -> Result<SentResult, SentResult> {
+        if let Some(egress) = &self.egress {
+            let checked = if self.proxied {
+                egress.check_resolved_url(&self.url).await
+            } else {
+                egress.check_url(&self.url)
+            };
+
+            if let Err(err) = checked {
+                Self::log_error_response(None, &err.to_string());
+
+                return Err(SentResult::without_body(
+                    Blocked(err.to_string()),
+                    Duration::ZERO,
+                ));
+            }
+        }
+
+        let cx = start_span("POST", SpanKind::Client, &Context::current());
+        cx.span()
+            .set_attribute(KeyValue::new("url.full", self.url.to_string()));
+
+        let mut headers = self.headers.clone();
+        inject_http_headers(&cx, &mut headers);
+
         let start = Instant::now();
 
-        let response = reqwest::Client::new()
+        let mut request = self
+            .client
+            .clone()
+            .unwrap_or_default()
             .post(self.url.clone())
-            .json(&self.payload)
-            .send()
-            .await;
+            .headers(headers)
+            .json(&self.payload);
+
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+
+        let response = request.send().await;
 
         let end = start.elapsed();
 
-        match response {
+        match &response {
             Ok(res) => {
-                if res.status().is_success() {
-                    debug!("Success response! {}", res.status());
-
-                    return Ok(SentResult::with_body(
-                        Numeric(res.status().as_u16()),
-                        end,
-                        res.text().await.unwrap(),
-                    ));
-                }
+                cx.span().set_attribute(KeyValue::new(
+                    "http.response.status_code",
+                    i64::from(res.status().as_u16()),
+                ));
+                end_span(
+                    &cx,
+                    (!res.status().is_success()).then(|| res.status().to_string()),
+                );
+            }
+            Err(err) => end_span(&cx, Some(err.to_string())),
+        }
 
+        match response {
+            Ok(res) => {
                 let status_code = res.status();
                 let status = status_code.as_u16();
-                let body = res.text().await.unwrap();
+                let response = self.capture.capture(res).await;
+
+                if status_code.is_success() {
+                    debug!("Success response! {}", status_code);
+
+                    return Ok(SentResult::with_response(Numeric(status), end, response));
+                }
 
-                Self::log_error_response(Some(status_code), &body.clone());
+                Self::log_error_response(Some(status_code), &response.body);
 
-                Err(SentResult::with_body(Numeric(status), end, body))
+                Err(SentResult::with_response(Numeric(status), end, response))
             }
             Err(err) => {
                 Self::log_error_response(err.status(), &err.to_string());
 
-                Err(SentResult::without_body(Unknown(err.to_string()), end))
+                let status = if err.is_timeout() {
+                    Timeout(err.to_string())
+                } else if let Some(blocked) = BlockedDestination::find(&err) {
+                    Blocked(blocked.to_string())
+                } else {
+                    Unknown(err.to_string())
+                };
+
+                Err(SentResult::without_body(status, end))
             }
         }
     }
@@ -115,13 +295,37 @@ impl Sender {
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
-
-    use mockito::Matcher::Json;
+    use std::time::Duration;
+
+    use mockito::Matcher::{Any, Json, Regex};
+    use opentelemetry::global;
+    use opentelemetry::trace::{FutureExt, SpanKind, TraceContextExt};
+    use opentelemetry::Context;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use reqwest::header::{HeaderMap, HeaderValue};
     use serde_json::json;
+    use tokio::net::TcpListener;
     use url::Url;
 
+    use crate::egress::EgressPolicy;
     use crate::events::domain::Payload;
-    use crate::sender::Sender;
+    use crate::sender::{Sender, Status};
+    use crate::telemetry::start_span;
+
+    #[test_case::test_case(Status::Numeric(200), "2xx")]
+    #[test_case::test_case(Status::Numeric(204), "2xx")]
+    #[test_case::test_case(Status::Numeric(404), "4xx")]
+    #[test_case::test_case(Status::Numeric(503), "5xx")]
+    #[test_case::test_case(Status::Unknown("connection refused".to_string()), "unknown")]
+    #[test_case::test_case(Status::Timeout("timed out".to_string()), "timeout")]
+    #[test_case::test_case(Status::Blocked("blocked".to_string()), "blocked")]
+    #[test_case::test_case(Status::AuthFailed("rejected".to_string()), "auth_failed")]
+    #[test_case::test_case(Status::Expired("expired".to_string()), "expired")]
+    #[test_case::test_case(Status::Misconfigured("invalid certificate".to_string()), "misconfigured")]
+    fn status_is_grouped_into_class(status: Status, expected: &str) {
+        assert_eq!(expected, status.class());
+    }
 
     #[test_case::test_case(200, Ok(()))]
     #[test_case::test_case(201, Ok(()))]
@@ -166,5 +370,193 @@ mod tests {
         assert!(result.is_err())
     }
 
+    #[tokio::test]
+    async fn headers_are_sent_with_request() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::from_str(server.url().as_str()).unwrap();
+        let payload = Payload::from(json!({"foo": "bar"}));
+
+        let mock = server
+            .mock("POST", "/")
+            .match_header("webhook-topic", "contact.created")
+            .match_header("content-type", "application/cloudevents+json")
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("webhook-topic", HeaderValue::from_static("contact.created"));
+        headers.insert(
+            "content-type",
+            HeaderValue::from_static("application/cloudevents+json"),
+        );
+
+        let result = Sender::new(payload, url).with_headers(headers).send().await;
+
+        mock.assert_async().await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn trace_context_is_propagated_to_endpoint() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        global::set_tracer_provider(TracerProvider::builder().build());
+
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::from_str(server.url().as_str()).unwrap();
+        let payload = Payload::from(json!({"foo": "bar"}));
+        let parent = start_span("dispatch", SpanKind::Consumer, &Context::new());
+        let trace_id = parent.span().span_context().trace_id();
+
+        let mock = server
+            .mock("POST", "/")
+            .match_header(
+                "traceparent",
+                Regex(format!("^00-{}-[0-9a-f]{{16}}-01$", trace_id)),
+            )
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let result = Sender::new(payload, url).send().with_context(parent).await;
+
+        mock.assert_async().await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn hanging_server_is_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::from_str(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let payload = Payload::from(json!({"foo": "bar"}));
+
+        let result = Sender::new(payload, url)
+            .with_timeout(Duration::from_millis(50))
+            .send()
+            .await;
+
+        assert!(matches!(result.err().unwrap().status, Status::Timeout(_)));
+    }
+
+    #[tokio::test]
+    async fn bearer_token_overrides_authorization_header() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::from_str(server.url().as_str()).unwrap();
+        let payload = Payload::from(json!({"foo": "bar"}));
+
+        let mock = server
+            .mock("POST", "/")
+            .match_header("authorization", "Bearer token")
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Basic foo"));
+
+        let result = Sender::new(payload, url)
+            .with_headers(headers)
+            .with_bearer_token("token")
+            .unwrap()
+            .send()
+            .await;
+
+        mock.assert_async().await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn bearer_token_with_invalid_header_bytes_is_error() {
+        let url = Url::from_str("http://localhost").unwrap();
+        let payload = Payload::from(json!({"foo": "bar"}));
+
+        let result = Sender::new(payload, url).with_bearer_token("token\r\nx-injected: 1");
+
+        assert_eq!(
+            Some("Access token is not valid header value".to_string()),
+            result.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn internal_destination_is_blocked() {
+        let url = Url::from_str("http://169.254.169.254/latest/meta-data").unwrap();
+        let payload = Payload::from(json!({"foo": "bar"}));
+
+        let result = Sender::new(payload, url)
+            .with_egress_policy(EgressPolicy::default())
+            .send()
+            .await;
+
+        assert_eq!(
+            Status::Blocked("'169.254.169.254' is not allowed destination".to_string()),
+            result.err().unwrap().status
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_to_internal_destination_is_blocked() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::from_str(server.url().as_str()).unwrap();
+        let payload = Payload::from(json!({"foo": "bar"}));
+        let egress = EgressPolicy::new("127.0.0.0/8").unwrap();
+
+        server
+            .mock("POST", "/")
+            .with_status(307)
+            .with_header("location", "http://10.0.0.1/")
+            .create_async()
+            .await;
+
+        let client = reqwest::Client::builder()
+            .redirect(egress.redirect_policy())
+            .build()
+            .unwrap();
+
+        let result = Sender::new(payload, url)
+            .with_client(client)
+            .with_egress_policy(egress)
+            .send()
+            .await;
+
+        assert_eq!(
+            Status::Blocked("'10.0.0.1' is not allowed destination".to_string()),
+            result.err().unwrap().status
+        );
+    }
+
+    #[tokio::test]
+    async fn proxied_request_to_unverified_destination_is_blocked() {
+        let mut proxy = mockito::Server::new_async().await;
+        let url = Url::from_str("http://webhooks.invalid/").unwrap();
+        let payload = Payload::from(json!({"foo": "bar"}));
+        let egress = EgressPolicy::new("127.0.0.0/8").unwrap();
+
+        let mock = proxy
+            .mock("POST", Any)
+            .with_status(204)
+            .expect(0)
+            .create_async()
+            .await;
+
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all(proxy.url()).unwrap())
+            .build()
+            .unwrap();
+
+        let result = Sender::new(payload, url)
+            .with_client(client)
+            .with_egress_policy(egress)
+            .with_proxy(true)
+            .send()
+            .await;
+
+        mock.assert_async().await;
+        assert_eq!(
+            Status::Blocked("'webhooks.invalid' is not allowed destination".to_string()),
+            result.err().unwrap().status
+        );
+    }
+
     //todo: test response object
 }
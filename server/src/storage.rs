@@ -1,25 +1,52 @@
+use std::sync::Arc;
+
 use sqlx::PgPool;
 
-use crate::configuration::storage::{ApplicationStorage, EndpointStorage};
-use crate::events::storage::{AttemptLogStorage, EventStorage, MessageStorage};
+use crate::configuration::storage::{
+    ApplicationRepository, ApplicationStorage, EndpointRepository, EndpointStorage,
+    InFlightRepository, InFlightStorage, InMemoryApplicationStorage, InMemoryEndpointStorage,
+    InMemoryInFlightStorage,
+};
+use crate::crypto::Cipher;
+use crate::events::storage::{
+    AttemptLogRepository, AttemptLogStorage, EventRepository, EventStorage, InMemoryEventStorage,
+    InMemoryMessageStorage, MessageRepository, MessageStorage,
+};
 
+#[derive(Clone)]
 pub struct Storage {
-    pub applications: ApplicationStorage,
-    pub endpoints: EndpointStorage,
-    pub events: EventStorage,
-    pub messages: MessageStorage,
-    pub attempt_log: AttemptLogStorage,
+    pub applications: Arc<dyn ApplicationRepository>,
+    pub endpoints: Arc<dyn EndpointRepository>,
+    pub events: Arc<dyn EventRepository>,
+    pub messages: Arc<dyn MessageRepository>,
+    pub attempt_log: Arc<dyn AttemptLogRepository>,
+    pub in_flight: Arc<dyn InFlightRepository>,
 }
 
 impl Storage {
     #[must_use]
-    pub fn new(pool: PgPool) -> Self {
+    pub fn new(pool: PgPool, cipher: Cipher) -> Self {
+        Self {
+            applications: Arc::new(ApplicationStorage::new(pool.clone(), cipher.clone())),
+            endpoints: Arc::new(EndpointStorage::new(pool.clone(), cipher)),
+            events: Arc::new(EventStorage::new(pool.clone())),
+            messages: Arc::new(MessageStorage::new(pool.clone())),
+            attempt_log: Arc::new(AttemptLogStorage::new(pool.clone())),
+            in_flight: Arc::new(InFlightStorage::new(pool)),
+        }
+    }
+
+    #[must_use]
+    pub fn in_memory() -> Self {
+        let messages = InMemoryMessageStorage::default();
+
         Self {
-            applications: ApplicationStorage::new(pool.clone()),
-            endpoints: EndpointStorage::new(pool.clone()),
-            events: EventStorage::new(pool.clone()),
-            messages: MessageStorage::new(pool.clone()),
-            attempt_log: AttemptLogStorage::new(pool),
+            applications: Arc::<InMemoryApplicationStorage>::default(),
+            endpoints: Arc::<InMemoryEndpointStorage>::default(),
+            events: Arc::<InMemoryEventStorage>::default(),
+            attempt_log: Arc::new(messages.attempt_logs()),
+            messages: Arc::new(messages),
+            in_flight: Arc::<InMemoryInFlightStorage>::default(),
         }
     }
 }
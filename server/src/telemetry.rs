@@ -0,0 +1,302 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+
+use actix_web::dev::ServiceRequest;
+use actix_web::http::StatusCode;
+use lapin::types::{AMQPValue, FieldTable, LongString, ShortString};
+use log::{error, info};
+use opentelemetry::global::{self, BoxedTracer};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{FutureExt, SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::config::TracingConfig;
+
+const TRACER_NAME: &str = "webhooks";
+
+pub fn init_tracing(config: &TracingConfig, default_service_name: &str) {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let Some(endpoint) = config.endpoint() else {
+        return;
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build();
+
+    match exporter {
+        Ok(exporter) => {
+            let provider = TracerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    config.service_name(default_service_name),
+                )]))
+                .build();
+
+            global::set_tracer_provider(provider);
+
+            info!("Traces are exported to {}", endpoint);
+        }
+        Err(err) => error!("Traces exporter cannot be built: {}", err),
+    }
+}
+
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+fn tracer() -> BoxedTracer {
+    global::tracer(TRACER_NAME)
+}
+
+pub fn start_span<T>(name: T, kind: SpanKind, parent: &Context) -> Context
+where
+    T: Into<Cow<'static, str>>,
+{
+    let span = tracer()
+        .span_builder(name)
+        .with_kind(kind)
+        .start_with_context(&tracer(), parent);
+
+    parent.with_span(span)
+}
+
+pub fn end_span(cx: &Context, error: Option<String>) {
+    if let Some(error) = error {
+        cx.span().set_status(Status::error(error));
+    }
+
+    cx.span().end();
+}
+
+pub async fn in_db_span<F>(name: &'static str, future: F) -> F::Output
+where
+    F: Future,
+{
+    let cx = start_span(name, SpanKind::Client, &Context::current());
+    cx.span()
+        .set_attribute(KeyValue::new("db.system", "postgresql"));
+
+    let output = future.with_context(cx.clone()).await;
+
+    end_span(&cx, None);
+
+    output
+}
+
+pub trait WithDbSpan: Future + Sized {
+    fn with_db_span(self, name: &'static str) -> impl Future<Output = Self::Output> {
+        in_db_span(name, self)
+    }
+}
+
+impl<F: Future> WithDbSpan for F {}
+
+pub fn start_server_span(request: &ServiceRequest) -> Context {
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&ActixHeaders(request)));
+    let route = request
+        .match_pattern()
+        .unwrap_or_else(|| request.path().to_string());
+
+    let cx = start_span(
+        format!("{} {}", request.method(), route),
+        SpanKind::Server,
+        &parent,
+    );
+    cx.span().set_attributes([
+        KeyValue::new("http.request.method", request.method().to_string()),
+        KeyValue::new("http.route", route),
+    ]);
+
+    cx
+}
+
+pub fn end_server_span(cx: &Context, status: Option<StatusCode>) {
+    if let Some(status) = status {
+        cx.span().set_attribute(KeyValue::new(
+            "http.response.status_code",
+            i64::from(status.as_u16()),
+        ));
+    }
+
+    let error = match status {
+        Some(status) if status.is_server_error() => Some(status.to_string()),
+        None => Some("Request failed".to_string()),
+        _ => None,
+    };
+
+    end_span(cx, error);
+}
+
+pub fn inject_http_headers(cx: &Context, headers: &mut HeaderMap) {
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(cx, &mut HttpHeaders(headers))
+    });
+}
+
+pub fn inject_amqp_headers(cx: &Context, headers: &mut FieldTable) {
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(cx, &mut AmqpHeaders(headers))
+    });
+}
+
+pub fn inject_map(cx: &Context) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+
+    global::get_text_map_propagator(|propagator| propagator.inject_context(cx, &mut map));
+
+    map
+}
+
+pub fn extract_map(map: &HashMap<String, String>) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(map))
+}
+
+pub fn extract_amqp_headers(headers: Option<&FieldTable>) -> Context {
+    match headers {
+        Some(headers) => global::get_text_map_propagator(|propagator| {
+            propagator.extract(&AmqpHeadersExtractor(headers))
+        }),
+        None => Context::new(),
+    }
+}
+
+struct HttpHeaders<'a>(&'a mut HeaderMap);
+
+impl Injector for HttpHeaders<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+struct ActixHeaders<'a>(&'a ServiceRequest);
+
+impl Extractor for ActixHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .headers()
+            .get(key)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.headers().keys().map(|name| name.as_str()).collect()
+    }
+}
+
+struct AmqpHeaders<'a>(&'a mut FieldTable);
+
+impl Injector for AmqpHeaders<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(
+            ShortString::from(key),
+            AMQPValue::LongString(LongString::from(value)),
+        );
+    }
+}
+
+struct AmqpHeadersExtractor<'a>(&'a FieldTable);
+
+impl Extractor for AmqpHeadersExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        match self.0.inner().get(key) {
+            Some(AMQPValue::LongString(value)) => std::str::from_utf8(value.as_bytes()).ok(),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.inner().keys().map(ShortString::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lapin::types::FieldTable;
+    use opentelemetry::global;
+    use opentelemetry::trace::{SpanKind, TraceContextExt};
+    use opentelemetry::Context;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use reqwest::header::HeaderMap;
+
+    use crate::telemetry::{
+        extract_amqp_headers, extract_map, inject_amqp_headers, inject_http_headers, inject_map,
+        start_span,
+    };
+
+    #[test]
+    fn context_is_propagated_through_amqp_headers() {
+        init();
+        let cx = start_span("publish", SpanKind::Producer, &Context::new());
+        let mut headers = FieldTable::default();
+
+        inject_amqp_headers(&cx, &mut headers);
+        let extracted = extract_amqp_headers(Some(&headers));
+
+        assert_eq!(
+            cx.span().span_context().trace_id(),
+            extracted.span().span_context().trace_id()
+        );
+        assert!(extracted.span().span_context().is_remote());
+    }
+
+    #[test]
+    fn context_is_propagated_through_map() {
+        init();
+        let cx = start_span("publish", SpanKind::Producer, &Context::new());
+
+        let extracted = extract_map(&inject_map(&cx));
+
+        assert_eq!(
+            cx.span().span_context().trace_id(),
+            extracted.span().span_context().trace_id()
+        );
+    }
+
+    #[test]
+    fn missing_amqp_headers_are_empty_context() {
+        let extracted = extract_amqp_headers(None);
+
+        assert!(!extracted.span().span_context().is_valid());
+    }
+
+    #[test]
+    fn traceparent_is_injected_into_http_headers() {
+        init();
+        let cx = start_span("send", SpanKind::Client, &Context::new());
+        let mut headers = HeaderMap::new();
+
+        inject_http_headers(&cx, &mut headers);
+
+        let traceparent = headers.get("traceparent").unwrap().to_str().unwrap();
+        assert_eq!(
+            format!(
+                "00-{}-{}-01",
+                cx.span().span_context().trace_id(),
+                cx.span().span_context().span_id()
+            ),
+            traceparent
+        );
+    }
+
+    fn init() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        global::set_tracer_provider(TracerProvider::builder().build());
+    }
+}
@@ -1,6 +1,6 @@
 macro_rules! make_ksuid {
     ($name: ident, $prefix: literal) => {
-        #[derive(Clone, Copy, Eq, PartialEq)]
+        #[derive(Clone, Copy, Eq, PartialEq, Hash)]
         pub struct $name ([u8; 27]);
 
         impl $name {
@@ -1,3 +1,4 @@
+use std::future::{pending, Future};
 use std::net::TcpListener;
 
 use dotenv::dotenv;
@@ -7,13 +8,21 @@ use reqwest::Client;
 use serde_json::{json, Value};
 use sqlx::{migrate, Connection, Executor, PgConnection, PgPool};
 use svix_ksuid::{Ksuid, KsuidLike};
+use tokio::task::JoinHandle;
 
-use server::app::{run_dispatcher, run_server};
-use server::config::{AMQPConfig, PostgresConfig};
+use server::app::{run_dispatcher, run_metrics_server, run_server};
+use server::config::{AMQPConfig, DeliveryConfig, LogConfig, PostgresConfig, QueueConfig};
+use server::crypto::Cipher;
+use server::egress::EgressPolicy;
+use server::health::HealthCheck;
 use server::logs::init_log;
+use server::queue::connect_queue;
 use server::storage::Storage;
 use server::types::{ApplicationId, EndpointId};
 
+// Endpoint secrets of tests are encrypted with their own key, so the environment doesn't have to provide one
+const TEST_ENCRYPTION_KEY: &str = "R8G6OBFlxu1lW4a8TYg10YNk1fzBUepw4wt7Op/5eDI=";
+
 struct TestEnvironmentBuilder;
 
 impl TestEnvironmentBuilder {
@@ -25,11 +34,13 @@ impl TestEnvironmentBuilder {
         TestEnvironment {
             pool: Self::prepare_db(test_id.as_str()).await,
             amqp_config: Self::prepare_amqp(test_id.as_str()),
+            queue_config: QueueConfig::init_from_env().unwrap(),
+            cipher: Cipher::new(TEST_ENCRYPTION_KEY).unwrap(),
         }
     }
 
     pub async fn build_with_logs() -> TestEnvironment {
-        init_log();
+        init_log(&LogConfig::init_from_env().unwrap());
 
         Self::build().await
     }
@@ -71,6 +82,8 @@ impl TestEnvironmentBuilder {
 pub struct TestEnvironment {
     pool: PgPool,
     amqp_config: AMQPConfig,
+    queue_config: QueueConfig,
+    cipher: Cipher,
 }
 
 impl TestEnvironment {
@@ -83,50 +96,125 @@ impl TestEnvironment {
         TestEnvironmentBuilder::build_with_logs().await
     }
 
+    #[allow(dead_code)]
+    pub fn amqp_config(&self) -> &AMQPConfig {
+        &self.amqp_config
+    }
+
+    #[allow(dead_code)]
+    pub fn with_queue_backend(self, backend: &str) -> Self {
+        Self {
+            queue_config: self.queue_config.with_backend(backend),
+            ..self
+        }
+    }
+
     pub async fn server(&self) -> TestServer {
-        TestServerBuilder::new(self.pool.clone(), self.amqp_config.clone())
-            .run()
-            .await
+        TestServerBuilder::new(
+            self.pool.clone(),
+            self.amqp_config.clone(),
+            self.queue_config.clone(),
+            self.cipher.clone(),
+        )
+        .run()
+        .await
     }
 
     pub async fn dispatcher(&self) {
-        TestDispatcherBuilder::new(self.pool.clone(), self.amqp_config.clone())
-            .run()
-            .await
+        self.dispatcher_with_shutdown(pending()).await;
+    }
+
+    pub async fn dispatcher_with_shutdown<S>(&self, shutdown: S) -> JoinHandle<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
+        TestDispatcherBuilder::new(
+            self.pool.clone(),
+            self.amqp_config.clone(),
+            self.queue_config.clone(),
+            self.cipher.clone(),
+        )
+        .run(shutdown)
+        .await
     }
 }
 
+fn egress_policy() -> EgressPolicy {
+    EgressPolicy::new("127.0.0.0/8,localhost").unwrap()
+}
+
 struct TestServerBuilder {
     pool: PgPool,
     amqp_config: AMQPConfig,
+    queue_config: QueueConfig,
+    cipher: Cipher,
 }
 
 impl TestServerBuilder {
-    fn new(pool: PgPool, amqp_config: AMQPConfig) -> Self {
-        Self { pool, amqp_config }
+    fn new(
+        pool: PgPool,
+        amqp_config: AMQPConfig,
+        queue_config: QueueConfig,
+        cipher: Cipher,
+    ) -> Self {
+        Self {
+            pool,
+            amqp_config,
+            queue_config,
+            cipher,
+        }
     }
 
     async fn run(&self) -> TestServer {
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
         let addr = format!("http://{}", listener.local_addr().unwrap());
 
-        let server = run_server(listener, self.pool.clone(), self.amqp_config.clone())
-            .await
-            .unwrap();
+        let queue = connect_queue(
+            &self.queue_config,
+            self.amqp_config.clone(),
+            self.pool.clone(),
+        )
+        .await;
+
+        let metrics_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let metrics_addr = format!("http://{}", metrics_listener.local_addr().unwrap());
+        let metrics_server = run_metrics_server(
+            metrics_listener,
+            HealthCheck::new(self.pool.clone(), queue.clone()),
+            Some(queue.clone()),
+        )
+        .unwrap();
+
+        #[allow(clippy::let_underscore_future)]
+        let _ = tokio::spawn(metrics_server);
+
+        let server = run_server(
+            listener,
+            self.pool.clone(),
+            queue,
+            self.cipher.clone(),
+            egress_policy(),
+        )
+        .await
+        .unwrap();
 
         #[allow(clippy::let_underscore_future)]
         let _ = tokio::spawn(server);
 
         TestServer {
             server_url: addr,
-            storage: Storage::new(self.pool.clone()),
+            metrics_url: metrics_addr,
+            storage: Storage::new(self.pool.clone(), self.cipher.clone()),
+            pool: self.pool.clone(),
         }
     }
 }
 
 pub struct TestServer {
     server_url: String,
+    metrics_url: String,
     storage: Storage,
+    pool: PgPool,
 }
 
 impl TestServer {
@@ -134,6 +222,10 @@ impl TestServer {
         format!("{}/{}", self.base_url(), endpoint)
     }
 
+    pub fn metrics_url(&self, endpoint: &str) -> String {
+        format!("{}/{}", self.metrics_url, endpoint)
+    }
+
     fn base_url(&self) -> String {
         self.server_url.to_string()
     }
@@ -141,24 +233,60 @@ impl TestServer {
     pub fn storage(&self) -> &Storage {
         &self.storage
     }
+
+    #[allow(dead_code)]
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
 }
 
 struct TestDispatcherBuilder {
     pool: PgPool,
     amqp_config: AMQPConfig,
+    queue_config: QueueConfig,
+    cipher: Cipher,
 }
 
 impl TestDispatcherBuilder {
-    fn new(pool: PgPool, amqp_config: AMQPConfig) -> Self {
-        Self { pool, amqp_config }
+    fn new(
+        pool: PgPool,
+        amqp_config: AMQPConfig,
+        queue_config: QueueConfig,
+        cipher: Cipher,
+    ) -> Self {
+        Self {
+            pool,
+            amqp_config,
+            queue_config,
+            cipher,
+        }
     }
 
-    async fn run(&self) {
+    async fn run<S>(&self, shutdown: S) -> JoinHandle<()>
+    where
+        S: Future<Output = ()> + Send + 'static,
+    {
         let pool = self.pool.clone();
-        let amqp_config = self.amqp_config.clone();
-
-        #[allow(clippy::let_underscore_future)]
-        tokio::spawn(async move { run_dispatcher(pool, amqp_config).await });
+        let cipher = self.cipher.clone();
+        let delivery_config = DeliveryConfig::init_from_env().unwrap();
+        let queue = connect_queue(
+            &self.queue_config,
+            self.amqp_config.clone(),
+            self.pool.clone(),
+        )
+        .await;
+
+        tokio::spawn(async move {
+            run_dispatcher(
+                pool,
+                queue,
+                cipher,
+                delivery_config,
+                egress_policy(),
+                shutdown,
+            )
+            .await
+        })
     }
 }
 
@@ -212,12 +340,17 @@ impl Given {
     }
 
     pub async fn app(&self) -> ApplicationId {
+        self.app_with_delivery_format("raw").await
+    }
+
+    pub async fn app_with_delivery_format(&self, delivery_format: &str) -> ApplicationId {
         let name: String = Faker.fake::<String>();
 
         let response = Client::new()
             .post(&format!("{}/application", self.url))
             .json(&json!({
-              "name": name
+              "name": name,
+              "delivery_format": delivery_format
             }))
             .send()
             .await
@@ -237,7 +370,17 @@ impl Given {
         topics: Vec<&str>,
     ) -> (ApplicationId, EndpointId) {
         let app_id = self.app().await;
+        let endpoint_id = self.endpoint(&app_id, url, topics).await;
 
+        (app_id, endpoint_id)
+    }
+
+    pub async fn endpoint(
+        &self,
+        app_id: &ApplicationId,
+        url: &str,
+        topics: Vec<&str>,
+    ) -> EndpointId {
         let response = Client::new()
             .post(&format!("{}/application/{}/endpoint", self.url, app_id))
             .json(&json!({
@@ -250,10 +393,7 @@ impl Given {
 
         let body = response.json::<Value>().await.unwrap();
 
-        let endpoint_id = EndpointId::try_from(body["id"].as_str().unwrap().to_string())
-            .expect("Invalid endpoint id");
-
-        (app_id, endpoint_id)
+        EndpointId::try_from(body["id"].as_str().unwrap().to_string()).expect("Invalid endpoint id")
     }
 
     pub async fn disable_endpoint(&self, app_id: &ApplicationId, endpoint_id: &EndpointId) {
@@ -1,6 +1,9 @@
+use std::time::Duration;
+
 use reqwest::Client;
 use serde_json::{json, Value};
 
+use server::configuration::domain::{DeliveryFormat, Retention, Topic};
 use server::types::ApplicationId;
 
 use crate::common::{run_test_server, TestEnvironment};
@@ -25,6 +28,7 @@ async fn application_is_created() {
 
     let body = response.json::<Value>().await.unwrap();
     assert_eq!("Dummy application", body["name"].as_str().unwrap());
+    assert_eq!("raw", body["delivery_format"].as_str().unwrap());
 
     let id = ApplicationId::try_from(body["id"].as_str().unwrap().to_string())
         .expect("Invalid application id");
@@ -58,6 +62,195 @@ async fn application_names_can_be_without_space() {
     assert_eq!(201, response.status());
 }
 
+#[tokio::test]
+async fn application_is_created_with_delivery_format() {
+    // Arrange
+    let server = run_test_server!();
+
+    // Act
+    let response = Client::new()
+        .post(server.url("application"))
+        .json(&json!({
+          "name": "Dummy application",
+          "delivery_format": "cloudevents_structured"
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(201, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    let id = ApplicationId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid application id");
+
+    let app = server
+        .storage()
+        .applications
+        .get(&id)
+        .await
+        .expect("Application was not created");
+
+    assert_eq!(DeliveryFormat::CloudEventsStructured, app.delivery_format);
+}
+
+#[tokio::test]
+async fn application_is_created_with_proxy() {
+    // Arrange
+    let server = run_test_server!();
+
+    // Act
+    let response = Client::new()
+        .post(server.url("application"))
+        .json(&json!({
+          "name": "Dummy application",
+          "proxy": {
+            "url": "http://proxy.example.com:3128",
+            "username": "user",
+            "password": "secret",
+            "no_proxy": ["partner.com"]
+          }
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(201, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(
+        json!({"url": "http://proxy.example.com:3128/", "username": "user", "no_proxy": ["partner.com"]}),
+        body["proxy"]
+    );
+
+    let id = ApplicationId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid application id");
+
+    let app = server
+        .storage()
+        .applications
+        .get(&id)
+        .await
+        .expect("Application was not created");
+
+    assert_eq!(
+        "http://proxy.example.com:3128/",
+        app.proxy.unwrap().address()
+    );
+}
+
+#[tokio::test]
+async fn application_is_created_with_retention() {
+    // Arrange
+    let server = run_test_server!();
+
+    // Act
+    let response = Client::new()
+        .post(server.url("application"))
+        .json(&json!({
+          "name": "Dummy application",
+          "retention_days": 30,
+          "payload_retention_days": 7
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(201, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(30, body["retention_days"]);
+    assert_eq!(7, body["payload_retention_days"]);
+
+    let id = ApplicationId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid application id");
+
+    let app = server
+        .storage()
+        .applications
+        .get(&id)
+        .await
+        .expect("Application was not created");
+
+    assert_eq!(Retention::new(Some(30), Some(7)).unwrap(), app.retention);
+}
+
+#[tokio::test]
+async fn application_is_created_with_topic_ttls() {
+    // Arrange
+    let server = run_test_server!();
+
+    // Act
+    let response = Client::new()
+        .post(server.url("application"))
+        .json(&json!({
+          "name": "Dummy application",
+          "topic_ttl_secs": {"otp.sent": 300}
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(201, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(json!({"otp.sent": 300}), body["topic_ttl_secs"]);
+
+    let id = ApplicationId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid application id");
+
+    let app = server
+        .storage()
+        .applications
+        .get(&id)
+        .await
+        .expect("Application was not created");
+
+    assert_eq!(
+        Some(Duration::from_secs(300)),
+        app.topic_ttls.for_topic(&Topic::new("otp.sent").unwrap())
+    );
+}
+
+#[tokio::test]
+async fn application_is_created_with_max_concurrency() {
+    // Arrange
+    let server = run_test_server!();
+
+    // Act
+    let response = Client::new()
+        .post(server.url("application"))
+        .json(&json!({
+          "name": "Dummy application",
+          "max_concurrency": 4
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(201, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(json!(4), body["max_concurrency"]);
+
+    let id = ApplicationId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid application id");
+
+    let app = server
+        .storage()
+        .applications
+        .get(&id)
+        .await
+        .expect("Application was not created");
+
+    assert_eq!(Some(4), app.max_concurrency);
+}
+
 #[tokio::test]
 async fn validation() {
     // Arrange
@@ -72,6 +265,46 @@ async fn validation() {
             json!({"name": "  "}),
             json!({"error": "Validation errors", "messages": ["Name cannot be empty"]}),
         ),
+        (
+            json!({"name": "test", "delivery_format": "soap"}),
+            json!({"error": "Validation errors", "messages": ["'soap' is invalid delivery format"]}),
+        ),
+        (
+            json!({"name": "test", "proxy": {"url": "ftp://proxy.example.com"}}),
+            json!({"error": "Validation errors", "messages": ["Proxy url should be valid"]}),
+        ),
+        (
+            json!({"name": "test", "proxy": {"url": "http://proxy.example.com", "password": "secret"}}),
+            json!({"error": "Validation errors", "messages": ["Proxy password cannot be provided without username"]}),
+        ),
+        (
+            json!({"name": "test", "proxy": {"url": "http://169.254.169.254"}}),
+            json!({"error": "Validation errors", "messages": ["'169.254.169.254' is not allowed destination"]}),
+        ),
+        (
+            json!({"name": "test", "retention_days": 0}),
+            json!({"error": "Validation errors", "messages": ["Retention should be between 1 and 3650 days"]}),
+        ),
+        (
+            json!({"name": "test", "payload_retention_days": 3651}),
+            json!({"error": "Validation errors", "messages": ["Payload retention should be between 1 and 3650 days"]}),
+        ),
+        (
+            json!({"name": "test", "retention_days": 7, "payload_retention_days": 30}),
+            json!({"error": "Validation errors", "messages": ["Payload retention cannot be longer than retention"]}),
+        ),
+        (
+            json!({"name": "test", "topic_ttl_secs": {"otp.sent": 0}}),
+            json!({"error": "Validation errors", "messages": ["TTL of topic 'otp.sent' should be greater than 0"]}),
+        ),
+        (
+            json!({"name": "test", "max_concurrency": 0}),
+            json!({"error": "Validation errors", "messages": ["Max concurrency should be between 1 and 1000"]}),
+        ),
+        (
+            json!({"name": "test", "topic_priorities": {"otp.sent": "urgent"}}),
+            json!({"error": "Validation errors", "messages": ["'urgent' is invalid priority"]}),
+        ),
     ];
 
     for test_case in test_cases {
@@ -1,8 +1,10 @@
+use std::time::Duration;
+
 use reqwest::Client;
 use serde_json::{json, Value};
 use url::Url;
 
-use server::configuration::domain::{EndpointStatus, TopicsList};
+use server::configuration::domain::{EndpointAuth, EndpointStatus, TopicsList};
 use server::types::{ApplicationId, EndpointId};
 
 use crate::common::{run_test_server, Given, TestEnvironment};
@@ -51,6 +53,200 @@ async fn endpoint_is_created() {
     assert_eq!(Url::parse("http://localhost:8080").unwrap(), endpoint.url);
 }
 
+#[tokio::test]
+async fn endpoint_is_created_with_custom_headers() {
+    // Arrange
+    let server = run_test_server!();
+    let app_id = Given::from(&server).app().await;
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/endpoint", app_id)))
+        .json(&json!({
+          "url": "http://localhost:8080",
+          "topics": ["contact.created"],
+          "headers": [
+            {"name": "X-Tenant", "value": "acme"},
+            {"name": "Authorization", "value": "Bearer secret-token", "secret": true}
+          ]
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(201, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+
+    assert_eq!(
+        json!([
+            {"name": "X-Tenant", "value": "acme", "secret": false},
+            {"name": "Authorization", "value": "********", "secret": true}
+        ]),
+        body["headers"]
+    );
+
+    let id = EndpointId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid endpoint id");
+
+    let endpoint = server
+        .storage()
+        .endpoints
+        .get(&id)
+        .await
+        .expect("Endpoint not found");
+    let headers = endpoint.headers.to_header_map();
+
+    assert_eq!("acme", headers["x-tenant"]);
+    assert_eq!("Bearer secret-token", headers["authorization"]);
+}
+
+#[tokio::test]
+async fn endpoint_is_created_with_oauth2_auth() {
+    // Arrange
+    let server = run_test_server!();
+    let app_id = Given::from(&server).app().await;
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/endpoint", app_id)))
+        .json(&json!({
+          "url": "http://localhost:8080",
+          "topics": ["contact.created"],
+          "auth": {
+            "type": "oauth2_client_credentials",
+            "token_url": "https://idp.example.com/oauth/token",
+            "client_id": "client",
+            "client_secret": "secret",
+            "scopes": ["webhooks:write"]
+          }
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(201, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+
+    assert_eq!(
+        json!({
+            "type": "oauth2_client_credentials",
+            "token_url": "https://idp.example.com/oauth/token",
+            "client_id": "client",
+            "scopes": ["webhooks:write"]
+        }),
+        body["auth"]
+    );
+
+    let id = EndpointId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid endpoint id");
+
+    let endpoint = server
+        .storage()
+        .endpoints
+        .get(&id)
+        .await
+        .expect("Endpoint not found");
+
+    match endpoint.auth {
+        EndpointAuth::OAuth2ClientCredentials(credentials) => {
+            assert_eq!("client", credentials.client_id());
+            assert_eq!("secret", credentials.client_secret());
+        }
+        EndpointAuth::None => panic!("Endpoint should have auth"),
+    }
+}
+
+#[tokio::test]
+async fn endpoint_is_created_with_tls_config() {
+    // Arrange
+    let server = run_test_server!();
+    let app_id = Given::from(&server).app().await;
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/endpoint", app_id)))
+        .json(&json!({
+          "url": "https://localhost:8443",
+          "topics": ["contact.created"],
+          "tls": {
+            "client_certificate": include_str!("../fixtures/tls/client.crt"),
+            "client_key": include_str!("../fixtures/tls/client.key"),
+            "ca_certificate": include_str!("../fixtures/tls/ca.crt")
+          }
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(201, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+
+    assert_eq!(
+        json!({"client_certificate": true, "ca_certificate": true}),
+        body["tls"]
+    );
+
+    let id = EndpointId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid endpoint id");
+
+    let endpoint = server
+        .storage()
+        .endpoints
+        .get(&id)
+        .await
+        .expect("Endpoint not found");
+
+    assert!(endpoint.tls.identity().unwrap().is_some());
+    assert_eq!(1, endpoint.tls.ca_certificates().unwrap().len());
+}
+
+#[tokio::test]
+async fn endpoint_is_created_with_timeouts() {
+    // Arrange
+    let server = run_test_server!();
+    let app_id = Given::from(&server).app().await;
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/endpoint", app_id)))
+        .json(&json!({
+          "url": "https://localhost:8080",
+          "topics": ["contact.created"],
+          "connect_timeout_ms": 1000,
+          "request_timeout_ms": 5000
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(201, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+
+    assert_eq!(1000, body["connect_timeout_ms"]);
+    assert_eq!(5000, body["request_timeout_ms"]);
+
+    let id = EndpointId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid endpoint id");
+
+    let endpoint = server
+        .storage()
+        .endpoints
+        .get(&id)
+        .await
+        .expect("Endpoint not found");
+
+    assert_eq!(Some(Duration::from_millis(1000)), endpoint.timeouts.connect);
+    assert_eq!(Some(Duration::from_millis(5000)), endpoint.timeouts.request);
+}
+
 #[tokio::test]
 async fn validation() {
     // Arrange
@@ -94,6 +290,72 @@ async fn validation() {
             400,
             json!({"error": "Validation errors", "messages": ["'bar baz' is invalid topic name"]}),
         ),
+        (
+            app_id,
+            json!({"url": "http://localhost", "topics": ["foo.bar"], "headers": [{"name": "Content-Type", "value": "text/plain"}]}),
+            400,
+            json!({"error": "Validation errors", "messages": ["'Content-Type' header is protected and cannot be overridden"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://localhost", "topics": ["foo.bar"], "headers": [{"name": "X-Api-Key", "value": "a"}, {"name": "x-api-key", "value": "b"}]}),
+            400,
+            json!({"error": "Validation errors", "messages": ["'x-api-key' header is defined more than once"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://localhost", "topics": ["foo.bar"], "auth": {"type": "basic"}}),
+            400,
+            json!({"error": "Validation errors", "messages": ["'basic' is invalid auth type"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://localhost", "topics": ["foo.bar"], "auth": {"type": "oauth2_client_credentials", "token_url": "https://idp", "client_id": "client"}}),
+            400,
+            json!({"error": "Validation errors", "messages": ["Client id and client secret cannot be empty"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://localhost", "topics": ["foo.bar"], "tls": {"client_certificate": include_str!("../fixtures/tls/client.crt")}}),
+            400,
+            json!({"error": "Validation errors", "messages": ["Client certificate and client key should be provided together"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://localhost", "topics": ["foo.bar"], "tls": {"ca_certificate": "invalid"}}),
+            400,
+            json!({"error": "Validation errors", "messages": ["CA certificate is invalid"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://169.254.169.254/latest/meta-data", "topics": ["foo.bar"]}),
+            400,
+            json!({"error": "Validation errors", "messages": ["'169.254.169.254' is not allowed destination"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://localhost", "topics": ["foo.bar"], "auth": {"type": "oauth2_client_credentials", "token_url": "http://169.254.169.254/token", "client_id": "client", "client_secret": "secret"}}),
+            400,
+            json!({"error": "Validation errors", "messages": ["'169.254.169.254' is not allowed destination"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://10.0.0.1:8080", "topics": ["foo.bar"]}),
+            400,
+            json!({"error": "Validation errors", "messages": ["'10.0.0.1' is not allowed destination"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://localhost", "topics": ["foo.bar"], "connect_timeout_ms": 0}),
+            400,
+            json!({"error": "Validation errors", "messages": ["Connect timeout should be between 1 and 300000 ms"]}),
+        ),
+        (
+            app_id,
+            json!({"url": "http://localhost", "topics": ["foo.bar"], "request_timeout_ms": 300001}),
+            400,
+            json!({"error": "Validation errors", "messages": ["Request timeout should be between 1 and 300000 ms"]}),
+        ),
         // (
         //     app_id,
         //     json!({"url": "http://localhost", "topics": ["foo bar", "bar baz"]}),
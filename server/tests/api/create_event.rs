@@ -1,5 +1,5 @@
-use mockito::Matcher::Json;
-use mockito::Server;
+use mockito::Matcher::{Json, PartialJson};
+use mockito::{Matcher, Server};
 use reqwest::Client;
 use serde_json::{json, Value};
 
@@ -71,3 +71,396 @@ async fn event_is_created_and_dispatched() {
     assert_eq!(Topic::try_from("contact.created").unwrap(), event.topic);
     assert_mock_with_retry!(mock);
 }
+
+#[tokio::test]
+async fn event_is_dispatched_in_envelope() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+
+    let topic = "contact.created";
+    let given = Given::from(&server);
+    let app_id = given.app_with_delivery_format("envelope").await;
+
+    let mut destination_server = Server::new_async().await;
+    let mock = destination_server
+        .mock("POST", "/some_endpoint")
+        .match_header("webhook-topic", topic)
+        .match_header("webhook-attempt", "1")
+        .match_body(PartialJson(json!({
+           "type": topic,
+           "data": {
+              "foo": "bar"
+           }
+        })))
+        .with_status(201)
+        .create_async()
+        .await;
+
+    given
+        .endpoint(
+            &app_id,
+            &format!("{}/some_endpoint", destination_server.url()),
+            vec![topic],
+        )
+        .await;
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({
+          "topic": topic,
+          "payload": {
+             "foo": "bar"
+          }
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+    assert_mock_with_retry!(mock);
+}
+
+#[tokio::test]
+async fn event_is_dispatched_with_custom_headers() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+
+    let topic = "contact.created";
+    let app_id = Given::from(&server).app().await;
+
+    let mut destination_server = Server::new_async().await;
+    let mock = destination_server
+        .mock("POST", "/some_endpoint")
+        .match_header("x-tenant", "acme")
+        .match_header("authorization", "Bearer secret-token")
+        .match_header("webhook-topic", topic)
+        .with_status(201)
+        .create_async()
+        .await;
+
+    Client::new()
+        .post(server.url(&format!("application/{}/endpoint", app_id)))
+        .json(&json!({
+          "url": format!("{}/some_endpoint", destination_server.url()),
+          "topics": [topic],
+          "headers": [
+            {"name": "X-Tenant", "value": "acme"},
+            {"name": "Authorization", "value": "Bearer secret-token", "secret": true}
+          ]
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({
+          "topic": topic,
+          "payload": {
+             "foo": "bar"
+          }
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+    assert_mock_with_retry!(mock);
+}
+
+#[tokio::test]
+async fn event_is_dispatched_through_application_proxy() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+
+    let topic = "contact.created";
+
+    let mut proxy_server = Server::new_async().await;
+    let mock = proxy_server
+        .mock("POST", Matcher::Any)
+        .match_header("host", "example.com")
+        .match_header("proxy-authorization", "Basic dXNlcjpzZWNyZXQ=")
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let response = Client::new()
+        .post(server.url("application"))
+        .json(&json!({
+          "name": "Proxied application",
+          "proxy": {
+            "url": proxy_server.url(),
+            "username": "user",
+            "password": "secret"
+          }
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+    let app_id = response.json::<Value>().await.unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    Client::new()
+        .post(server.url(&format!("application/{}/endpoint", app_id)))
+        .json(&json!({
+          "url": "http://example.com/webhook",
+          "topics": [topic]
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({
+          "topic": topic,
+          "payload": {
+             "foo": "bar"
+          }
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+    assert_mock_with_retry!(mock);
+}
+
+#[tokio::test]
+async fn event_is_dispatched_with_oauth2_token() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+
+    let topic = "contact.created";
+    let app_id = Given::from(&server).app().await;
+
+    let mut identity_provider = Server::new_async().await;
+    let token_mock = identity_provider
+        .mock("POST", "/oauth/token")
+        .with_body(r#"{"access_token":"access-token","token_type":"Bearer","expires_in":3600}"#)
+        .with_header("content-type", "application/json")
+        .create_async()
+        .await;
+
+    let mut destination_server = Server::new_async().await;
+    let mock = destination_server
+        .mock("POST", "/some_endpoint")
+        .match_header("authorization", "Bearer access-token")
+        .with_status(201)
+        .create_async()
+        .await;
+
+    Client::new()
+        .post(server.url(&format!("application/{}/endpoint", app_id)))
+        .json(&json!({
+          "url": format!("{}/some_endpoint", destination_server.url()),
+          "topics": [topic],
+          "auth": {
+            "type": "oauth2_client_credentials",
+            "token_url": format!("{}/oauth/token", identity_provider.url()),
+            "client_id": "client",
+            "client_secret": "secret"
+          }
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({
+          "topic": topic,
+          "payload": {
+             "foo": "bar"
+          }
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+    assert_mock_with_retry!(token_mock);
+    assert_mock_with_retry!(mock);
+}
+
+#[tokio::test]
+async fn cloud_event_is_created() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+    let app_id = Given::from(&server).app().await;
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .header("content-type", "application/cloudevents+json")
+        .body(
+            json!({
+              "specversion": "1.0",
+              "id": "A234-1234-1234",
+              "source": "/crm",
+              "type": "contact.created",
+              "comexampleextension": "value",
+              "data": {"foo": "bar"}
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+    let body = response.json::<Value>().await.unwrap();
+    let id = EventId::try_from(body["id"].as_str().unwrap().to_string()).expect("Invalid event id");
+
+    let event = server
+        .storage()
+        .events
+        .get(id)
+        .await
+        .expect("Event wasn't persisted");
+
+    assert_eq!(Topic::try_from("contact.created").unwrap(), event.topic);
+    assert_eq!(Some("A234-1234-1234".to_string()), event.idempotency_key);
+    assert_eq!(json!("value"), event.extensions["comexampleextension"]);
+}
+
+#[tokio::test]
+async fn cloud_event_with_the_same_id_is_created_once() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+    let app_id = Given::from(&server).app().await;
+    let cloud_event = json!({
+      "specversion": "1.0",
+      "id": "A234-1234-1234",
+      "source": "/crm",
+      "type": "contact.created",
+      "data": {"foo": "bar"}
+    });
+
+    // Act
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let response = Client::new()
+            .post(server.url(&format!("application/{}/event", app_id)))
+            .header("content-type", "application/cloudevents+json")
+            .body(cloud_event.to_string())
+            .send()
+            .await
+            .expect("Failed to executed request");
+
+        assert_eq!(200, response.status());
+        ids.push(response.json::<Value>().await.unwrap()["id"].clone());
+    }
+
+    // Assert
+    assert_eq!(ids[0], ids[1]);
+}
+
+#[tokio::test]
+async fn cloud_events_with_the_same_id_from_different_sources_are_created() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+    let app_id = Given::from(&server).app().await;
+
+    // Act
+    let mut ids = Vec::new();
+    for source in ["/crm", "/billing"] {
+        let response = Client::new()
+            .post(server.url(&format!("application/{}/event", app_id)))
+            .header("content-type", "application/cloudevents+json")
+            .body(
+                json!({
+                  "specversion": "1.0",
+                  "id": "A234-1234-1234",
+                  "source": source,
+                  "type": "contact.created",
+                  "data": {"foo": "bar"}
+                })
+                .to_string(),
+            )
+            .send()
+            .await
+            .expect("Failed to executed request");
+
+        assert_eq!(200, response.status());
+        ids.push(response.json::<Value>().await.unwrap()["id"].clone());
+    }
+
+    // Assert
+    assert_ne!(ids[0], ids[1]);
+}
+
+#[tokio::test]
+async fn cloud_events_batch_is_created() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+    let app_id = Given::from(&server).app().await;
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .header("content-type", "application/cloudevents-batch+json")
+        .body(
+            json!([
+              {"specversion": "1.0", "id": "1", "source": "/crm", "type": "contact.created", "data": {}},
+              {"specversion": "1.0", "id": "2", "source": "/crm", "type": "contact.updated", "data": {}}
+            ])
+            .to_string(),
+        )
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+    let body = response.json::<Value>().await.unwrap();
+
+    assert_eq!(2, body.as_array().unwrap().len());
+}
+
+#[tokio::test]
+async fn event_is_dispatched_through_postgres_queue() {
+    // Arrange
+    let environment = TestEnvironment::new().await.with_queue_backend("postgres");
+    let server = environment.server().await;
+    environment.dispatcher().await;
+
+    let mut destination_server = Server::new_async().await;
+    let mock = destination_server
+        .mock("POST", "/some_endpoint")
+        .match_body(Json(json!({"foo": "bar"})))
+        .with_status(201)
+        .create_async()
+        .await;
+
+    let topic = "contact.created";
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app(
+            &format!("{}/some_endpoint", destination_server.url()),
+            vec![topic],
+        )
+        .await;
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"foo": "bar"}}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+    assert_mock_with_retry!(mock);
+}
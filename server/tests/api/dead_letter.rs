@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use lapin::options::{BasicPublishOptions, QueueDeclareOptions};
+use lapin::types::FieldTable;
+use lapin::BasicProperties;
+use serde_json::json;
+use sqlx::{query, query_scalar};
+use tokio::time::sleep;
+
+use server::amqp::connect_with_rabbit;
+
+use crate::common::TestEnvironment;
+
+#[tokio::test]
+async fn unparseable_message_is_dead_lettered() {
+    // Arrange
+    let environment = TestEnvironment::new().await.with_queue_backend("postgres");
+    let server = environment.server().await;
+
+    query("INSERT INTO jobs (queue, payload, run_at) VALUES ('sent_message', $1, NOW() AT TIME ZONE 'UTC')")
+        .bind(json!({"unknown": "command"}))
+        .execute(server.pool())
+        .await
+        .unwrap();
+
+    // Act
+    environment.dispatcher().await;
+
+    // Assert
+    let mut queue = String::new();
+    for _ in 0..20 {
+        queue = query_scalar("SELECT queue FROM jobs")
+            .fetch_one(server.pool())
+            .await
+            .unwrap();
+
+        if queue != "sent_message" {
+            break;
+        }
+
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!("sent_message-dead-letter", queue);
+}
+
+#[tokio::test]
+async fn unparseable_message_is_routed_to_dead_letter_queue() {
+    // Arrange
+    let environment = TestEnvironment::new().await;
+    let _server = environment.server().await;
+    let config = environment.amqp_config();
+    let (connection, channel) = connect_with_rabbit(config.clone()).await;
+
+    channel
+        .basic_publish(
+            &config.sent_message_exchange_name(),
+            "normal",
+            BasicPublishOptions::default(),
+            json!({"unknown": "command"}).to_string().as_bytes(),
+            BasicProperties::default(),
+        )
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    // Act
+    environment.dispatcher().await;
+
+    // Assert
+    let mut dead_lettered = 0;
+    for _ in 0..20 {
+        dead_lettered = channel
+            .queue_declare(
+                &config.dead_letter_queue_name(),
+                QueueDeclareOptions {
+                    passive: true,
+                    ..QueueDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .unwrap()
+            .message_count();
+
+        if dead_lettered > 0 {
+            break;
+        }
+
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(1, dead_lettered);
+
+    connection.close(200, "Test finished").await.unwrap();
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use mockito::Server;
+use reqwest::Client;
+use serde_json::json;
+use sqlx::query_scalar;
+use tokio::sync::oneshot;
+use tokio::time::{sleep, timeout};
+
+use crate::common::{Given, TestEnvironment};
+
+#[tokio::test]
+async fn in_flight_delivery_is_finished_on_shutdown() {
+    // Arrange
+    let environment = TestEnvironment::new().await;
+    let server = environment.server().await;
+    let (stop, stopped) = oneshot::channel::<()>();
+    let dispatcher = environment
+        .dispatcher_with_shutdown(async {
+            stopped.await.ok();
+        })
+        .await;
+
+    let mut destination_server = Server::new_async().await;
+    let mock = destination_server
+        .mock("POST", "/some_endpoint")
+        .with_status(200)
+        .with_chunked_body(|w| {
+            std::thread::sleep(Duration::from_millis(500));
+            w.write_all(b"ok")
+        })
+        .create_async()
+        .await;
+
+    let topic = "contact.created";
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app(
+            &format!("{}/some_endpoint", destination_server.url()),
+            vec![topic],
+        )
+        .await;
+
+    Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"foo": "bar"}}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    sleep(Duration::from_millis(200)).await;
+
+    // Act
+    stop.send(()).unwrap();
+
+    // Assert
+    timeout(Duration::from_secs(5), dispatcher)
+        .await
+        .expect("Dispatcher hasn't stopped")
+        .unwrap();
+
+    mock.assert_async().await;
+
+    let attempts: i64 = query_scalar("SELECT COUNT(*) FROM attempt_logs")
+        .fetch_one(server.pool())
+        .await
+        .unwrap();
+    assert_eq!(1, attempts);
+}
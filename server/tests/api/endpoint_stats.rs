@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use mockito::Server;
+use reqwest::Client;
+use serde_json::{json, Value};
+use sqlx::query_scalar;
+use tokio::time::sleep;
+
+use crate::common::{run_test_server, run_test_server_and_dispatcher, Given, TestEnvironment};
+
+#[tokio::test]
+async fn endpoint_stats_are_computed_from_attempts() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+
+    let mut destination_server = Server::new_async().await;
+    destination_server
+        .mock("POST", "/some_endpoint")
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let topic = "contact.created";
+    let (app_id, endpoint_id) = Given::from(&server)
+        .endpoint_with_app(
+            &format!("{}/some_endpoint", destination_server.url()),
+            vec![topic],
+        )
+        .await;
+
+    Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"foo": "bar"}}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    for _ in 0..30 {
+        let logs: i64 = query_scalar("SELECT COUNT(*) FROM attempt_logs")
+            .fetch_one(server.pool())
+            .await
+            .unwrap();
+
+        if logs > 0 {
+            break;
+        }
+
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    // Act
+    let response = Client::new()
+        .get(server.url(&format!(
+            "application/{}/endpoint/{}/stats?window=1h",
+            app_id, endpoint_id
+        )))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(json!("1h"), body["window"]);
+    assert_eq!(json!(1), body["attempts"]);
+    assert_eq!(json!(1.0), body["success_rate"]);
+    assert_eq!(json!({"2xx": 1}), body["status_classes"]);
+    assert!(body["response_time_ms"]["p99"].is_u64());
+    assert!(body["avg_processing_time_ms"].is_u64());
+    assert!(body["last_success_at"].is_string());
+    assert!(body["last_failure_at"].is_null());
+    assert_eq!(json!("closed"), body["circuit_state"]);
+}
+
+#[tokio::test]
+async fn endpoint_disabled_for_failing_has_open_circuit() {
+    // Arrange
+    let server = run_test_server!();
+
+    let (app_id, endpoint_id) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec!["contact.created"])
+        .await;
+
+    let mut endpoint = server.storage().endpoints.get(&endpoint_id).await.unwrap();
+    endpoint.disable_failing();
+    server.storage().endpoints.save(endpoint).await.unwrap();
+
+    // Act
+    let response = Client::new()
+        .get(server.url(&format!(
+            "application/{}/endpoint/{}/stats",
+            app_id, endpoint_id
+        )))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(json!("open"), body["circuit_state"]);
+}
+
+#[tokio::test]
+async fn endpoint_without_attempts_has_empty_stats() {
+    // Arrange
+    let server = run_test_server!();
+
+    let (app_id, endpoint_id) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec!["contact.created"])
+        .await;
+
+    // Act
+    let response = Client::new()
+        .get(server.url(&format!(
+            "application/{}/endpoint/{}/stats",
+            app_id, endpoint_id
+        )))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(json!("24h"), body["window"]);
+    assert_eq!(json!(0), body["attempts"]);
+    assert!(body["success_rate"].is_null());
+    assert!(body["response_time_ms"].is_null());
+}
+
+#[tokio::test]
+async fn endpoint_stats_validation() {
+    // Arrange
+    let server = run_test_server!();
+
+    let given = Given::from(&server);
+    let (app_id, endpoint_id) = given
+        .endpoint_with_app("http://localhost:8080", vec!["contact.created"])
+        .await;
+    let other_app_id = given.app().await;
+
+    let test_cases = vec![
+        (
+            format!(
+                "application/{}/endpoint/{}/stats?window=2w",
+                app_id, endpoint_id
+            ),
+            400,
+        ),
+        (
+            format!(
+                "application/{}/endpoint/{}/stats",
+                other_app_id, endpoint_id
+            ),
+            404,
+        ),
+    ];
+
+    for (path, expected_status) in test_cases {
+        // Act
+        let response = Client::new()
+            .get(server.url(&path))
+            .send()
+            .await
+            .expect("Failed to executed request");
+
+        // Assert
+        assert_eq!(expected_status, response.status(), "{}", path);
+    }
+}
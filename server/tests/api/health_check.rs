@@ -1,4 +1,5 @@
 use reqwest::Client;
+use serde_json::Value;
 
 use crate::common::{run_test_server, TestEnvironment};
 
@@ -18,3 +19,63 @@ async fn health_check_works() {
     assert_eq!(204, response.status());
     assert_eq!(0, response.content_length().unwrap());
 }
+
+#[tokio::test]
+async fn liveness_works() {
+    // Arrange
+    let server = run_test_server!();
+
+    // Act
+    let response = Client::new()
+        .get(server.url("health/live"))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(204, response.status());
+}
+
+#[tokio::test]
+async fn readiness_reports_dependencies() {
+    // Arrange
+    let server = run_test_server!();
+
+    // Act
+    let response = Client::new()
+        .get(server.url("health/ready"))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!("up", body["status"]);
+    assert_eq!("up", body["checks"]["postgres"]["status"]);
+    assert_eq!("up", body["checks"]["amqp"]["status"]);
+}
+
+#[tokio::test]
+async fn readiness_fails_when_postgres_is_unreachable() {
+    // Arrange
+    let server = run_test_server!();
+    server.pool().close().await;
+
+    // Act
+    let response = Client::new()
+        .get(server.url("health/ready"))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(503, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!("down", body["status"]);
+    assert_eq!("down", body["checks"]["postgres"]["status"]);
+    assert!(body["checks"]["postgres"]["error"].is_string());
+    assert_eq!("up", body["checks"]["amqp"]["status"]);
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use envconfig::Envconfig;
+use tokio::time::{sleep, timeout};
+
+use server::cmd::{AsyncMessage, SentMessage};
+use server::config::QueueConfig;
+use server::jobs::JobQueue;
+use server::queue::Queue;
+use server::types::MessageId;
+
+use crate::common::TestEnvironment;
+
+#[tokio::test]
+async fn held_job_is_not_delivered_again_after_visibility_timeout() {
+    // Arrange
+    let environment = TestEnvironment::new().await.with_queue_backend("postgres");
+    let server = environment.server().await;
+    let config = QueueConfig::init_from_hashmap(&HashMap::from([(
+        "QUEUE_VISIBILITY_TIMEOUT_SECS".to_string(),
+        "1".to_string(),
+    )]))
+    .unwrap();
+    let queue = JobQueue::new(server.pool().clone(), &config);
+
+    queue
+        .publish(AsyncMessage::SentMessage(
+            SentMessage::new(MessageId::new()),
+        ))
+        .await
+        .unwrap();
+
+    let mut first = queue.consumer("first").await;
+    let mut second = queue.consumer("second").await;
+
+    // Act
+    let delivery = first.next().await.unwrap();
+    sleep(Duration::from_millis(2500)).await;
+
+    // Assert
+    assert!(timeout(Duration::from_millis(500), second.next())
+        .await
+        .is_err());
+
+    drop(delivery);
+    sleep(Duration::from_millis(1500)).await;
+
+    assert!(timeout(Duration::from_millis(500), second.next())
+        .await
+        .is_ok());
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use lapin::options::{
+    BasicPublishOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+};
+use lapin::types::{AMQPValue, FieldTable, ShortString};
+use lapin::{BasicProperties, Connection, ConnectionProperties, ExchangeKind};
+use tokio::time::sleep;
+
+use server::amqp::AmqpQueue;
+use server::cmd::{AsyncMessage, SentMessage};
+use server::queue::Queue;
+use server::types::MessageId;
+
+use crate::common::TestEnvironment;
+
+#[tokio::test]
+async fn legacy_queue_is_drained_into_durable_lanes() {
+    // Arrange
+    let environment = TestEnvironment::new().await;
+    let config = environment.amqp_config();
+    let connection =
+        Connection::connect(&config.connection_string(), ConnectionProperties::default())
+            .await
+            .unwrap();
+    let channel = connection.create_channel().await.unwrap();
+
+    let delayed_type = FieldTable::from(BTreeMap::from([(
+        ShortString::from("x-delayed-type"),
+        AMQPValue::LongString("direct".into()),
+    )]));
+    channel
+        .exchange_declare(
+            &config.legacy_exchange_name(),
+            ExchangeKind::Custom(String::from("x-delayed-message")),
+            ExchangeDeclareOptions::default(),
+            delayed_type,
+        )
+        .await
+        .unwrap();
+    channel
+        .queue_declare(
+            &config.legacy_queue_name(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    channel
+        .queue_bind(
+            &config.legacy_queue_name(),
+            &config.legacy_exchange_name(),
+            "",
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+
+    let delay = FieldTable::from(BTreeMap::from([(
+        ShortString::from("x-delay"),
+        AMQPValue::LongLongInt(500),
+    )]));
+    for properties in [
+        BasicProperties::default(),
+        BasicProperties::default().with_headers(delay),
+    ] {
+        let message = AsyncMessage::SentMessage(SentMessage::new(MessageId::new()));
+
+        channel
+            .basic_publish(
+                &config.legacy_exchange_name(),
+                "",
+                BasicPublishOptions::default(),
+                &message.encode(),
+                properties,
+            )
+            .await
+            .unwrap();
+    }
+    sleep(Duration::from_millis(100)).await;
+
+    // Act
+    let queue = AmqpQueue::connect(config.clone()).await;
+
+    // Assert
+    let mut depth = 0;
+    for _ in 0..40 {
+        depth = queue.depth().await.unwrap();
+        if depth == 2 {
+            break;
+        }
+
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(2, depth);
+    assert!(channel
+        .queue_declare(
+            &config.legacy_queue_name(),
+            QueueDeclareOptions {
+                passive: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .is_err());
+
+    queue.close().await;
+    connection.close(200, "Test finished").await.unwrap();
+}
@@ -2,5 +2,15 @@ mod common;
 mod create_application;
 mod create_endpoint;
 mod create_event;
+mod dead_letter;
+mod dispatcher_shutdown;
+mod endpoint_stats;
 mod endpoint_status;
 mod health_check;
+mod job_queue;
+mod legacy_queue;
+mod metrics;
+mod priority;
+mod retention;
+mod scheduled_event;
+mod update_endpoint;
@@ -0,0 +1,57 @@
+use reqwest::Client;
+use serde_json::json;
+
+use crate::common::{run_test_server, Given, TestEnvironment};
+
+#[tokio::test]
+async fn metrics_are_exposed_in_prometheus_format() {
+    // Arrange
+    let server = run_test_server!();
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec!["contact.created"])
+        .await;
+
+    Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": "contact.created", "payload": {"foo": "bar"}}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Act
+    let response = Client::new()
+        .get(server.metrics_url("metrics"))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+
+    let body = response.text().await.unwrap();
+    assert!(body.contains(&format!(
+        "webhooks_events_ingested_total{{app_id=\"{}\",topic=\"contact.created\"}} 1",
+        app_id
+    )));
+    assert!(body.contains(&format!(
+        "webhooks_messages_fanned_out_total{{app_id=\"{}\"}} 1",
+        app_id
+    )));
+    assert!(body.contains("webhooks_queue_depth "));
+}
+
+#[tokio::test]
+async fn metrics_are_not_exposed_on_public_listener() {
+    // Arrange
+    let server = run_test_server!();
+
+    // Act
+    let response = Client::new()
+        .get(server.url("metrics"))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(404, response.status());
+}
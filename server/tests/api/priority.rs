@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use envconfig::Envconfig;
+use reqwest::Client;
+use serde_json::{json, Value};
+use server::amqp::AmqpQueue;
+use server::cmd::{AsyncMessage, SentMessage};
+use server::config::QueueConfig;
+use server::configuration::domain::Priority;
+use server::jobs::JobQueue;
+use server::queue::Queue;
+use server::types::{ApplicationId, MessageId};
+use sqlx::query_scalar;
+use tokio::time::sleep;
+
+use crate::common::{run_test_server, Given, TestEnvironment};
+
+#[tokio::test]
+async fn jobs_are_queued_with_event_priority() {
+    // Arrange
+    let environment = TestEnvironment::new().await.with_queue_backend("postgres");
+    let server = environment.server().await;
+
+    let response = Client::new()
+        .post(server.url("application"))
+        .json(&json!({
+          "name": "Dummy application",
+          "topic_priorities": {"password.reset": "high"}
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(json!({"password.reset": "high"}), body["topic_priorities"]);
+
+    let app_id = ApplicationId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid application id");
+    Given::from(&server)
+        .endpoint(
+            &app_id,
+            "http://localhost:8080",
+            vec!["password.reset", "newsletter.sent"],
+        )
+        .await;
+
+    // Act
+    for request in [
+        json!({"topic": "password.reset", "payload": {}}),
+        json!({"topic": "newsletter.sent", "payload": {}}),
+        json!({"topic": "newsletter.sent", "payload": {}, "priority": "low"}),
+    ] {
+        let response = Client::new()
+            .post(server.url(&format!("application/{}/event", app_id)))
+            .json(&request)
+            .send()
+            .await
+            .expect("Failed to executed request");
+
+        assert_eq!(200, response.status());
+    }
+
+    // Assert
+    let priorities: Vec<i16> = query_scalar("SELECT priority FROM jobs ORDER BY id")
+        .fetch_all(server.pool())
+        .await
+        .unwrap();
+
+    assert_eq!(vec![2, 1, 0], priorities);
+}
+
+#[tokio::test]
+async fn low_priority_jobs_are_fetched_under_high_priority_load() {
+    // Arrange
+    let environment = TestEnvironment::new().await.with_queue_backend("postgres");
+    let server = environment.server().await;
+    let config = QueueConfig::init_from_hashmap(&HashMap::new()).unwrap();
+    let queue = JobQueue::new(server.pool().clone(), &config);
+
+    for priority in [vec![Priority::High; 20], vec![Priority::Low]].concat() {
+        let message = SentMessage::new(MessageId::new()).with_priority(priority);
+
+        queue
+            .publish(AsyncMessage::SentMessage(message))
+            .await
+            .unwrap();
+    }
+
+    // Act
+    let mut consumer = queue.consumer("priority-test").await;
+
+    let mut consumed = Vec::new();
+    for _ in 0..7 {
+        let delivery = consumer.next().await.unwrap();
+        let AsyncMessage::SentMessage(message) = AsyncMessage::decode(&delivery.data).unwrap();
+
+        consumed.push(message.priority);
+        delivery.ack().await;
+    }
+
+    // Assert
+    let count = |priority| consumed.iter().filter(|&&p| p == priority).count();
+
+    assert_eq!(6, count(Priority::High));
+    assert_eq!(1, count(Priority::Low));
+}
+
+#[tokio::test]
+async fn event_with_invalid_priority_is_rejected() {
+    // Arrange
+    let server = run_test_server!();
+
+    let topic = "contact.created";
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec![topic])
+        .await;
+
+    // Act
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"foo": "bar"}, "priority": "urgent"}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(400, response.status());
+}
+
+#[tokio::test]
+async fn amqp_lanes_are_consumed_by_weight() {
+    // Arrange
+    let environment = TestEnvironment::new().await;
+    let queue = AmqpQueue::connect(environment.amqp_config().clone()).await;
+
+    for priority in Priority::ALL {
+        for _ in 0..10 {
+            let message = SentMessage::new(MessageId::new()).with_priority(priority);
+
+            queue
+                .publish(AsyncMessage::SentMessage(message))
+                .await
+                .unwrap();
+        }
+    }
+
+    for _ in 0..20 {
+        if queue.depth().await.unwrap() == 30 {
+            break;
+        }
+
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    // Act
+    let mut consumer = queue.consumer("priority-test").await;
+    sleep(Duration::from_millis(200)).await;
+
+    let mut consumed = Vec::new();
+    for _ in 0..7 {
+        let delivery = consumer.next().await.unwrap();
+        let AsyncMessage::SentMessage(message) = AsyncMessage::decode(&delivery.data).unwrap();
+
+        consumed.push(message.priority);
+        delivery.ack().await;
+    }
+
+    // Assert
+    let count = |priority| consumed.iter().filter(|&&p| p == priority).count();
+
+    assert_eq!(4, count(Priority::High));
+    assert_eq!(2, count(Priority::Normal));
+    assert_eq!(1, count(Priority::Low));
+
+    consumer.cancel().await;
+    queue.close().await;
+}
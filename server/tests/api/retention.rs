@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use envconfig::Envconfig;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use server::config::RetentionConfig;
+use server::configuration::domain::Topic;
+use server::events::domain::{Event, Payload};
+use server::retention::Purger;
+use server::time::Clock;
+use server::types::ApplicationId;
+
+use crate::common::{run_test_server, TestEnvironment, TestServer};
+
+#[tokio::test]
+async fn expired_events_are_purged_with_application_retention() {
+    // Arrange
+    let server = run_test_server!();
+    let app_id = app_with_retention(&server, json!({"retention_days": 30})).await;
+
+    let expired = event(&app_id, 31);
+    let kept = event(&app_id, 29);
+    server.storage().events.save(expired.clone()).await.unwrap();
+    server.storage().events.save(kept.clone()).await.unwrap();
+
+    // Act
+    let stats = purger(&server, &[]).purge().await.unwrap();
+
+    // Assert
+    assert_eq!(1, stats.events);
+    assert!(server.storage().events.get(expired.id).await.is_err());
+    assert!(server.storage().events.get(kept.id).await.is_ok());
+}
+
+#[tokio::test]
+async fn expired_events_are_purged_with_default_retention() {
+    // Arrange
+    let server = run_test_server!();
+    let app_id = app_with_retention(&server, json!({})).await;
+
+    let expired = event(&app_id, 8);
+    server.storage().events.save(expired.clone()).await.unwrap();
+
+    // Act
+    let stats = purger(
+        &server,
+        &[("RETENTION_DAYS", "7"), ("RETENTION_BATCH_SIZE", "1")],
+    )
+    .purge()
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(1, stats.events);
+    assert!(server.storage().events.get(expired.id).await.is_err());
+}
+
+#[tokio::test]
+async fn events_are_kept_without_retention() {
+    // Arrange
+    let server = run_test_server!();
+    let app_id = app_with_retention(&server, json!({})).await;
+
+    let old = event(&app_id, 3650);
+    server.storage().events.save(old.clone()).await.unwrap();
+
+    // Act
+    let stats = purger(&server, &[]).purge().await.unwrap();
+
+    // Assert
+    assert_eq!(0, stats.events);
+    assert!(server.storage().events.get(old.id).await.is_ok());
+}
+
+#[tokio::test]
+async fn scheduled_events_are_not_purged_before_delivery() {
+    // Arrange
+    let server = run_test_server!();
+    let app_id = app_with_retention(
+        &server,
+        json!({"retention_days": 30, "payload_retention_days": 7}),
+    )
+    .await;
+
+    let scheduled = event(&app_id, 31).with_deliver_at(Utc::now() + Duration::days(1));
+    server
+        .storage()
+        .events
+        .save(scheduled.clone())
+        .await
+        .unwrap();
+
+    // Act
+    let stats = purger(&server, &[]).purge().await.unwrap();
+
+    // Assert
+    assert_eq!(0, stats.events);
+    assert_eq!(0, stats.payloads);
+
+    let event = server.storage().events.get(scheduled.id).await.unwrap();
+    assert_ne!("null", event.payload.to_string());
+}
+
+#[tokio::test]
+async fn expired_payloads_are_cleared() {
+    // Arrange
+    let server = run_test_server!();
+    let app_id = app_with_retention(
+        &server,
+        json!({"retention_days": 30, "payload_retention_days": 7}),
+    )
+    .await;
+
+    let old = event(&app_id, 8);
+    server.storage().events.save(old.clone()).await.unwrap();
+
+    // Act
+    let stats = purger(&server, &[]).purge().await.unwrap();
+
+    // Assert
+    assert_eq!(0, stats.events);
+    assert_eq!(1, stats.payloads);
+
+    let event = server.storage().events.get(old.id).await.unwrap();
+    assert_eq!("null", event.payload.to_string());
+}
+
+async fn app_with_retention(server: &TestServer, retention: Value) -> ApplicationId {
+    let mut request = json!({"name": "app"});
+    request
+        .as_object_mut()
+        .unwrap()
+        .extend(retention.as_object().unwrap().clone());
+
+    let response = Client::new()
+        .post(server.url("application"))
+        .json(&request)
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    let body = response.json::<Value>().await.unwrap();
+
+    ApplicationId::try_from(body["id"].as_str().unwrap().to_string())
+        .expect("Invalid application id")
+}
+
+fn event(app_id: &ApplicationId, days_ago: i64) -> Event {
+    Event::new(
+        *app_id,
+        Payload::from(json!({"foo": "bar"})),
+        Topic::new("contact.created").unwrap(),
+        &Clock::chrono(),
+    )
+    .occurred_at(Utc::now() - Duration::days(days_ago))
+}
+
+fn purger(server: &TestServer, vars: &[(&str, &str)]) -> Purger {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    Purger::new(
+        server.pool().clone(),
+        RetentionConfig::init_from_hashmap(&vars).unwrap(),
+    )
+}
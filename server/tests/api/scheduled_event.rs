@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use mockito::Server;
+use reqwest::Client;
+use serde_json::{json, Value};
+use sqlx::query_scalar;
+use tokio::time::sleep;
+
+use crate::common::{run_test_server, run_test_server_and_dispatcher, Given, TestEnvironment};
+
+#[tokio::test]
+async fn cancelled_scheduled_event_is_not_dispatched() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+
+    let mut destination_server = Server::new_async().await;
+    let mock = destination_server
+        .mock("POST", "/some_endpoint")
+        .expect(0)
+        .create_async()
+        .await;
+
+    let topic = "contact.created";
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app(
+            &format!("{}/some_endpoint", destination_server.url()),
+            vec![topic],
+        )
+        .await;
+
+    let deliver_at = Utc::now() + chrono::Duration::seconds(1);
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"foo": "bar"}, "deliver_at": deliver_at}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+    let event_id = response.json::<Value>().await.unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Act
+    let response = Client::new()
+        .delete(server.url(&format!("application/{}/event/{}", app_id, event_id)))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(204, response.status());
+
+    sleep(Duration::from_millis(1500)).await;
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn event_without_schedule_cannot_be_cancelled() {
+    // Arrange
+    let server = run_test_server!();
+
+    let topic = "contact.created";
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec![topic])
+        .await;
+
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"foo": "bar"}}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+    let event_id = response.json::<Value>().await.unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Act
+    let response = Client::new()
+        .delete(server.url(&format!("application/{}/event/{}", app_id, event_id)))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(400, response.status());
+}
+
+#[tokio::test]
+async fn event_cannot_be_scheduled_beyond_max_delay() {
+    // Arrange
+    let server = run_test_server!();
+
+    let topic = "contact.created";
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec![topic])
+        .await;
+
+    // Act
+    let deliver_at = Utc::now() + chrono::Duration::days(50);
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"foo": "bar"}, "deliver_at": deliver_at}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(400, response.status());
+    assert_eq!(
+        json!({"error": "Event cannot be scheduled more than 49 days ahead", "messages": []}),
+        response.json::<Value>().await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn event_of_other_application_cannot_be_cancelled() {
+    // Arrange
+    let server = run_test_server!();
+
+    let topic = "contact.created";
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec![topic])
+        .await;
+    let (other_app_id, _) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec![topic])
+        .await;
+
+    let deliver_at = Utc::now() + chrono::Duration::hours(1);
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"foo": "bar"}, "deliver_at": deliver_at}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+    let event_id = response.json::<Value>().await.unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Act
+    let response = Client::new()
+        .delete(server.url(&format!("application/{}/event/{}", other_app_id, event_id)))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(404, response.status());
+}
+
+#[tokio::test]
+async fn expired_event_is_not_retried() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+
+    let mut destination_server = Server::new_async().await;
+    let mock = destination_server
+        .mock("POST", "/some_endpoint")
+        .with_status(500)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let topic = "otp.sent";
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app(
+            &format!("{}/some_endpoint", destination_server.url()),
+            vec![topic],
+        )
+        .await;
+
+    // Act
+    Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"code": "1234"}, "ttl_secs": 1}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    let mut status_kind: Option<String> = None;
+    for _ in 0..50 {
+        status_kind =
+            query_scalar("SELECT status_kind FROM attempts WHERE status_kind IS NOT NULL")
+                .fetch_optional(server.pool())
+                .await
+                .unwrap()
+                .flatten();
+
+        if status_kind.is_some() {
+            break;
+        }
+
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    assert_eq!(Some("expired".to_string()), status_kind);
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn scheduled_event_is_dispatched_when_ttl_is_shorter_than_schedule() {
+    // Arrange
+    let server = run_test_server_and_dispatcher!();
+
+    let mut destination_server = Server::new_async().await;
+    let mock = destination_server
+        .mock("POST", "/some_endpoint")
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let topic = "otp.sent";
+    let (app_id, _) = Given::from(&server)
+        .endpoint_with_app(
+            &format!("{}/some_endpoint", destination_server.url()),
+            vec![topic],
+        )
+        .await;
+
+    // Act
+    let deliver_at = Utc::now() + chrono::Duration::seconds(2);
+    let response = Client::new()
+        .post(server.url(&format!("application/{}/event", app_id)))
+        .json(&json!({"topic": topic, "payload": {"code": "1234"}, "deliver_at": deliver_at, "ttl_secs": 1}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+
+    sleep(Duration::from_millis(3000)).await;
+    mock.assert_async().await;
+}
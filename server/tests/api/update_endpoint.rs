@@ -0,0 +1,148 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+use url::Url;
+
+use server::configuration::domain::TopicsList;
+use server::types::EndpointId;
+
+use crate::common::{run_test_server, Given, TestEnvironment};
+
+#[tokio::test]
+async fn endpoint_is_updated() {
+    // Arrange
+    let server = run_test_server!();
+    let (app_id, endpoint_id) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec!["contact.created"])
+        .await;
+
+    // Act
+    let response = Client::new()
+        .patch(server.url(&format!("application/{}/endpoint/{}", app_id, endpoint_id)))
+        .json(&json!({
+          "url": "http://localhost:8081",
+          "headers": [
+            {"name": "X-Api-Key", "value": "secret-key", "secret": true}
+          ]
+        }))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+
+    let body = response.json::<Value>().await.unwrap();
+
+    assert_eq!(
+        json!([{"name": "X-Api-Key", "value": "********", "secret": true}]),
+        body["headers"]
+    );
+
+    let endpoint = server
+        .storage()
+        .endpoints
+        .get(&endpoint_id)
+        .await
+        .expect("Endpoint not found");
+
+    assert_eq!(Url::parse("http://localhost:8081").unwrap(), endpoint.url);
+    assert_eq!(TopicsList::from(vec!["contact.created"]), endpoint.topics);
+    assert_eq!("secret-key", endpoint.headers.to_header_map()["x-api-key"]);
+}
+
+#[tokio::test]
+async fn timeout_override_is_reset_with_null() {
+    // Arrange
+    let server = run_test_server!();
+    let (app_id, endpoint_id) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec!["contact.created"])
+        .await;
+    let url = server.url(&format!("application/{}/endpoint/{}", app_id, endpoint_id));
+
+    Client::new()
+        .patch(&url)
+        .json(&json!({"connect_timeout_ms": 100, "request_timeout_ms": 200}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Act
+    let response = Client::new()
+        .patch(&url)
+        .json(&json!({"connect_timeout_ms": null}))
+        .send()
+        .await
+        .expect("Failed to executed request");
+
+    // Assert
+    assert_eq!(200, response.status());
+
+    let endpoint = server
+        .storage()
+        .endpoints
+        .get(&endpoint_id)
+        .await
+        .expect("Endpoint not found");
+
+    assert_eq!(None, endpoint.timeouts.connect);
+    assert_eq!(
+        Some(std::time::Duration::from_millis(200)),
+        endpoint.timeouts.request
+    );
+}
+
+#[tokio::test]
+async fn validation() {
+    // Arrange
+    let server = run_test_server!();
+    let (app_id, endpoint_id) = Given::from(&server)
+        .endpoint_with_app("http://localhost:8080", vec!["contact.created"])
+        .await;
+
+    let test_cases = vec![
+        (
+            EndpointId::new(),
+            json!({"url": "http://localhost"}),
+            404,
+            json!({"error": "Entity not found", "messages": []}),
+        ),
+        (
+            endpoint_id,
+            json!({"url": "invalid-url"}),
+            400,
+            json!({"error": "Validation errors", "messages": ["Url should be valid"]}),
+        ),
+        (
+            endpoint_id,
+            json!({"url": "http://169.254.169.254"}),
+            400,
+            json!({"error": "Validation errors", "messages": ["'169.254.169.254' is not allowed destination"]}),
+        ),
+        (
+            endpoint_id,
+            json!({"auth": {"type": "oauth2_client_credentials", "token_url": "http://169.254.169.254/token", "client_id": "client", "client_secret": "secret"}}),
+            400,
+            json!({"error": "Validation errors", "messages": ["'169.254.169.254' is not allowed destination"]}),
+        ),
+        (
+            endpoint_id,
+            json!({"headers": [{"name": "webhook-signature", "value": "v1,abc"}]}),
+            400,
+            json!({"error": "Validation errors", "messages": ["'webhook-signature' header is protected and cannot be overridden"]}),
+        ),
+    ];
+
+    for test_case in test_cases {
+        // Act
+        let response = Client::new()
+            .patch(server.url(&format!("application/{}/endpoint/{}", app_id, test_case.0)))
+            .json(&test_case.1)
+            .send()
+            .await
+            .expect("Failed to executed request");
+
+        // Assert
+        assert_eq!(test_case.2, response.status());
+        assert_eq!(test_case.3, response.json::<Value>().await.unwrap());
+    }
+}